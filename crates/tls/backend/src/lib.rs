@@ -66,6 +66,32 @@ pub enum DecryptMode {
     Application,
 }
 
+/// The keys protecting the application data of a connection, in the clear.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TrafficSecrets {
+    /// The key the client encrypts with.
+    pub client_write_key: Vec<u8>,
+    /// The implicit part of the client's nonce.
+    pub client_write_iv: Vec<u8>,
+    /// The key the server encrypts with.
+    pub server_write_key: Vec<u8>,
+    /// The implicit part of the server's nonce.
+    pub server_write_iv: Vec<u8>,
+    /// The sequence number of the next record the client sends.
+    pub client_seq: u64,
+    /// The sequence number of the next record the server sends.
+    pub server_seq: u64,
+}
+
+impl std::fmt::Debug for TrafficSecrets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrafficSecrets")
+            .field("client_seq", &self.client_seq)
+            .field("server_seq", &self.server_seq)
+            .finish_non_exhaustive()
+    }
+}
+
 /// Core trait which manages crypto operations for the TLS connection such as
 /// key exchange, encryption and decryption.
 #[async_trait]
@@ -86,6 +112,13 @@ pub trait Backend: Send {
     async fn get_client_random(&mut self) -> Result<Random, BackendError>;
     /// Returns public client keyshare.
     async fn get_client_key_share(&mut self) -> Result<PublicKey, BackendError>;
+    /// Generates the client keyshare ahead of time, so that
+    /// [`Backend::get_client_key_share`] does not have to.
+    ///
+    /// By default this does nothing.
+    async fn prepare_client_key_share(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
     /// Sets server random.
     async fn set_server_random(&mut self, random: Random) -> Result<(), BackendError>;
     /// Sets server keyshare.
@@ -129,8 +162,27 @@ pub trait Backend: Send {
     }
     /// Returns `true` if there are no buffered messages in the backend.
     async fn is_empty(&mut self) -> Result<bool, BackendError>;
+    /// Returns `true` if there are no buffered messages in the backend,
+    /// and none will appear until more are pushed.
+    ///
+    /// Connections asked to process no new records skip the backend when
+    /// this is true.  Backends which can produce messages on their own,
+    /// such as by decrypting deferred records, must return `false`, as
+    /// the default does.
+    fn is_idle(&self) -> bool {
+        false
+    }
     /// Signals to the backend that the server has closed the connection.
     async fn server_closed(&mut self) -> Result<(), BackendError> {
         Ok(())
     }
+    /// Returns the application traffic keys in the clear.
+    ///
+    /// Backends which never hold the keys in one place, such as MPC
+    /// backends, cannot do this, and by default this fails.
+    async fn extract_secrets(&mut self) -> Result<TrafficSecrets, BackendError> {
+        Err(BackendError::InvalidState(
+            "backend cannot extract secrets".to_string(),
+        ))
+    }
 }
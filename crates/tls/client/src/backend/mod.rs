@@ -1,4 +1,4 @@
 mod standard;
 
 pub use standard::RustCryptoBackend;
-pub use tls_backend::{Backend, BackendError, DecryptMode, EncryptMode};
+pub use tls_backend::{Backend, BackendError, DecryptMode, EncryptMode, TrafficSecrets};
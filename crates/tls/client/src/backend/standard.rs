@@ -1,11 +1,11 @@
-use super::{Backend, BackendError};
+use super::{Backend, BackendError, TrafficSecrets};
 use crate::{DecryptMode, EncryptMode, Error};
 use aes_gcm::{
     aead::{generic_array::GenericArray, Aead, NewAead, Payload},
     Aes128Gcm,
 };
 use async_trait::async_trait;
-use p256::{ecdh::EphemeralSecret, EncodedPoint, PublicKey as ECDHPublicKey};
+use p256::{ecdh::diffie_hellman, EncodedPoint, NonZeroScalar, PublicKey as ECDHPublicKey};
 use rand::{rng, rngs::OsRng, Rng};
 
 use digest::Digest;
@@ -24,6 +24,8 @@ use tls_core::{
     prf::prf,
     suites::{self, SupportedCipherSuite},
 };
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, Zeroizing};
 
 /// Implementation of TLS backend using RustCrypto primitives
 pub struct RustCryptoBackend {
@@ -34,7 +36,11 @@ pub struct RustCryptoBackend {
     // extended master secret seed
     ems_seed: Option<Vec<u8>>,
     ecdh_pubkey: Option<Vec<u8>>,
-    ecdh_secret: Option<EphemeralSecret>,
+    ecdh_secret: Option<NonZeroScalar>,
+    // values to use instead of generating our own, either injected or
+    // generated ahead of time
+    injected_client_random: Option<Random>,
+    injected_ecdh_secret: Option<NonZeroScalar>,
     // session_keys size can vary depending on the ciphersuite
     session_keys: Option<Vec<u8>>,
     protocol_version: Option<ProtocolVersion>,
@@ -60,6 +66,8 @@ impl RustCryptoBackend {
             server_random: None,
             ecdh_pubkey: None,
             ecdh_secret: None,
+            injected_client_random: None,
+            injected_ecdh_secret: None,
             master_secret: None,
             ems_seed: None,
             session_keys: None,
@@ -81,6 +89,38 @@ impl RustCryptoBackend {
         }
     }
 
+    /// Sets the client random to send, instead of generating one.
+    ///
+    /// This must be called before the connection is started.  It is
+    /// intended for reproducing a handshake whose randomness was chosen
+    /// elsewhere, e.g. jointly with another party; all other random values,
+    /// such as the session ID, are still generated.
+    #[cfg(feature = "dangerous_configuration")]
+    pub fn set_client_random(&mut self, random: [u8; 32]) {
+        self.injected_client_random = Some(Random(random));
+    }
+
+    /// Sets the private scalar of our key share for `group`, as big-endian
+    /// bytes, instead of generating one.
+    ///
+    /// This must be called before the connection is started.  Only
+    /// secp256r1 is supported.
+    #[cfg(feature = "dangerous_configuration")]
+    pub fn set_key_share_private(
+        &mut self,
+        group: NamedGroup,
+        scalar: &[u8],
+    ) -> Result<(), BackendError> {
+        if group != NamedGroup::secp256r1 {
+            return Err(BackendError::UnsupportedCurveGroup(group));
+        }
+
+        let secret = p256::SecretKey::from_slice(scalar)
+            .map_err(|_| BackendError::InvalidConfig("invalid key share scalar".to_string()))?;
+        self.injected_ecdh_secret = Some(secret.to_nonzero_scalar());
+        Ok(())
+    }
+
     /// Expands the handshake hash and master secret into verify_data for
     /// the Server_Finished
     pub fn verify_data_sf_tls12(&self, hs_hash: &[u8], ms: &[u8; 48]) -> [u8; 12] {
@@ -128,6 +168,31 @@ impl RustCryptoBackend {
         (ms, session_keys)
     }
 
+    /// Like [`key_expansion_tls12`](Self::key_expansion_tls12), but
+    /// expands the pre-master secret into the extended master secret of
+    /// RFC 7627, using `session_hash`.
+    pub fn extended_key_expansion_tls12(
+        &mut self,
+        client_random: &[u8; 32],
+        server_random: &[u8; 32],
+        pms: &[u8],
+        session_hash: &[u8],
+    ) -> ([u8; 48], [u8; 40]) {
+        let mut ms = [0u8; 48];
+        prf(&mut ms, pms, b"extended master secret", session_hash).expect("key length is valid");
+
+        let mut session_keys = [0u8; 40];
+        prf(
+            &mut session_keys,
+            &ms,
+            b"key expansion",
+            &concat::<64>(server_random, client_random),
+        )
+        .expect("key length is valid");
+
+        (ms, session_keys)
+    }
+
     fn set_encrypter(&mut self) -> Result<(), BackendError> {
         let cipher_suite = self.cipher_suite.ok_or(BackendError::InvalidState(
             "can not set enccrypter, ciphersuite not set".to_string(),
@@ -181,7 +246,7 @@ impl RustCryptoBackend {
         Ok(())
     }
 
-    fn encrypt(&mut self, msg: PlainMessage, seq: u64) -> Result<OpaqueMessage, BackendError> {
+    fn encrypt(&mut self, msg: &PlainMessage, seq: u64) -> Result<OpaqueMessage, BackendError> {
         let enc = self
             .encrypter
             .as_mut()
@@ -193,7 +258,7 @@ impl RustCryptoBackend {
             CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256
             | CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256 => match msg.version {
                 ProtocolVersion::TLSv1_2 => {
-                    return enc.encrypt_aes128gcm(&msg, seq, &seq.to_be_bytes());
+                    return enc.encrypt_aes128gcm(msg, seq, &seq.to_be_bytes());
                 }
                 version => {
                     return Err(BackendError::UnsupportedProtocolVersion(version));
@@ -230,6 +295,24 @@ impl RustCryptoBackend {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl Drop for RustCryptoBackend {
+    fn drop(&mut self) {
+        self.master_secret.zeroize();
+        self.ems_seed.zeroize();
+        self.session_keys.zeroize();
+        self.ecdh_secret.zeroize();
+        self.injected_ecdh_secret.zeroize();
+        for msg in self
+            .incoming_plain
+            .iter_mut()
+            .chain(&mut self.outgoing_plain)
+        {
+            msg.payload.0.zeroize();
+        }
+    }
+}
+
 #[async_trait]
 impl Backend for RustCryptoBackend {
     async fn set_protocol_version(&mut self, version: ProtocolVersion) -> Result<(), BackendError> {
@@ -279,7 +362,10 @@ impl Backend for RustCryptoBackend {
 
     async fn get_client_random(&mut self) -> Result<Random, BackendError> {
         // generate client random and store it
-        let r = Random(rng().random());
+        let r = self
+            .injected_client_random
+            .take()
+            .unwrap_or_else(|| Random(rng().random()));
         self.client_random = Some(r);
         Ok(r)
     }
@@ -287,8 +373,13 @@ impl Backend for RustCryptoBackend {
     async fn get_client_key_share(&mut self) -> Result<PublicKey, BackendError> {
         // TODO make sure this and other methods are not called twice/out of order
         // generate our ECDH keypair
-        let sk = EphemeralSecret::random(&mut rng().compat());
-        let pk_bytes = EncodedPoint::from(sk.public_key()).to_bytes().to_vec();
+        let sk = self
+            .injected_ecdh_secret
+            .take()
+            .unwrap_or_else(|| NonZeroScalar::random(&mut rng().compat()));
+        let pk_bytes = EncodedPoint::from(ECDHPublicKey::from_secret_scalar(&sk))
+            .to_bytes()
+            .to_vec();
         self.ecdh_pubkey = Some(pk_bytes.clone());
         self.ecdh_secret = Some(sk);
 
@@ -303,6 +394,13 @@ impl Backend for RustCryptoBackend {
         })
     }
 
+    async fn prepare_client_key_share(&mut self) -> Result<(), BackendError> {
+        if self.injected_ecdh_secret.is_none() {
+            self.injected_ecdh_secret = Some(NonZeroScalar::random(&mut rng().compat()));
+        }
+        Ok(())
+    }
+
     async fn set_server_random(&mut self, random: Random) -> Result<(), BackendError> {
         // store server random
         self.server_random = Some(random);
@@ -327,7 +425,7 @@ impl Backend for RustCryptoBackend {
         };
 
         let mut pms = vec![0u8; x_size];
-        let secret = *sk.diffie_hellman(&server_pk).raw_secret_bytes();
+        let secret = *diffie_hellman(sk, server_pk.as_affine()).raw_secret_bytes();
         pms.copy_from_slice(&secret);
 
         let (client_random, server_random) = match (self.client_random, self.server_random) {
@@ -343,7 +441,15 @@ impl Backend for RustCryptoBackend {
             BackendError::InvalidState("Protocol version not set".to_string()),
         )? {
             ProtocolVersion::TLSv1_2 => {
-                let (ms, ek) = self.key_expansion_tls12(&client_random, &server_random, &pms);
+                let (ms, ek) = match self.ems_seed.clone() {
+                    Some(session_hash) => self.extended_key_expansion_tls12(
+                        &client_random,
+                        &server_random,
+                        &pms,
+                        &session_hash,
+                    ),
+                    None => self.key_expansion_tls12(&client_random, &server_random, &pms),
+                };
                 (Some(ms), Some(ek.to_vec()))
             }
             version => return Err(BackendError::UnsupportedProtocolVersion(version)),
@@ -442,7 +548,10 @@ impl Backend for RustCryptoBackend {
 
         for outgoing in take(&mut self.outgoing_plain) {
             let seq = self.write_seq;
-            let encrypted = self.encrypt(outgoing, seq)?;
+            let encrypted = self.encrypt(&outgoing, seq);
+            #[cfg(feature = "zeroize")]
+            drop(Zeroizing::new(outgoing.payload.0));
+            let encrypted = encrypted?;
             self.outgoing_encrypted.push_back(encrypted);
             self.write_seq += 1;
         }
@@ -451,10 +560,33 @@ impl Backend for RustCryptoBackend {
     }
 
     async fn is_empty(&mut self) -> Result<bool, BackendError> {
-        Ok(self.incoming_plain.is_empty()
+        Ok(self.is_idle())
+    }
+
+    fn is_idle(&self) -> bool {
+        self.incoming_plain.is_empty()
             && self.outgoing_plain.is_empty()
             && self.incoming_encrypted.is_empty()
-            && self.outgoing_encrypted.is_empty())
+            && self.outgoing_encrypted.is_empty()
+    }
+
+    async fn extract_secrets(&mut self) -> Result<TrafficSecrets, BackendError> {
+        let session_keys = self
+            .session_keys
+            .as_ref()
+            .ok_or(BackendError::InvalidState(
+                "can not extract secrets, session_keys are not set".to_string(),
+            ))?;
+
+        // The same layout as used by `set_encrypter` and `set_decrypter`.
+        Ok(TrafficSecrets {
+            client_write_key: session_keys[0..16].to_vec(),
+            client_write_iv: session_keys[32..36].to_vec(),
+            server_write_key: session_keys[16..32].to_vec(),
+            server_write_iv: session_keys[36..40].to_vec(),
+            client_seq: self.write_seq,
+            server_seq: self.read_seq,
+        })
     }
 }
 
@@ -528,6 +660,14 @@ impl Encrypter {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl Drop for Encrypter {
+    fn drop(&mut self) {
+        self.write_key.zeroize();
+        self.write_iv.zeroize();
+    }
+}
+
 pub struct Decrypter {
     write_key: [u8; 16],
     write_iv: [u8; 4],
@@ -580,3 +720,11 @@ impl Decrypter {
         })
     }
 }
+
+#[cfg(feature = "zeroize")]
+impl Drop for Decrypter {
+    fn drop(&mut self) {
+        self.write_key.zeroize();
+        self.write_iv.zeroize();
+    }
+}
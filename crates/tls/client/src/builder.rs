@@ -1,9 +1,11 @@
 use crate::{
     error::Error,
+    fingerprint::ClientProfile,
     kx::{SupportedKxGroup, ALL_KX_GROUPS},
 };
 use tls_core::{
-    suites::{SupportedCipherSuite, DEFAULT_CIPHER_SUITES},
+    msgs::enums::{Compression, SignatureScheme},
+    suites::{SupportedCipherSuite, ALL_CIPHER_SUITES, DEFAULT_CIPHER_SUITES},
     versions,
 };
 
@@ -134,10 +136,54 @@ impl ConfigBuilder<WantsCipherSuites> {
                 cipher_suites: DEFAULT_CIPHER_SUITES.to_vec(),
                 kx_groups: ALL_KX_GROUPS.to_vec(),
                 versions: versions::EnabledVersions::new(versions::DEFAULT_VERSIONS),
+                profile: None,
+                signature_schemes: None,
             },
         }
     }
 
+    /// Start side-specific config shaped like the given [`ClientProfile`].
+    ///
+    /// The cipher suites and key exchange groups are those of the profile
+    /// which are supported, in the profile's order. All safe supported
+    /// protocol versions are enabled. The profile's extension order, GREASE
+    /// and compression settings are carried through to the [`ClientConfig`].
+    ///
+    /// This fails if the profile names no supported cipher suite or key
+    /// exchange group, or does not offer null compression.
+    ///
+    /// [`ClientConfig`]: crate::ClientConfig
+    pub fn with_profile(
+        self,
+        profile: ClientProfile,
+    ) -> Result<ConfigBuilder<WantsVerifier>, Error> {
+        if !profile.compression_methods.contains(&Compression::Null) {
+            return Err(Error::General(
+                "profile does not offer null compression".into(),
+            ));
+        }
+
+        let cipher_suites: Vec<SupportedCipherSuite> = profile
+            .cipher_suites
+            .iter()
+            .filter_map(|cs| ALL_CIPHER_SUITES.iter().find(|scs| scs.suite() == *cs))
+            .copied()
+            .collect();
+        let kx_groups: Vec<&'static SupportedKxGroup> = profile
+            .kx_groups
+            .iter()
+            .filter_map(|group| ALL_KX_GROUPS.iter().find(|skxg| skxg.name == *group))
+            .copied()
+            .collect();
+
+        let mut builder = self
+            .with_cipher_suites(&cipher_suites)
+            .with_kx_groups(&kx_groups)
+            .with_safe_default_protocol_versions()?;
+        builder.state.profile = Some(profile);
+        Ok(builder)
+    }
+
     /// Choose a specific set of cipher suites.
     pub fn with_cipher_suites(
         self,
@@ -232,6 +278,8 @@ impl ConfigBuilder<WantsVersions> {
                 cipher_suites: self.state.cipher_suites,
                 kx_groups: self.state.kx_groups,
                 versions: versions::EnabledVersions::new(versions),
+                profile: None,
+                signature_schemes: None,
             },
         })
     }
@@ -244,4 +292,6 @@ pub struct WantsVerifier {
     pub(crate) cipher_suites: Vec<SupportedCipherSuite>,
     pub(crate) kx_groups: Vec<&'static SupportedKxGroup>,
     pub(crate) versions: versions::EnabledVersions,
+    pub(crate) profile: Option<ClientProfile>,
+    pub(crate) signature_schemes: Option<Vec<SignatureScheme>>,
 }
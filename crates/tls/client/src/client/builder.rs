@@ -1,17 +1,65 @@
 use crate::{
     anchors,
     builder::{ConfigBuilder, WantsVerifier},
-    client::{handy, ClientConfig, ResolvesClientCert},
+    client::{handy, ClientConfig, PathPolicy, ResolvesClientCertAsync},
     error::Error,
+    fingerprint::ClientProfile,
     kx::SupportedKxGroup,
     verify::{self, CertificateTransparencyPolicy},
-    NoKeyLog,
+    NoKeyLog, SystemRandomProvider,
 };
 use std::sync::Arc;
-use tls_core::{key, suites::SupportedCipherSuite, versions};
+use tls_core::{
+    key,
+    msgs::enums::{Compression, SignatureScheme},
+    suites::SupportedCipherSuite,
+    versions,
+};
 use web_time::SystemTime;
 
 impl ConfigBuilder<WantsVerifier> {
+    /// Choose the signature schemes offered to the server, in order of
+    /// preference.
+    ///
+    /// Schemes the default verifier cannot check are dropped.  This fails
+    /// if none remain.
+    pub fn with_signature_schemes(
+        mut self,
+        signature_schemes: &[SignatureScheme],
+    ) -> Result<Self, Error> {
+        let usable = verify::WebPkiVerifier::verification_schemes();
+        let signature_schemes: Vec<SignatureScheme> = signature_schemes
+            .iter()
+            .filter(|scheme| usable.contains(scheme))
+            .copied()
+            .collect();
+
+        if signature_schemes.is_empty() {
+            return Err(Error::General("no usable signature schemes".into()));
+        }
+
+        self.state.signature_schemes = Some(signature_schemes);
+        Ok(self)
+    }
+
+    /// Verify server certificates against the Mozilla root certificates
+    /// bundled in the `webpki-roots` crate.
+    ///
+    /// This is [`Self::with_root_certificates`] with a store holding
+    /// those roots.
+    #[cfg(feature = "webpki-roots")]
+    pub fn with_webpki_roots(self) -> ConfigBuilder<WantsTransparencyPolicyOrClientCert> {
+        let mut root_store = anchors::RootCertStore::empty();
+        root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            anchors::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject.as_ref(),
+                ta.subject_public_key_info.as_ref(),
+                ta.name_constraints.as_ref().map(|nc| nc.as_ref()),
+            )
+        }));
+        self.with_root_certificates(root_store)
+    }
+
     /// Choose how to verify client certificates.
     pub fn with_root_certificates(
         self,
@@ -23,6 +71,8 @@ impl ConfigBuilder<WantsVerifier> {
                 kx_groups: self.state.kx_groups,
                 versions: self.state.versions,
                 root_store,
+                profile: self.state.profile,
+                signature_schemes: self.state.signature_schemes,
             },
         }
     }
@@ -40,6 +90,8 @@ pub struct WantsTransparencyPolicyOrClientCert {
     kx_groups: Vec<&'static SupportedKxGroup>,
     versions: versions::EnabledVersions,
     root_store: anchors::RootCertStore,
+    profile: Option<ClientProfile>,
+    signature_schemes: Option<Vec<SignatureScheme>>,
 }
 
 impl ConfigBuilder<WantsTransparencyPolicyOrClientCert> {
@@ -76,16 +128,31 @@ impl ConfigBuilder<WantsTransparencyPolicyOrClientCert> {
         self.with_logs(None).with_single_cert(cert_chain, key_der)
     }
 
+    /// Sets the certificate chain and private key for use in client
+    /// authentication from a DER-encoded PKCS#12 archive.
+    ///
+    /// See [`ConfigBuilder<WantsClientCert>::with_client_auth_pkcs12`].
+    #[cfg(feature = "pkcs12")]
+    pub fn with_client_auth_pkcs12(
+        self,
+        der: &[u8],
+        password: &str,
+    ) -> Result<ClientConfig, Error> {
+        self.with_logs(None).with_client_auth_pkcs12(der, password)
+    }
+
     /// Do not support client auth.
     pub fn with_no_client_auth(self) -> ClientConfig {
         self.with_logs(None)
             .with_client_cert_resolver(Arc::new(handy::FailResolveClientCert {}))
     }
 
-    /// Sets a custom [`ResolvesClientCert`].
+    /// Sets a custom [`ResolvesClientCert`] or [`ResolvesClientCertAsync`].
+    ///
+    /// [`ResolvesClientCert`]: crate::client::ResolvesClientCert
     pub fn with_client_cert_resolver(
         self,
-        client_auth_cert_resolver: Arc<dyn ResolvesClientCert>,
+        client_auth_cert_resolver: Arc<dyn ResolvesClientCertAsync>,
     ) -> ClientConfig {
         self.with_logs(None)
             .with_client_cert_resolver(client_auth_cert_resolver)
@@ -104,6 +171,8 @@ impl ConfigBuilder<WantsTransparencyPolicyOrClientCert> {
                     self.state.root_store,
                     ct_policy,
                 )),
+                profile: self.state.profile,
+                signature_schemes: self.state.signature_schemes,
             },
         }
     }
@@ -118,6 +187,8 @@ pub struct WantsClientCert {
     kx_groups: Vec<&'static SupportedKxGroup>,
     versions: versions::EnabledVersions,
     verifier: Arc<dyn verify::ServerCertVerifier>,
+    profile: Option<ClientProfile>,
+    signature_schemes: Option<Vec<SignatureScheme>>,
 }
 
 impl ConfigBuilder<WantsClientCert> {
@@ -137,16 +208,46 @@ impl ConfigBuilder<WantsClientCert> {
         Ok(self.with_client_cert_resolver(Arc::new(resolver)))
     }
 
+    /// Sets the certificate chain and private key for use in client
+    /// authentication from a DER-encoded PKCS#12 archive.
+    ///
+    /// The archive must contain exactly one private key, and its
+    /// certificates must start with the one matching that key, as is
+    /// usual.  It is decrypted with `password`.
+    ///
+    /// This function fails with [`Error::General`] if the archive cannot
+    /// be parsed or decrypted, or its key is invalid.
+    #[cfg(feature = "pkcs12")]
+    pub fn with_client_auth_pkcs12(
+        self,
+        der: &[u8],
+        password: &str,
+    ) -> Result<ClientConfig, Error> {
+        let (cert_chain, key_der) = crate::pkcs12::parse(der, password)?;
+        self.with_single_cert(cert_chain, key_der)
+    }
+
     /// Do not support client auth.
     pub fn with_no_client_auth(self) -> ClientConfig {
         self.with_client_cert_resolver(Arc::new(handy::FailResolveClientCert {}))
     }
 
-    /// Sets a custom [`ResolvesClientCert`].
+    /// Sets a custom [`ResolvesClientCert`] or [`ResolvesClientCertAsync`].
+    ///
+    /// [`ResolvesClientCert`]: crate::client::ResolvesClientCert
     pub fn with_client_cert_resolver(
         self,
-        client_auth_cert_resolver: Arc<dyn ResolvesClientCert>,
+        client_auth_cert_resolver: Arc<dyn ResolvesClientCertAsync>,
     ) -> ClientConfig {
+        let (extension_order, grease, compression_methods) = match self.state.profile {
+            Some(profile) => (
+                profile.extension_order,
+                profile.grease,
+                profile.compression_methods,
+            ),
+            None => (Vec::new(), false, vec![Compression::Null]),
+        };
+
         ClientConfig {
             cipher_suites: self.state.cipher_suites,
             kx_groups: self.state.kx_groups,
@@ -158,8 +259,41 @@ impl ConfigBuilder<WantsClientCert> {
             versions: self.state.versions,
             enable_sni: true,
             verifier: self.state.verifier,
+            async_verifier: None,
             key_log: Arc::new(NoKeyLog {}),
             enable_early_data: false,
+            strict_post_handshake: false,
+            extension_order,
+            enable_grease: grease,
+            compression_methods,
+            request_ocsp_stapling: true,
+            reject_unsolicited_extensions: false,
+            strict_record_parsing: false,
+            coalesce_writes: false,
+            max_fragment_length: None,
+            required_alpn: None,
+            alpn_strict_client_preference: false,
+            enforce_downgrade_protection: true,
+            max_handshake_fragments: 64,
+            server_random_check: None,
+            transcript_byte_observer: None,
+            tolerate_unordered_chain: false,
+            client_hello_transform: None,
+            client_hello_inspector: None,
+            sigalg_reduction: None,
+            path_building: PathPolicy::TryAlternates,
+            report_post_handshake_events: false,
+            max_handshake_message_size: Some(0xffff),
+            time_provider: Arc::new(handy::SystemTimeProvider),
+            send_tls13_compat_ccs: true,
+            rng_provider: Arc::new(SystemRandomProvider),
+            signature_schemes: self.state.signature_schemes,
+            record_observer: None,
+            client_cert_type_preference: Vec::new(),
+            allow_unclean_eof: false,
+            enable_ech_grease: false,
+            record_size_limit: None,
+            require_ems: false,
         }
     }
 }
@@ -3,12 +3,14 @@ use async_trait::async_trait;
 use super::hs;
 #[cfg(feature = "logging")]
 use crate::log::trace;
+#[cfg(feature = "notary")]
+use crate::notary::NotaryBundle;
 use crate::{
     builder::{ConfigBuilder, WantsCipherSuites},
     conn::{CommonState, ConnectionCommon, Protocol, Side, State},
     error::Error,
     kx::SupportedKxGroup,
-    sign, verify, Backend, KeyLog,
+    sign, verify, Backend, KeyLog, SecureRandomProvider,
 };
 use std::{
     convert::TryFrom,
@@ -19,14 +21,20 @@ use std::{
 };
 pub use tls_core::dns::*;
 use tls_core::{
+    key::Certificate,
     msgs::{
-        enums::{CipherSuite, ProtocolVersion, SignatureScheme},
-        handshake::ClientExtension,
+        base::PayloadU16,
+        enums::{
+            CertificateType, CipherSuite, Compression, ContentType, ExtensionType,
+            MaxFragmentLength, ProtocolVersion, SignatureScheme,
+        },
+        handshake::{ClientExtension, ClientHelloPayload, SCTList},
         message::Message,
     },
     suites::SupportedCipherSuite,
     versions,
 };
+use web_time::SystemTime;
 
 /// A trait for the ability to store client session data.
 /// The keys and values are opaque.
@@ -49,6 +57,97 @@ pub trait StoresClientSessions: Send + Sync {
     fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
 }
 
+/// An asynchronous version of [`StoresClientSessions`], for stores
+/// which cannot answer without waiting, such as a network cache.
+///
+/// Every [`StoresClientSessions`] is also a `StoresClientSessionsAsync`,
+/// so either can be used as [`ClientConfig::session_storage`].
+#[async_trait]
+pub trait StoresClientSessionsAsync: Send + Sync {
+    /// Stores a new `value` for `key`.  Returns `true`
+    /// if the value was stored.
+    async fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool;
+
+    /// Returns the latest value for `key`.  Returns `None`
+    /// if there's no such value.
+    async fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+}
+
+#[async_trait]
+impl<T: StoresClientSessions + ?Sized> StoresClientSessionsAsync for T {
+    async fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        StoresClientSessions::put(self, key, value)
+    }
+
+    async fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        StoresClientSessions::get(self, key)
+    }
+}
+
+/// Something that can verify a server certificate chain without
+/// blocking, for example by consulting a remote OCSP or Certificate
+/// Transparency service.
+///
+/// This replaces only the chain check of the configured
+/// [`verify::ServerCertVerifier`], which still verifies handshake
+/// signatures.  Set it with `ClientConfig::dangerous`, which requires the
+/// `dangerous_configuration` feature.
+#[async_trait]
+pub trait ServerCertVerifierAsync: Send + Sync {
+    /// Verify the end-entity certificate `end_entity` is valid for
+    /// `server_name` and chains to a trusted root.
+    ///
+    /// `intermediates` are the other certificates the server sent, in the
+    /// order it sent them.  `scts` are the Signed Certificate Timestamps
+    /// and `ocsp_response` the stapled OCSP response it sent, if any.
+    ///
+    /// An error fails the handshake after a bad_certificate alert, as a
+    /// failure of the built-in verifier does.
+    async fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &[&[u8]],
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<verify::ServerCertVerified, tls_core::Error>;
+}
+
+/// A source of the current time, against which the validity period of
+/// the server's certificates is checked.
+///
+/// This allows clients without a wall clock to supply a time from
+/// elsewhere, such as NTP, and tests to check certificates at a fixed
+/// time.
+pub trait TimeProvider: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// Which way a record observed by a [`RecordObserver`] travelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// The record was read from the server.
+    Inbound,
+    /// The record was queued to be written to the server.
+    Outbound,
+}
+
+/// Observes the TLS records a connection reads and writes, for
+/// debugging.
+///
+/// Only metadata is reported: record payloads are never passed on.
+pub trait RecordObserver: Send + Sync {
+    /// Called once per record, with the content type from its header
+    /// and the length of its payload on the wire.
+    ///
+    /// Inbound records are reported by `read_tls` as soon as they are
+    /// complete, and outbound records when they are queued for
+    /// `write_tls`.
+    fn on_record(&self, direction: Direction, content_type: ContentType, len: usize);
+}
+
 /// A trait for the ability to choose a certificate chain and
 /// private key for the purposes of client authentication.
 pub trait ResolvesClientCert: Send + Sync {
@@ -73,6 +172,44 @@ pub trait ResolvesClientCert: Send + Sync {
     fn has_certs(&self) -> bool;
 }
 
+/// An asynchronous version of [`ResolvesClientCert`], for keys held
+/// behind a service that cannot answer without waiting, such as a
+/// PKCS#11 token or a remote key management service.
+///
+/// Every [`ResolvesClientCert`] is also a `ResolvesClientCertAsync`,
+/// so either can be used as [`ClientConfig::client_auth_cert_resolver`].
+#[async_trait]
+pub trait ResolvesClientCertAsync: Send + Sync {
+    /// Chooses a certificate chain and signing key to authenticate
+    /// with, as [`ResolvesClientCert::resolve`] does.
+    ///
+    /// Return None to continue the handshake without any client
+    /// authentication.
+    async fn resolve(
+        &self,
+        acceptable_issuers: &[&[u8]],
+        sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<sign::CertifiedKey>>;
+
+    /// Return true if any certificates at all are available.
+    fn has_certs(&self) -> bool;
+}
+
+#[async_trait]
+impl<T: ResolvesClientCert + ?Sized> ResolvesClientCertAsync for T {
+    async fn resolve(
+        &self,
+        acceptable_issuers: &[&[u8]],
+        sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<sign::CertifiedKey>> {
+        ResolvesClientCert::resolve(self, acceptable_issuers, sigschemes)
+    }
+
+    fn has_certs(&self) -> bool {
+        ResolvesClientCert::has_certs(self)
+    }
+}
+
 /// Common configuration for (typically) all connections made by
 /// a program.
 ///
@@ -87,6 +224,39 @@ pub trait ResolvesClientCert: Send + Sync {
 /// * [`ClientConfig::session_storage`]: the default stores 256 sessions in memory.
 /// * [`ClientConfig::alpn_protocols`]: the default is empty -- no ALPN protocol is negotiated.
 /// * [`ClientConfig::key_log`]: key material is not logged.
+/// * [`ClientConfig::strict_post_handshake`]: the default is false.
+/// * [`ClientConfig::extension_order`]: the default is empty -- extensions are sent in their usual order.
+/// * [`ClientConfig::enable_grease`]: the default is false.
+/// * [`ClientConfig::compression_methods`]: the default offers only null compression.
+/// * [`ClientConfig::request_ocsp_stapling`]: the default is true.
+/// * [`ClientConfig::reject_unsolicited_extensions`]: the default is false.
+/// * [`ClientConfig::strict_record_parsing`]: the default is false.
+/// * [`ClientConfig::coalesce_writes`]: the default is false.
+/// * [`ClientConfig::max_fragment_length`]: the default is `None` -- no extension is sent.
+/// * [`ClientConfig::required_alpn`]: the default is `None` -- any offered protocol is accepted.
+/// * [`ClientConfig::alpn_strict_client_preference`]: the default is false.
+/// * [`ClientConfig::enforce_downgrade_protection`]: the default is true.
+/// * [`ClientConfig::max_handshake_fragments`]: the default is 64.
+/// * [`ClientConfig::server_random_check`]: the default is `None` -- the server random is not checked.
+/// * [`ClientConfig::transcript_byte_observer`]: the default is `None`.
+/// * [`ClientConfig::tolerate_unordered_chain`]: the default is false.
+/// * [`ClientConfig::client_hello_transform`]: the default is `None`.
+/// * [`ClientConfig::client_hello_inspector`]: the default is `None`.
+/// * [`ClientConfig::sigalg_reduction`]: the default is `None` -- the suite's own signature algorithms are accepted.
+/// * [`ClientConfig::path_building`]: the default is [`PathPolicy::TryAlternates`].
+/// * [`ClientConfig::report_post_handshake_events`]: the default is false.
+/// * [`ClientConfig::max_handshake_message_size`]: the default is 65535 bytes.
+/// * [`ClientConfig::time_provider`]: the default is
+///   [`SystemTimeProvider`](crate::client::SystemTimeProvider).
+/// * [`ClientConfig::send_tls13_compat_ccs`]: the default is true.
+/// * [`ClientConfig::rng_provider`]: the default is [`SystemRandomProvider`](crate::SystemRandomProvider).
+/// * [`ClientConfig::signature_schemes`]: the default is `None` -- every scheme the verifier supports is offered.
+/// * [`ClientConfig::record_observer`]: the default is `None`.
+/// * [`ClientConfig::client_cert_type_preference`]: the default is empty -- only X.509 is used.
+/// * [`ClientConfig::allow_unclean_eof`]: the default is false.
+/// * [`ClientConfig::enable_ech_grease`]: the default is false.
+/// * [`ClientConfig::record_size_limit`]: the default is `None` -- no extension is sent.
+/// * [`ClientConfig::require_ems`]: the default is false.
 #[derive(Clone)]
 pub struct ClientConfig {
     /// List of ciphersuites, in preference order.
@@ -104,7 +274,14 @@ pub struct ClientConfig {
     pub alpn_protocols: Vec<Vec<u8>>,
 
     /// How we store session data or tickets.
-    pub session_storage: Arc<dyn StoresClientSessions>,
+    ///
+    /// Sessions are stored by server name, not per config, so configs
+    /// sharing a storage can resume each other's sessions.  A stored
+    /// session is only offered if its cipher suite is in this config's
+    /// cipher suites and its protocol version is enabled here.
+    ///
+    /// The handshake awaits the storage, so it may be asynchronous.
+    pub session_storage: Arc<dyn StoresClientSessionsAsync>,
 
     /// The maximum size of TLS message we'll emit.  If None, we don't limit TLS
     /// message lengths except to the 2**16 limit specified in the standard.
@@ -116,7 +293,9 @@ pub struct ClientConfig {
     pub max_fragment_size: Option<usize>,
 
     /// How to decide what client auth certificate/keys to use.
-    pub client_auth_cert_resolver: Arc<dyn ResolvesClientCert>,
+    ///
+    /// The handshake awaits the resolver, so it may be asynchronous.
+    pub client_auth_cert_resolver: Arc<dyn ResolvesClientCertAsync>,
 
     /// Whether to support RFC5077 tickets.  You must provide a working
     /// `session_storage` member for this to have any meaningful
@@ -138,6 +317,10 @@ pub struct ClientConfig {
     /// How to verify the server certificate chain.
     pub(super) verifier: Arc<dyn verify::ServerCertVerifier>,
 
+    /// Verifies the server certificate chain in place of `verifier`,
+    /// if set.
+    pub(super) async_verifier: Option<Arc<dyn ServerCertVerifierAsync>>,
+
     /// How to output key material for debugging.  The default
     /// does nothing.
     pub key_log: Arc<dyn KeyLog>,
@@ -147,6 +330,380 @@ pub struct ClientConfig {
     ///
     /// The default is false.
     pub enable_early_data: bool,
+
+    /// Whether to treat any handshake message received after the handshake,
+    /// other than NewSessionTicket, KeyUpdate or a post-handshake
+    /// CertificateRequest, as a fatal error.
+    ///
+    /// When set, such messages (including TLS1.2 renegotiation requests)
+    /// are rejected with [`Error::PeerMisbehavedError`].
+    ///
+    /// The default is false.
+    pub strict_post_handshake: bool,
+
+    /// The order in which ClientHello extensions are sent.  Extensions
+    /// which are not listed follow those which are, in their usual order.
+    pub extension_order: Vec<ExtensionType>,
+
+    /// Whether to send GREASE values (RFC 8701) in the ClientHello
+    /// cipher suites, supported groups, supported versions and extensions.
+    ///
+    /// A GREASE extension echoed by the server is ignored, while a GREASE
+    /// cipher suite, group or version chosen by the server fails the
+    /// handshake as any unsupported choice would.
+    ///
+    /// The default is false, unless set by a [`ClientProfile`].
+    ///
+    /// [`ClientProfile`]: crate::fingerprint::ClientProfile
+    pub enable_grease: bool,
+
+    /// Compression methods offered in the ClientHello.  Servers choosing
+    /// anything other than null compression are rejected regardless.
+    pub compression_methods: Vec<Compression>,
+
+    /// Whether to ask the server to staple an OCSP response, by sending
+    /// the `status_request` extension.
+    ///
+    /// The default is true.
+    pub request_ocsp_stapling: bool,
+
+    /// Whether to treat any server extension we did not offer as a fatal
+    /// [`Error::PeerMisbehavedError`], as RFC 8446 requires.
+    ///
+    /// When false, a stapled OCSP response we didn't ask for is ignored
+    /// rather than rejected.
+    ///
+    /// The default is false.
+    pub reject_unsolicited_extensions: bool,
+
+    /// Whether to check record framing strictly, failing with a precise
+    /// [`Error::PeerMisbehavedError`] for records with an illegal length,
+    /// content type or version, oversized plaintext records, and records
+    /// interleaved with a fragmented handshake message.
+    ///
+    /// When false, malformed framing is reported as [`Error::CorruptMessage`].
+    ///
+    /// The default is false.
+    pub strict_record_parsing: bool,
+
+    /// Whether to hold back application data written after the handshake,
+    /// so that several small writes are sent as one record.
+    ///
+    /// Buffered data is sent when a full record's worth has accumulated,
+    /// or when [`ConnectionCommon::flush_plaintext`] or
+    /// [`ConnectionCommon::complete_io`] is called.
+    ///
+    /// The default is false.
+    ///
+    /// [`ConnectionCommon::flush_plaintext`]: crate::ConnectionCommon::flush_plaintext
+    /// [`ConnectionCommon::complete_io`]: crate::ConnectionCommon::complete_io
+    pub coalesce_writes: bool,
+
+    /// A maximum fragment length to request from the server with the
+    /// `max_fragment_length` extension (RFC 6066).
+    ///
+    /// If the server acknowledges it, the echoed value must match or the
+    /// handshake fails with [`Error::PeerMisbehavedError`], and our own
+    /// records are limited to that length.  See
+    /// [`ClientConnection::negotiated_max_fragment_length`].
+    ///
+    /// The default is `None`.
+    pub max_fragment_length: Option<MaxFragmentLength>,
+
+    /// The ALPN protocols we are willing to accept from the server.
+    ///
+    /// This may be narrower than [`ClientConfig::alpn_protocols`], which
+    /// is what we offer.  If set, the handshake fails with
+    /// [`Error::NoApplicationProtocol`] unless the server selects one of
+    /// these protocols -- including when it selects none at all.
+    ///
+    /// The default is `None`.
+    pub required_alpn: Option<Vec<Vec<u8>>>,
+
+    /// Whether the server must select our most preferred ALPN protocol.
+    ///
+    /// The server does not tell us which protocols it supports, so when
+    /// this is set any selection other than the first entry of
+    /// [`ClientConfig::alpn_protocols`] fails the handshake with
+    /// [`Error::PeerMisbehavedError`].  A server selecting no protocol is
+    /// still accepted, unless [`ClientConfig::required_alpn`] says
+    /// otherwise.
+    ///
+    /// The default is false: the server's choice of the offered protocols
+    /// is accepted.
+    pub alpn_strict_client_preference: bool,
+
+    /// Whether to abort a TLS 1.2 handshake when TLS 1.3 was offered
+    /// and the server random carries the RFC 8446 downgrade sentinel.
+    ///
+    /// Setting this to false allows interoperating with middleboxes that
+    /// strip `supported_versions`, at the cost of losing protection
+    /// against an active attacker forcing TLS 1.2.  Only do so if you
+    /// understand and accept that risk.
+    ///
+    /// The default is true.
+    pub enforce_downgrade_protection: bool,
+
+    /// The maximum number of records a single handshake message from the
+    /// server may be split across.
+    ///
+    /// Servers may fragment large messages such as Certificate, but
+    /// splitting a message into very many tiny records only costs us
+    /// work.  A message exceeding this limit aborts the handshake with
+    /// [`Error::PeerMisbehavedError`].
+    ///
+    /// The default is 64.
+    pub max_handshake_fragments: usize,
+
+    /// A check run on the random value in the server's ServerHello.
+    ///
+    /// If it returns false the handshake is aborted with
+    /// [`Error::PeerMisbehavedError`].  This allows rejecting randoms
+    /// that are evidently not random, such as all zeros, as a sign of a
+    /// broken or malicious server.
+    ///
+    /// The default is `None`.
+    pub server_random_check: Option<Arc<dyn Fn(&[u8; 32]) -> bool + Send + Sync>>,
+
+    /// Called with each chunk of bytes fed to the handshake transcript
+    /// hash, in the order they are hashed.
+    ///
+    /// Hashing these bytes with the negotiated suite's hash function
+    /// gives the transcript hash used by the handshake.  After a
+    /// HelloRetryRequest, TLS1.3 replaces the transcript so far with a
+    /// synthetic `message_hash` handshake message (type 254); the
+    /// observer is then called with that message, and an external
+    /// hasher should start over from it.
+    ///
+    /// The default is `None`.
+    pub transcript_byte_observer: Option<Arc<dyn Fn(&[u8]) + Send + Sync>>,
+
+    /// Whether to accept a server certificate chain that is not in the
+    /// order TLS requires.
+    ///
+    /// TLS requires the end-entity certificate first, followed by the
+    /// certificate that issued it, and so on.  Some servers send their
+    /// chain in another order.  If this is true such a chain is put in
+    /// order before it is verified; if false the handshake fails with
+    /// [`Error::CertificateChainOutOfOrder`].  Extra certificates after
+    /// that path are allowed either way.
+    ///
+    /// The default is false.
+    pub tolerate_unordered_chain: bool,
+
+    /// Called with each ClientHello just before it is encoded and sent,
+    /// and may change it arbitrarily.
+    ///
+    /// This allows reordering, adding or removing extensions beyond what
+    /// the other options here offer, for example for conformance
+    /// testing.  The handshake continues from whatever the hook leaves,
+    /// so removing or corrupting an extension the handshake relies on
+    /// makes it fail.
+    ///
+    /// The default is `None`.
+    pub client_hello_transform: Option<Arc<dyn Fn(&mut ClientHelloPayload) + Send + Sync>>,
+
+    /// Called with each encoded ClientHello handshake message just
+    /// before it is queued to be sent, after
+    /// [`ClientConfig::client_hello_transform`] has run.
+    ///
+    /// The bytes are those that go into the handshake transcript: the
+    /// handshake header and body, without the record header.  This lets
+    /// callers check a config produces a particular wire image; use
+    /// `client_hello_transform` to change it.
+    ///
+    /// The default is `None`.
+    pub client_hello_inspector: Option<Arc<dyn Fn(&[u8]) + Send + Sync>>,
+
+    /// Decides which signature schemes a TLS1.2 server may sign its key
+    /// exchange with, given the negotiated cipher suite.
+    ///
+    /// It is called with the suite and the schemes we offered, and
+    /// returns those allowed; a ServerKeyExchange signed with any other
+    /// scheme fails the handshake.  This lets callers narrow the choice
+    /// further than the suite does, for example refusing PKCS#1 for RSA
+    /// suites.
+    ///
+    /// The default is `None`, which accepts any scheme using the
+    /// signature algorithm the suite names.
+    pub sigalg_reduction:
+        Option<Arc<dyn Fn(CipherSuite, &[SignatureScheme]) -> Vec<SignatureScheme> + Send + Sync>>,
+
+    /// Which of the paths from the server's certificate to a trust
+    /// anchor are tried when verifying it.
+    ///
+    /// With cross-signed CAs, a chain may offer several paths, some of
+    /// which fail, for example because a cross-signature has expired.
+    ///
+    /// The default is [`PathPolicy::TryAlternates`].
+    pub path_building: PathPolicy,
+
+    /// Whether to report handshake messages the server sends once the
+    /// main handshake is done, as [`PostHandshakeEvent`]s.
+    ///
+    /// The messages are handled as usual either way; if this is true they
+    /// are also queued for [`ClientConnection::take_post_handshake_events`].
+    ///
+    /// The default is false.
+    pub report_post_handshake_events: bool,
+
+    /// The largest handshake message body we accept from the server, in
+    /// bytes, or `None` for any size TLS can encode (up to 16MiB).
+    ///
+    /// A handshake message is buffered until it is complete, so this
+    /// bounds the memory a server can make us use.  A message exceeding
+    /// it aborts the handshake with [`Error::HandshakeMessageTooLarge`]
+    /// as soon as its header arrives.
+    ///
+    /// The default is 65535 bytes.
+    pub max_handshake_message_size: Option<usize>,
+
+    /// Where the time at which the server's certificates are verified
+    /// comes from.
+    ///
+    /// This time is also what a custom [`ServerCertVerifierAsync`] is
+    /// given.
+    ///
+    /// The default is [`SystemTimeProvider`], the system clock.
+    ///
+    /// [`SystemTimeProvider`]: crate::client::SystemTimeProvider
+    pub time_provider: Arc<dyn TimeProvider>,
+
+    /// Whether to send the dummy ChangeCipherSpec message of TLS1.3's
+    /// middlebox compatibility mode (RFC 8446 appendix D.4) in our
+    /// handshake flight.
+    ///
+    /// Turning this off saves a record per handshake where no middlebox
+    /// inspects the connection.  A dummy ChangeCipherSpec from the
+    /// server is accepted either way.
+    ///
+    /// The default is true.
+    pub send_tls13_compat_ccs: bool,
+
+    /// Where the random material of the handshake, such as the session
+    /// ID and GREASE values, comes from.
+    ///
+    /// The client random and key shares come from the backend, so a
+    /// reproducible ClientHello also needs a backend which makes those
+    /// reproducible.
+    ///
+    /// The default is [`SystemRandomProvider`], the system's random
+    /// number generator.
+    ///
+    /// [`SystemRandomProvider`]: crate::SystemRandomProvider
+    pub rng_provider: Arc<dyn SecureRandomProvider>,
+
+    /// The signature schemes offered in the `signature_algorithms`
+    /// extension, in preference order.
+    ///
+    /// Schemes the verifier does not support are never offered.  This is
+    /// usually set with [`ConfigBuilder::with_signature_schemes`].
+    ///
+    /// The default is `None`, offering every scheme the verifier supports.
+    ///
+    /// [`ConfigBuilder::with_signature_schemes`]: crate::ConfigBuilder::with_signature_schemes
+    pub signature_schemes: Option<Vec<SignatureScheme>>,
+
+    /// Called with the metadata of every record read or written.
+    ///
+    /// The default is `None`.
+    pub record_observer: Option<Arc<dyn RecordObserver>>,
+
+    /// The client certificate types we offer in the RFC 7250
+    /// `client_certificate_type` extension, in preference order.
+    ///
+    /// If the server chooses [`CertificateType::RawPublicKey`], the
+    /// resolved client credential is sent as its
+    /// [`CertifiedKey::raw_public_key`](crate::sign::CertifiedKey::raw_public_key).
+    /// A server which does not support the extension ignores it, and an
+    /// X.509 chain is sent as usual.
+    ///
+    /// The default is empty, which sends no extension.
+    pub client_cert_type_preference: Vec<CertificateType>,
+
+    /// Whether a transport EOF without a preceding `close_notify` alert
+    /// is treated like a clean close.
+    ///
+    /// Some servers, notably HTTP/1.0-style ones which delimit the
+    /// response by closing the connection, never send `close_notify`.
+    /// When this is set, [`Reader`](crate::Reader) returns `Ok(0)` after
+    /// the buffered plaintext has been drained, instead of
+    /// `io::ErrorKind::UnexpectedEof`.  Such a close is indistinguishable
+    /// from a truncation attack, so only set this if the application
+    /// protocol detects truncation itself.  [`IoState::peer_has_closed`]
+    /// still only reports a received `close_notify`.
+    ///
+    /// The default is false.
+    ///
+    /// [`IoState::peer_has_closed`]: crate::IoState::peer_has_closed
+    pub allow_unclean_eof: bool,
+
+    /// Whether to send a GREASE `encrypted_client_hello` extension
+    /// (draft-ietf-tls-esni), as clients supporting ECH do when they have
+    /// no ECH configuration for the server.
+    ///
+    /// The extension is well-formed but random, so a server cannot
+    /// decrypt it and proceeds with the outer ClientHello as usual.
+    /// Sending it keeps our ClientHello from standing out from those of
+    /// clients which use ECH.  Real ECH is not supported.
+    ///
+    /// The default is false.
+    pub enable_ech_grease: bool,
+
+    /// The largest record we are willing to receive, to send to the
+    /// server in the `record_size_limit` extension (RFC 8449).  This
+    /// must be at least 64.
+    ///
+    /// If the server answers with its own limit, our records are limited
+    /// to that size.  See [`ClientConnection::negotiated_record_size_limit`].
+    /// When [`ClientConfig::max_fragment_length`] is also set, a server
+    /// supporting both uses this, and one supporting only
+    /// `max_fragment_length` uses that.
+    ///
+    /// The default is `None`.
+    pub record_size_limit: Option<u16>,
+
+    /// Whether TLS1.2 connections must use the extended master secret
+    /// of RFC 7627, which protects against triple handshake attacks.
+    ///
+    /// If true, the `extended_master_secret` extension is sent, and the
+    /// handshake fails with [`Error::PeerIncompatibleError`] if the
+    /// server does not agree to it.  The backend is then given the
+    /// session hash through `set_hs_hash_client_key_exchange`, and must
+    /// derive the extended master secret from it, as
+    /// [`RustCryptoBackend`](crate::RustCryptoBackend) does.
+    ///
+    /// The default is false.
+    pub require_ems: bool,
+}
+
+/// A handshake message the server sent after the main handshake.
+///
+/// See [`ClientConfig::report_post_handshake_events`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PostHandshakeEvent {
+    /// The server issued a session ticket, with a lifetime of
+    /// `lifetime` seconds.
+    ///
+    /// A TLS1.2 server issues its ticket just before its Finished message
+    /// rather than after the handshake; that ticket is reported too.
+    NewSessionTicket {
+        /// The ticket lifetime, or in TLS1.2 the lifetime hint.
+        lifetime: u32,
+    },
+}
+
+/// Which paths through a server's certificate chain are tried.
+///
+/// See [`ClientConfig::path_building`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathPolicy {
+    /// Only the first path to a trust anchor is tried, and if it does
+    /// not verify the certificate is rejected.
+    FirstPathOnly,
+    /// Each path to a trust anchor is tried in turn, until one verifies,
+    /// as browsers do.
+    TryAlternates,
 }
 
 impl ClientConfig {
@@ -159,6 +716,13 @@ impl ClientConfig {
         }
     }
 
+    /// Access configuration options whose use is dangerous and requires
+    /// extra care.
+    #[cfg(feature = "dangerous_configuration")]
+    pub fn dangerous(&mut self) -> DangerousClientConfig<'_> {
+        DangerousClientConfig { cfg: self }
+    }
+
     #[doc(hidden)]
     /// We support a given TLS version if it's quoted in the configured
     /// versions *and* at least one ciphersuite for this version is
@@ -171,6 +735,20 @@ impl ClientConfig {
                 .any(|cs| cs.version().version == v)
     }
 
+    /// Returns the protocol versions this configuration will offer,
+    /// most preferred first.
+    ///
+    /// A version is only offered if it was selected when building the
+    /// configuration, is compiled in (TLS1.2 requires the `tls12`
+    /// feature), and at least one configured cipher suite can be used
+    /// with it.
+    pub fn enabled_protocol_versions(&self) -> Vec<ProtocolVersion> {
+        [ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2]
+            .into_iter()
+            .filter(|&v| self.supports_version(v))
+            .collect()
+    }
+
     pub(super) fn find_cipher_suite(&self, suite: CipherSuite) -> Option<SupportedCipherSuite> {
         self.cipher_suites
             .iter()
@@ -179,6 +757,24 @@ impl ClientConfig {
     }
 }
 
+/// Accessor for dangerous configuration options.
+#[cfg(feature = "dangerous_configuration")]
+pub struct DangerousClientConfig<'a> {
+    /// The underlying ClientConfig
+    pub cfg: &'a mut ClientConfig,
+}
+
+#[cfg(feature = "dangerous_configuration")]
+impl<'a> DangerousClientConfig<'a> {
+    /// Overrides how the server certificate chain is verified.
+    ///
+    /// Handshake signatures are still checked by the verifier chosen
+    /// when the config was built.
+    pub fn set_certificate_verifier(&mut self, verifier: Arc<dyn ServerCertVerifierAsync>) {
+        self.cfg.async_verifier = Some(verifier);
+    }
+}
+
 #[derive(Debug, PartialEq)]
 enum EarlyDataState {
     Disabled,
@@ -262,7 +858,6 @@ impl EarlyData {
 }
 
 pub struct Initialized {
-    server_name: ServerName,
     extra_exts: Vec<ClientExtension>,
     proto: Protocol,
     config: Arc<ClientConfig>,
@@ -274,7 +869,9 @@ impl State<ClientConnectionData> for Initialized {
         self: Box<Self>,
         cx: &mut crate::conn::Context<'_>,
     ) -> Result<Box<dyn State<ClientConnectionData>>, Error> {
-        hs::start_handshake(self.server_name, self.extra_exts, self.config, cx).await
+        cx.data.started = true;
+        let server_name = cx.data.server_name.clone();
+        hs::start_handshake(server_name, self.extra_exts, self.config, cx).await
     }
 
     async fn handle(
@@ -316,12 +913,24 @@ impl ClientConnection {
         extra_exts: Vec<ClientExtension>,
         proto: Protocol,
     ) -> Result<Self, Error> {
+        if matches!(config.record_size_limit, Some(limit) if limit < hs::MIN_RECORD_SIZE_LIMIT) {
+            return Err(Error::General("record_size_limit is below 64".into()));
+        }
         let mut common_state = CommonState::new(config.max_fragment_size, Side::Client, backend)?;
         common_state.protocol = proto;
-        let data = ClientConnectionData::new();
+        common_state.strict_post_handshake = config.strict_post_handshake;
+        common_state.strict_record_parsing = config.strict_record_parsing;
+        common_state.coalesce_writes = config.coalesce_writes;
+        common_state.max_handshake_fragments = config.max_handshake_fragments;
+        common_state.max_handshake_message_size = config.max_handshake_message_size;
+        common_state.record_observer = config.record_observer.clone();
+        common_state.allow_unclean_eof = config.allow_unclean_eof;
+        let mut data = ClientConnectionData::new(name);
+        if config.report_post_handshake_events {
+            data.post_handshake_events = Some(Vec::new());
+        }
 
         let state = Box::new(Initialized {
-            server_name: name,
             extra_exts,
             proto,
             config,
@@ -333,14 +942,314 @@ impl ClientConnection {
 
     /// Returns True if the server signalled it will process early data.
     ///
-    /// If you sent early data and this returns false at the end of the
-    /// handshake then the server will not process the data.  This
-    /// is not an error, but you may wish to resend the data.
+    /// This client does not resume sessions, so it never offers early
+    /// data and this is always false.
     pub fn is_early_data_accepted(&self) -> bool {
-        false
+        self.inner.core.data.early_data.is_accepted()
+    }
+
+    /// Returns the encoding of the ClientHello handshake message most
+    /// recently sent, including its four byte handshake header but not
+    /// the record header.
+    ///
+    /// This is available as soon as [`ConnectionCommon::start`] has
+    /// produced the ClientHello, before it is taken by
+    /// [`CommonState::write_tls`], so callers can check it fits their
+    /// transport's constraints.  After a HelloRetryRequest, this is the
+    /// second ClientHello.
+    ///
+    /// [`ConnectionCommon::start`]: crate::ConnectionCommon::start
+    /// [`CommonState::write_tls`]: crate::CommonState::write_tls
+    pub fn client_hello_bytes(&self) -> Option<&[u8]> {
+        self.inner.core.data.client_hello.as_deref()
+    }
+
+    /// Returns the encodings of the handshake messages sent and received
+    /// so far, concatenated in the order they were sent or received.
+    ///
+    /// Each message is included with its four byte handshake header but
+    /// not its record header.  Messages after the handshake completes,
+    /// such as TLS1.3 session tickets, are not included, so once
+    /// [`CommonState::is_handshaking`] returns false this no longer
+    /// changes.  Returns `None` before the ClientHello is sent.
+    pub fn handshake_transcript(&self) -> Option<&[u8]> {
+        Some(self.inner.handshake_transcript.as_slice()).filter(|t| !t.is_empty())
+    }
+
+    /// Returns the hash of [`ClientConnection::handshake_transcript`],
+    /// using the hash function of the negotiated cipher suite.
+    ///
+    /// Returns `None` until the cipher suite has been negotiated.
+    pub fn transcript_hash(&self) -> Option<Vec<u8>> {
+        let suite = self.inner.suite?;
+        let hash = ring::digest::digest(
+            crate::hash_hs::map_algorithm(suite.hash_algorithm()),
+            &self.inner.handshake_transcript,
+        );
+        Some(hash.as_ref().to_vec())
+    }
+
+    /// Returns the name of the server this connection was made for.
+    ///
+    /// Use [`ServerName::sni_hostname`] for its string form.
+    pub fn server_name(&self) -> &ServerName {
+        &self.inner.core.data.server_name
+    }
+
+    /// Replaces the name of the server this connection is for, which is
+    /// sent in SNI and used to verify the server's certificate.
+    ///
+    /// This lets connections to different hosts share one config.  It
+    /// fails with [`Error::General`] once [`ConnectionCommon::start`] has
+    /// been called.
+    ///
+    /// [`ConnectionCommon::start`]: crate::ConnectionCommon::start
+    pub fn set_server_name(&mut self, name: ServerName) -> Result<(), Error> {
+        if self.inner.core.data.started {
+            return Err(Error::General(
+                "server name cannot be changed after start".to_string(),
+            ));
+        }
+
+        self.inner.core.data.server_name = name;
+        Ok(())
+    }
+
+    /// Generates the ephemeral key exchange key pair now, rather than
+    /// while the handshake is in progress.
+    ///
+    /// The backend generates one key pair per connection, for its own
+    /// group, and doing so ahead of demand (for example while a pooled
+    /// connection is idle) takes it off the handshake's critical path.
+    /// Backends that cannot do this ignore it.  It fails with
+    /// [`Error::General`] once [`ConnectionCommon::start`] has been
+    /// called.
+    ///
+    /// [`ConnectionCommon::start`]: crate::ConnectionCommon::start
+    pub async fn precompute_key_shares(&mut self) -> Result<(), Error> {
+        if self.inner.core.data.started {
+            return Err(Error::General(
+                "key shares cannot be precomputed after start".to_string(),
+            ));
+        }
+
+        self.inner.backend.prepare_client_key_share().await?;
+        Ok(())
+    }
+
+    /// Refuses to complete the handshake using a protocol version lower
+    /// than `version`.
+    ///
+    /// This narrows [`ClientConfig::enabled_protocol_versions`] for this
+    /// connection only.
+    /// If the server selects a lower version, the handshake is aborted
+    /// with [`Error::UnsupportedProtocolVersion`].  Call this before
+    /// [`ConnectionCommon::start`].
+    ///
+    /// [`ConnectionCommon::start`]: crate::ConnectionCommon::start
+    pub fn require_min_version(&mut self, version: ProtocolVersion) {
+        self.inner.core.data.min_version = Some(version);
+    }
+
+    /// Returns how many certificates from the server's chain were passed
+    /// to the certificate verifier, including the end-entity certificate,
+    /// across every path tried (see [`ClientConfig::path_building`]).
+    ///
+    /// This counts certificates handed to the verifier, not signature
+    /// verifications: the verifier may check fewer signatures than this
+    /// while building a path.  Certificates in the chain that cannot be
+    /// on a path from the end-entity certificate to a trust anchor are
+    /// not passed to it, and neither are those on paths after the first
+    /// that verifies.  A [`ServerCertVerifierAsync`] is given the whole
+    /// chain.
+    ///
+    /// This is zero until the server's certificate has been verified.
+    pub fn certificates_passed_to_verifier(&self) -> usize {
+        self.inner.core.data.certificates_passed_to_verifier
+    }
+
+    /// Returns the subject name of the trust anchor the server's
+    /// certificate was verified against, DER-encoded.
+    ///
+    /// This is `None` until the server's certificate has been verified,
+    /// or if the verifier does not report its trust anchors.
+    pub fn trust_anchor_used(&self) -> Option<Vec<u8>> {
+        self.inner.core.data.trust_anchor_used.clone()
+    }
+
+    /// Returns the DER-encoded OCSP response the server stapled to its
+    /// certificate, as given to the certificate verifier.
+    ///
+    /// This is `None` if the server sent no response, or if
+    /// [`ClientConfig::request_ocsp_stapling`] is false.
+    pub fn peer_stapled_ocsp_response(&self) -> Option<&[u8]> {
+        self.inner.core.data.stapled_ocsp_response.as_deref()
+    }
+
+    /// Returns the Signed Certificate Timestamps the server sent in its
+    /// `signed_certificate_timestamp` extension, one entry per SCT.
+    ///
+    /// These are recorded before the certificate is verified, whichever
+    /// verifier is used and whether or not it checks them.  This is
+    /// `None` until the server's certificate has been received, or if
+    /// the server sent no SCTs.
+    pub fn peer_signed_cert_timestamps(&self) -> Option<&[PayloadU16]> {
+        self.inner
+            .core
+            .data
+            .signed_cert_timestamps
+            .as_ref()
+            .map(|scts| scts.as_slice())
+    }
+
+    /// Returns the key exchange group used by the handshake.
+    ///
+    /// In TLS1.3 this is the group of the final key share, so after a
+    /// HelloRetryRequest it is the group the server asked for.
+    ///
+    /// This returns `None` until the handshake completes.
+    pub fn negotiated_key_exchange_group(&self) -> Option<&'static SupportedKxGroup> {
+        if self.is_handshaking() {
+            return None;
+        }
+        self.inner.core.data.negotiated_kx_group
+    }
+
+    /// Takes the events queued since the last call, oldest first.
+    ///
+    /// This is always empty unless
+    /// [`ClientConfig::report_post_handshake_events`] is set.
+    pub fn take_post_handshake_events(&mut self) -> Vec<PostHandshakeEvent> {
+        self.inner
+            .core
+            .data
+            .post_handshake_events
+            .as_mut()
+            .map(mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Returns the cipher suites offered in the ClientHello most recently
+    /// sent, in the order they were sent.
+    ///
+    /// This includes any GREASE value and the renegotiation SCSV, so is
+    /// exactly what the server saw.  It is empty until
+    /// [`ConnectionCommon::start`] has produced the ClientHello.
+    ///
+    /// [`ConnectionCommon::start`]: crate::ConnectionCommon::start
+    pub fn offered_cipher_suites(&self) -> &[CipherSuite] {
+        &self.inner.core.data.offered_cipher_suites
+    }
+
+    /// Returns the cipher suite chosen by the server, as soon as its
+    /// ServerHello has been processed.
+    ///
+    /// This is for clients that act on data the server sends before the
+    /// handshake completes.  It is `None` until the ServerHello arrives,
+    /// and the server's Finished may not have been checked yet, so the
+    /// choice is not authenticated while [`is_handshaking`] is true.
+    ///
+    /// [`is_handshaking`]: crate::CommonState::is_handshaking
+    pub fn early_negotiated_cipher_suite(&self) -> Option<SupportedCipherSuite> {
+        self.inner.suite
+    }
+
+    /// Returns the maximum fragment length (RFC 6066) agreed with the
+    /// server, in bytes.
+    ///
+    /// This is `None` until the server has acknowledged the length
+    /// requested by [`ClientConfig::max_fragment_length`], or if no
+    /// length was requested.
+    pub fn negotiated_max_fragment_length(&self) -> Option<u16> {
+        self.inner.core.data.negotiated_max_fragment_length
+    }
+
+    /// Returns the record size limit (RFC 8449) the server sent, in bytes.
+    ///
+    /// This is `None` until the server has answered the extension sent
+    /// for [`ClientConfig::record_size_limit`], or if none was sent.
+    pub fn negotiated_record_size_limit(&self) -> Option<u16> {
+        self.inner.core.data.negotiated_record_size_limit
+    }
+
+    /// Packages this connection's handshake transcript, the server's
+    /// certificate chain, the negotiated parameters and the boundaries of
+    /// all records so far into a [`NotaryBundle`].
+    ///
+    /// Records exchanged after this call are not included, so call it
+    /// once the connection is finished with.  Fails with
+    /// [`Error::HandshakeNotComplete`] while the handshake is in progress.
+    #[cfg(feature = "notary")]
+    pub fn export_notary_bundle(&self) -> Result<NotaryBundle, Error> {
+        let (version, suite) = match (self.protocol_version(), self.negotiated_cipher_suite()) {
+            (Some(version), Some(suite)) if !self.is_handshaking() => (version, suite),
+            _ => return Err(Error::HandshakeNotComplete),
+        };
+
+        Ok(NotaryBundle {
+            version,
+            cipher_suite: suite.suite(),
+            alpn_protocol: self.alpn_protocol().map(|p| p.to_vec()),
+            certificates: self
+                .peer_certificates()
+                .map(|certs| certs.to_vec())
+                .unwrap_or_default(),
+            handshake_transcript: self.handshake_transcript.clone(),
+            records: self.notary_log.records.clone(),
+        })
+    }
+
+    /// Consumes the connection, returning its traffic keys.
+    ///
+    /// **This defeats the confidentiality and integrity of the session.**
+    /// Anyone holding the result can decrypt everything sent on the
+    /// connection and forge records in either direction.  It exists for
+    /// protocols, such as MPC-based ones, which take over the session
+    /// from here.
+    ///
+    /// Write out everything the connection has queued first: unsent
+    /// records are discarded, and the returned sequence numbers follow
+    /// the records already encrypted.
+    ///
+    /// Fails with [`Error::HandshakeNotComplete`] while the handshake is
+    /// in progress, and with [`Error::BackendError`] if the backend does
+    /// not hold the keys, as MPC backends do not.
+    #[cfg(feature = "dangerous_extract_secrets")]
+    pub async fn extract_secrets(mut self) -> Result<ExtractedSecrets, Error> {
+        if self.is_handshaking() {
+            return Err(Error::HandshakeNotComplete);
+        }
+        let suite = self.inner.suite.ok_or(Error::HandshakeNotComplete)?;
+        let secrets = self.inner.backend.extract_secrets().await?;
+
+        Ok(ExtractedSecrets { suite, secrets })
+    }
+
+    /// Returns the verify_data of the server's Finished message.
+    ///
+    /// This is available once the client has received the server's
+    /// Finished message and checked it against the handshake transcript,
+    /// and is `None` before then.
+    #[cfg(feature = "dangerous_configuration")]
+    pub fn server_finished_verify_data(&self) -> Option<Vec<u8>> {
+        self.inner.core.data.server_finished_verify_data.clone()
     }
 }
 
+/// The traffic keys of a connection, returned by
+/// [`ClientConnection::extract_secrets`].
+///
+/// These keys decrypt and forge every application data record of the
+/// session in both directions.  Handle them accordingly.
+#[cfg(feature = "dangerous_extract_secrets")]
+#[derive(Debug)]
+pub struct ExtractedSecrets {
+    /// The negotiated cipher suite, which says how the keys are used.
+    pub suite: SupportedCipherSuite,
+    /// The keys and next sequence numbers of each direction.
+    pub secrets: crate::TrafficSecrets,
+}
+
 impl Deref for ClientConnection {
     type Target = ConnectionCommon;
 
@@ -359,13 +1268,74 @@ impl DerefMut for ClientConnection {
 pub struct ClientConnectionData {
     pub(super) early_data: EarlyData,
     pub(super) resumption_ciphersuite: Option<SupportedCipherSuite>,
+    /// The maximum fragment length the server agreed to, in bytes.
+    pub(super) negotiated_max_fragment_length: Option<u16>,
+    /// The record size limit the server sent.
+    pub(super) negotiated_record_size_limit: Option<u16>,
+    /// The encoding of the last ClientHello we sent.
+    pub(super) client_hello: Option<Vec<u8>>,
+    /// The cipher suites in the last ClientHello we sent.
+    pub(super) offered_cipher_suites: Vec<CipherSuite>,
+    /// The name of the server we are connecting to.
+    pub(super) server_name: ServerName,
+    /// Whether the handshake has been started.
+    pub(super) started: bool,
+    /// The lowest protocol version this connection will accept.
+    pub(super) min_version: Option<ProtocolVersion>,
+    /// The verify_data of the server's Finished message, once verified.
+    pub(super) server_finished_verify_data: Option<Vec<u8>>,
+    /// How many certificates were passed to the verifier.
+    pub(super) certificates_passed_to_verifier: usize,
+    /// The subject of the trust anchor the server's certificate chains to.
+    pub(super) trust_anchor_used: Option<Vec<u8>>,
+    /// Events awaiting [`ClientConnection::take_post_handshake_events`],
+    /// or `None` if they are not reported.
+    pub(super) post_handshake_events: Option<Vec<PostHandshakeEvent>>,
+    /// The key exchange group used, once the server's key share is
+    /// accepted.
+    pub(super) negotiated_kx_group: Option<&'static SupportedKxGroup>,
+    /// The client certificate type the server chose, if it answered our
+    /// `client_certificate_type` extension.
+    pub(super) client_cert_type: Option<CertificateType>,
+    /// The OCSP response the server stapled to its certificate, if any.
+    pub(super) stapled_ocsp_response: Option<Vec<u8>>,
+    /// The SCTs the server sent for its certificate, if any.
+    pub(super) signed_cert_timestamps: Option<SCTList>,
 }
 
 impl ClientConnectionData {
-    fn new() -> Self {
+    fn new(server_name: ServerName) -> Self {
         Self {
             early_data: EarlyData::new(),
             resumption_ciphersuite: None,
+            negotiated_max_fragment_length: None,
+            negotiated_record_size_limit: None,
+            client_hello: None,
+            offered_cipher_suites: Vec::new(),
+            server_name,
+            started: false,
+            min_version: None,
+            server_finished_verify_data: None,
+            certificates_passed_to_verifier: 0,
+            trust_anchor_used: None,
+            post_handshake_events: None,
+            negotiated_kx_group: None,
+            client_cert_type: None,
+            stapled_ocsp_response: None,
+            signed_cert_timestamps: None,
+        }
+    }
+
+    /// Returns data for a connection to the same server which has not
+    /// started, to stand in for this while it is lent out.
+    pub(crate) fn placeholder(&self) -> Self {
+        Self::new(self.server_name.clone())
+    }
+
+    /// Queues `event` if post-handshake events are reported.
+    pub(super) fn report_post_handshake_event(&mut self, event: PostHandshakeEvent) {
+        if let Some(events) = &mut self.post_handshake_events {
+            events.push(event);
         }
     }
 }
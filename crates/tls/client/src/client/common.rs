@@ -1,22 +1,33 @@
-use super::ResolvesClientCert;
+use super::ResolvesClientCertAsync;
 #[cfg(feature = "logging")]
 use crate::log::{debug, trace};
 use crate::{sign, DistinguishedNames, SignatureScheme};
 use std::sync::Arc;
 pub use tls_core::cert::ServerCertDetails;
 use tls_core::msgs::{
-    enums::ExtensionType,
+    enums::{ExtensionType, ProtocolVersion},
     handshake::{CertificatePayload, SCTList, ServerExtension},
 };
 
 pub(super) struct ClientHelloDetails {
     pub(super) sent_extensions: Vec<ExtensionType>,
+    /// The versions offered in our hellos' `supported_versions`
+    /// extension, if any.
+    pub(super) offered_versions: Vec<ProtocolVersion>,
+    /// The GREASE value used in our hellos, if any.
+    pub(super) grease: Option<u16>,
+    /// The GREASE `encrypted_client_hello` extension body sent in our
+    /// hellos, if any.
+    pub(super) ech_grease: Option<Vec<u8>>,
 }
 
 impl ClientHelloDetails {
     pub(super) fn new() -> Self {
         Self {
             sent_extensions: Vec::new(),
+            offered_versions: Vec::new(),
+            grease: None,
+            ech_grease: None,
         }
     }
 
@@ -54,8 +65,8 @@ pub(super) enum ClientAuthDetails {
 }
 
 impl ClientAuthDetails {
-    pub(super) fn resolve(
-        resolver: &dyn ResolvesClientCert,
+    pub(super) async fn resolve(
+        resolver: &dyn ResolvesClientCertAsync,
         canames: Option<&DistinguishedNames>,
         sigschemes: &[SignatureScheme],
         auth_context_tls13: Option<Vec<u8>>,
@@ -67,7 +78,7 @@ impl ClientAuthDetails {
             .map(|p| p.0.as_slice())
             .collect::<Vec<&[u8]>>();
 
-        if let Some(certkey) = resolver.resolve(&acceptable_issuers, sigschemes) {
+        if let Some(certkey) = resolver.resolve(&acceptable_issuers, sigschemes).await {
             if let Some(signer) = certkey.key.choose_scheme(sigschemes) {
                 debug!("Attempting client auth");
                 return Self::Verify {
@@ -1,6 +1,7 @@
 use crate::{client, error::Error, limited_cache, sign};
 use std::sync::{Arc, Mutex};
 use tls_core::{key, msgs::enums::SignatureScheme};
+use web_time::SystemTime;
 
 /// An implementer of `StoresClientSessions` which does nothing.
 pub struct NoClientSessionStorage {}
@@ -87,6 +88,15 @@ impl client::ResolvesClientCert for AlwaysResolvesClientCert {
     }
 }
 
+/// A [`client::TimeProvider`] which reads the system clock.
+pub struct SystemTimeProvider;
+
+impl client::TimeProvider for SystemTimeProvider {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -6,34 +6,41 @@ use crate::{
     check::inappropriate_handshake_message,
     conn::{CommonState, ConnectionRandoms, State},
     error::Error,
+    fingerprint,
     hash_hs::HandshakeHashBuffer,
     msgs::persist,
     ticketer::TimeBase,
+    verify,
 };
 use tls_core::{
-    key::PublicKey,
+    key::{Certificate, PublicKey},
     msgs::{
         base::Payload,
         codec::{Codec, Reader},
         enums::{
-            AlertDescription, CipherSuite, Compression, ContentType, ECPointFormat, ExtensionType,
-            HandshakeType, PSKKeyExchangeMode, ProtocolVersion,
+            AlertDescription, CertificateType, CipherSuite, Compression, ContentType,
+            ECPointFormat, ExtensionType, HandshakeType, MaxFragmentLength, NamedGroup,
+            PSKKeyExchangeMode, ProtocolVersion, SignatureScheme,
         },
+        fragmenter::{MAX_FRAGMENT_LEN, PACKET_OVERHEAD},
         handshake::{
             CertificateStatusRequest, ClientExtension, ClientHelloPayload, ClientSessionTicket,
             ConvertProtocolNameList, ECPointFormatList, HandshakeMessagePayload, HandshakePayload,
             HasServerExtensions, HelloRetryRequest, ProtocolNameList, Random, SCTList, SessionID,
-            SupportedPointFormats,
+            SupportedPointFormats, UnknownExtension,
         },
         message::{Message, MessagePayload},
     },
     suites::SupportedCipherSuite,
+    x509,
 };
 
 #[cfg(feature = "tls12")]
 use super::tls12;
 use crate::client::{
-    client_conn::ClientConnectionData, common::ClientHelloDetails, tls13, ClientConfig, ServerName,
+    client_conn::ClientConnectionData,
+    common::{ClientHelloDetails, ServerCertDetails},
+    tls13, ClientConfig, PathPolicy, ServerName,
 };
 use async_trait::async_trait;
 use std::sync::Arc;
@@ -42,14 +49,17 @@ pub(super) type NextState = Box<dyn State<ClientConnectionData>>;
 pub(super) type NextStateOrError = Result<NextState, Error>;
 pub(super) type ClientContext<'a> = crate::conn::Context<'a>;
 
-fn find_session(
+/// The smallest `record_size_limit` RFC 8449 allows.
+pub(super) const MIN_RECORD_SIZE_LIMIT: u16 = 64;
+
+async fn find_session(
     server_name: &ServerName,
     config: &ClientConfig,
 ) -> Option<persist::Retrieved<persist::ClientSessionValue>> {
     let key = persist::ClientSessionKey::session_for_server_name(server_name);
     let key_buf = key.get_encoding();
 
-    let value = config.session_storage.get(&key_buf).or_else(|| {
+    let value = config.session_storage.get(&key_buf).await.or_else(|| {
         debug!("No cached session for {:?}", server_name);
         None
     })?;
@@ -81,6 +91,9 @@ pub(super) async fn start_handshake(
     if config.client_auth_cert_resolver.has_certs() {
         transcript_buffer.set_client_auth_enabled();
     }
+    if let Some(observer) = &config.transcript_byte_observer {
+        transcript_buffer.set_observer(Arc::clone(observer));
+    }
 
     let mut session_id: Option<SessionID> = None;
 
@@ -93,7 +106,7 @@ pub(super) async fn start_handshake(
 
     // For now we do not support session resumption
     //
-    // let mut resuming_session = find_session(&server_name, &config);
+    // let mut resuming_session = find_session(&server_name, &config).await;
     // if let Some(_resuming) = &mut resuming_session {
     //     #[cfg(feature = "tls12")]
     //     if let persist::ClientSessionValue::Tls12(inner) = &mut _resuming.value {
@@ -113,12 +126,15 @@ pub(super) async fn start_handshake(
 
     // https://tools.ietf.org/html/rfc8446#appendix-D.4
     if session_id.is_none() {
-        session_id = Some(SessionID::random()?);
+        let mut data = [0u8; 32];
+        config.rng_provider.fill(&mut data)?;
+        session_id = Some(SessionID::from(data));
     }
 
     let random = cx.common.backend.get_client_random().await?;
     let hello_details = ClientHelloDetails::new();
-    let sent_tls13_fake_ccs = false;
+    // Treating the CCS as already sent stops it being sent at all.
+    let sent_tls13_fake_ccs = !config.send_tls13_compat_ccs;
     let may_send_sct_list = config.verifier.request_scts();
     emit_client_hello_for_retry(
         config,
@@ -198,7 +214,18 @@ async fn emit_client_hello_for_retry(
     let support_tls12 = config.supports_version(ProtocolVersion::TLSv1_2);
     let support_tls13 = config.supports_version(ProtocolVersion::TLSv1_3);
 
+    // Keep the same GREASE value across a HelloRetryRequest.
+    if config.enable_grease && hello.grease.is_none() {
+        let mut data = [0u8; 4];
+        config.rng_provider.fill(&mut data)?;
+        hello.grease = Some(fingerprint::grease_value(u32::from_be_bytes(data)));
+    }
+
     let mut supported_versions = Vec::new();
+    if let Some(grease) = hello.grease {
+        supported_versions.push(ProtocolVersion::Unknown(grease));
+    }
+
     if support_tls13 {
         supported_versions.push(ProtocolVersion::TLSv1_3);
     }
@@ -208,21 +235,49 @@ async fn emit_client_hello_for_retry(
     }
 
     // should be unreachable thanks to config builder
-    assert!(!supported_versions.is_empty());
+    assert!(support_tls12 || support_tls13);
+
+    let mut named_groups: Vec<_> = config.kx_groups.iter().map(|skxg| skxg.name).collect();
+    if let Some(grease) = hello.grease {
+        named_groups.insert(0, NamedGroup::Unknown(grease));
+    }
 
     let mut exts = vec![
         ClientExtension::SupportedVersions(supported_versions),
         ClientExtension::ECPointFormats(ECPointFormatList::supported()),
-        ClientExtension::NamedGroups(config.kx_groups.iter().map(|skxg| skxg.name).collect()),
-        ClientExtension::SignatureAlgorithms(config.verifier.supported_verify_schemes()),
+        ClientExtension::NamedGroups(named_groups),
+        ClientExtension::SignatureAlgorithms(offered_signature_schemes(&config)),
         //ClientExtension::ExtendedMasterSecretRequest,
-        ClientExtension::CertificateStatusRequest(CertificateStatusRequest::build_ocsp()),
     ];
 
+    if config.require_ems {
+        exts.push(ClientExtension::ExtendedMasterSecretRequest);
+    }
+
+    if config.request_ocsp_stapling {
+        exts.push(ClientExtension::CertificateStatusRequest(
+            CertificateStatusRequest::build_ocsp(),
+        ));
+    }
+
     if let (Some(sni_name), true) = (server_name.for_sni(), config.enable_sni) {
         exts.push(ClientExtension::make_sni(sni_name));
     }
 
+    if let Some(mfl) = config.max_fragment_length {
+        exts.push(ClientExtension::MaxFragmentLength(mfl));
+    }
+
+    if let Some(limit) = config.record_size_limit {
+        exts.push(ClientExtension::RecordSizeLimit(limit));
+    }
+
+    if !config.client_cert_type_preference.is_empty() {
+        exts.push(ClientExtension::ClientCertificateTypes(
+            config.client_cert_type_preference.clone(),
+        ));
+    }
+
     if may_send_sct_list {
         exts.push(ClientExtension::SignedCertificateTimestampRequest);
     }
@@ -253,9 +308,31 @@ async fn emit_client_hello_for_retry(
         )));
     }
 
+    // Keep the same GREASE ECH extension across a HelloRetryRequest.
+    if config.enable_ech_grease && hello.ech_grease.is_none() {
+        hello.ech_grease = Some(fingerprint::grease_ech(config.rng_provider.as_ref())?);
+    }
+    if let Some(ech) = &hello.ech_grease {
+        exts.push(ClientExtension::Unknown(UnknownExtension {
+            typ: ExtensionType::EncryptedClientHello,
+            payload: Payload::new(ech.clone()),
+        }));
+    }
+
     // Extra extensions must be placed before the PSK extension
     exts.extend(extra_exts.iter().cloned());
 
+    fingerprint::order_extensions(&mut exts, &config.extension_order);
+    if let Some(grease) = hello.grease {
+        exts.insert(
+            0,
+            ClientExtension::Unknown(UnknownExtension {
+                typ: ExtensionType::Unknown(grease),
+                payload: Payload::empty(),
+            }),
+        );
+    }
+
     // let fill_in_binder = if support_tls13
     //     && config.enable_tickets
     //     && resume_version == ProtocolVersion::TLSv1_3
@@ -298,25 +375,47 @@ async fn emit_client_hello_for_retry(
     //     None
     // };
 
-    // Note what extensions we sent.
-    hello.sent_extensions = exts.iter().map(ClientExtension::get_type).collect();
-
-    let session_id = session_id.unwrap_or_else(SessionID::empty);
     let mut cipher_suites: Vec<_> = config.cipher_suites.iter().map(|cs| cs.suite()).collect();
+    if let Some(grease) = hello.grease {
+        cipher_suites.insert(0, CipherSuite::Unknown(grease));
+    }
     // We don't do renegotiation at all, in fact.
     cipher_suites.push(CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV);
 
+    let mut client_hello = ClientHelloPayload {
+        client_version: ProtocolVersion::TLSv1_2,
+        random,
+        session_id: session_id.unwrap_or_else(SessionID::empty),
+        cipher_suites,
+        compression_methods: config.compression_methods.clone(),
+        extensions: exts,
+    };
+    if let Some(transform) = &config.client_hello_transform {
+        transform(&mut client_hello);
+    }
+
+    // Note what we sent.
+    hello.sent_extensions = client_hello
+        .extensions
+        .iter()
+        .map(ClientExtension::get_type)
+        .collect();
+    hello.offered_versions = client_hello
+        .get_versions_extension()
+        .cloned()
+        .unwrap_or_default();
+    cx.data.offered_cipher_suites = client_hello.cipher_suites.clone();
+    let session_id = client_hello.session_id;
+
     let chp = HandshakeMessagePayload {
         typ: HandshakeType::ClientHello,
-        payload: HandshakePayload::ClientHello(ClientHelloPayload {
-            client_version: ProtocolVersion::TLSv1_2,
-            random,
-            session_id,
-            cipher_suites,
-            compression_methods: vec![Compression::Null],
-            extensions: exts,
-        }),
+        payload: HandshakePayload::ClientHello(client_hello),
     };
+    let encoding = chp.get_encoding();
+    if let Some(inspector) = &config.client_hello_inspector {
+        inspector(&encoding);
+    }
+    cx.data.client_hello = Some(encoding);
 
     // let early_key_schedule = if let Some(resuming) = fill_in_binder {
     //     let schedule = tls13::fill_in_psk_binder(&resuming, &transcript_buffer, &mut chp);
@@ -377,16 +476,38 @@ pub(super) async fn process_alpn_protocol(
     config: &ClientConfig,
     proto: Option<&[u8]>,
 ) -> Result<(), Error> {
-    common.alpn_protocol = proto.map(ToOwned::to_owned);
+    // A protocol we did not offer is never reported as agreed.
+    if let Some(proto) = proto {
+        if !config.alpn_protocols.iter().any(|offered| offered == proto) {
+            return Err(common.illegal_param("ALPN protocol not offered").await?);
+        }
+    }
 
-    if let Some(alpn_protocol) = &common.alpn_protocol {
-        if !config.alpn_protocols.contains(alpn_protocol) {
+    // The server's own protocol list is never sent, so a choice other than
+    // our first preference may have passed over a protocol it supports.
+    if let (Some(proto), true) = (proto, config.alpn_strict_client_preference) {
+        if config.alpn_protocols.first().map(Vec::as_slice) != Some(proto) {
             return Err(common
-                .illegal_param("server sent non-offered ALPN protocol")
+                .illegal_param("ALPN protocol is not the client's preference")
                 .await?);
         }
     }
 
+    common.alpn_protocol = proto.map(ToOwned::to_owned);
+
+    if let Some(required) = &config.required_alpn {
+        let acceptable = match &common.alpn_protocol {
+            Some(alpn_protocol) => required.contains(alpn_protocol),
+            None => false,
+        };
+        if !acceptable {
+            common
+                .send_fatal_alert(AlertDescription::NoApplicationProtocol)
+                .await?;
+            return Err(Error::NoApplicationProtocol);
+        }
+    }
+
     debug!(
         "ALPN protocol is {:?}",
         common.alpn_protocol.as_ref().map(|v| bs_debug::BsDebug(v))
@@ -394,6 +515,250 @@ pub(super) async fn process_alpn_protocol(
     Ok(())
 }
 
+/// Checks the server's `max_fragment_length` echo against what we
+/// requested (RFC 6066, section 4), and limits our records to match.
+pub(super) async fn process_max_fragment_length(
+    cx: &mut ClientContext<'_>,
+    config: &ClientConfig,
+    echoed: Option<MaxFragmentLength>,
+) -> Result<(), Error> {
+    let echoed = match echoed {
+        Some(echoed) => echoed,
+        None => return Ok(()),
+    };
+
+    let len = match echoed.max_len() {
+        Some(len) if config.max_fragment_length == Some(echoed) => len,
+        _ => return Err(cx.common.illegal_param("MFL mismatch").await?),
+    };
+
+    let fragment_size = len as usize + PACKET_OVERHEAD;
+    if config
+        .max_fragment_size
+        .map_or(true, |size| size > fragment_size)
+    {
+        cx.common.set_max_fragment_size(Some(fragment_size))?;
+    }
+
+    debug!("Negotiated max_fragment_length {}", len);
+    cx.data.negotiated_max_fragment_length = Some(len);
+    Ok(())
+}
+
+/// Checks the server's `record_size_limit` (RFC 8449), and limits our
+/// records to match.
+///
+/// A server supporting the extension ignores `max_fragment_length`, so
+/// one answering both is misbehaving.
+pub(super) async fn process_record_size_limit(
+    cx: &mut ClientContext<'_>,
+    config: &ClientConfig,
+    limit: Option<u16>,
+    max_fragment_length_echoed: bool,
+) -> Result<(), Error> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+
+    // An unrequested limit was already refused as an unsolicited
+    // extension.
+    debug_assert!(config.record_size_limit.is_some());
+    if max_fragment_length_echoed {
+        return Err(cx
+            .common
+            .illegal_param("server sent both max_fragment_length and record_size_limit")
+            .await?);
+    }
+    if limit < MIN_RECORD_SIZE_LIMIT {
+        return Err(cx
+            .common
+            .illegal_param("record_size_limit too small")
+            .await?);
+    }
+
+    // In TLS1.3 the limit includes the inner content type.
+    let mut len = limit as usize;
+    if cx.common.is_tls13() {
+        len -= 1;
+    }
+    cx.common.record_size_limit = Some(len.min(MAX_FRAGMENT_LEN));
+
+    debug!("Negotiated record_size_limit {}", limit);
+    cx.data.negotiated_record_size_limit = Some(limit);
+    Ok(())
+}
+
+/// Checks the server's choice in its `client_certificate_type` extension
+/// (RFC 7250, section 4.2) is one we offered.
+pub(super) async fn process_client_cert_type(
+    cx: &mut ClientContext<'_>,
+    config: &ClientConfig,
+    chosen: Option<CertificateType>,
+) -> Result<(), Error> {
+    if let Some(chosen) = chosen {
+        if !config.client_cert_type_preference.contains(&chosen) {
+            return Err(cx
+                .common
+                .illegal_param("server chose unoffered client certificate type")
+                .await?);
+        }
+        debug!("Client certificate type is {:?}", chosen);
+    }
+
+    cx.data.client_cert_type = chosen;
+    Ok(())
+}
+
+/// Checks the server's certificate chain is in the order TLS requires,
+/// reordering it instead if `config` tolerates unordered chains.
+pub(super) async fn order_server_cert_chain(
+    common: &mut CommonState,
+    config: &ClientConfig,
+    chain: Vec<Certificate>,
+) -> Result<Vec<Certificate>, Error> {
+    if x509::is_ordered_chain(&chain) {
+        return Ok(chain);
+    }
+
+    if config.tolerate_unordered_chain {
+        debug!("Reordering server certificate chain");
+        return Ok(x509::reorder_chain(&chain));
+    }
+
+    common
+        .send_fatal_alert(AlertDescription::BadCertificate)
+        .await?;
+    Err(Error::CertificateChainOutOfOrder)
+}
+
+/// Verifies the server's certificate chain, trying the paths to a trust
+/// anchor it offers as `config.path_building` allows.
+///
+/// The verifier builds its own path through the intermediates it is
+/// given, but cannot say which trust anchor it reached, so it is given
+/// one candidate path at a time.  Certificates on no path are never
+/// given to it.  If the verifier does not report its trust anchors,
+/// it is given every certificate that could be on a path, once.
+pub(super) async fn verify_server_cert_chain(
+    cx: &mut ClientContext<'_>,
+    config: &ClientConfig,
+    server_name: &ServerName,
+    server_cert: &ServerCertDetails,
+) -> Result<verify::ServerCertVerified, Error> {
+    let (end_entity, intermediates) = server_cert
+        .cert_chain()
+        .split_first()
+        .ok_or(Error::NoCertificatesPresented)?;
+    cx.data.signed_cert_timestamps = server_cert.scts().cloned();
+
+    // A custom verifier builds its own paths from the chain as sent.
+    if let Some(verifier) = &config.async_verifier {
+        let scts: Vec<&[u8]> = server_cert
+            .scts()
+            .map(|sct| sct.as_slice())
+            .unwrap_or(&[])
+            .iter()
+            .map(|sct| sct.0.as_slice())
+            .collect();
+        let result = verifier
+            .verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                &scts,
+                server_cert.ocsp_response(),
+                config.time_provider.now(),
+            )
+            .await;
+        return match result {
+            Ok(cert_verified) => {
+                cx.data.certificates_passed_to_verifier = server_cert.cert_chain().len();
+                Ok(cert_verified)
+            }
+            Err(e) => Err(send_cert_error_alert(cx.common, Error::CoreError(e)).await?),
+        };
+    }
+
+    let anchor_subjects = config
+        .verifier
+        .trust_anchors()
+        .map(|roots| roots.subjects())
+        .unwrap_or_default();
+    let anchor_subjects: Vec<&[u8]> = anchor_subjects
+        .iter()
+        .map(|subject| subject.0.as_slice())
+        .collect();
+
+    let mut paths = x509::candidate_paths(end_entity, intermediates, &anchor_subjects);
+    if paths.is_empty() {
+        paths.push(x509::issuer_path(
+            end_entity,
+            intermediates,
+            &anchor_subjects,
+        ));
+    }
+    if config.path_building == PathPolicy::FirstPathOnly {
+        paths.truncate(1);
+    }
+
+    let now = config.time_provider.now();
+    let mut passed = vec![end_entity];
+    let mut first_error = None;
+    for path in &paths {
+        for cert in path {
+            if !passed.contains(&cert) {
+                passed.push(cert);
+            }
+        }
+
+        match config.verifier.verify_server_cert(
+            end_entity,
+            path,
+            server_name,
+            &mut server_cert
+                .scts()
+                .map(|sct| sct.as_slice())
+                .unwrap_or(&[])
+                .iter()
+                .map(|sct| sct.0.as_slice()),
+            server_cert.ocsp_response(),
+            now,
+        ) {
+            Ok(cert_verified) => {
+                let top = path.last().unwrap_or(end_entity);
+                cx.data.certificates_passed_to_verifier = passed.len();
+                cx.data.trust_anchor_used = x509::issuer_and_subject(&top.0)
+                    .map(|(issuer, _)| issuer)
+                    .filter(|issuer| anchor_subjects.contains(issuer))
+                    .map(|issuer| issuer.to_vec());
+                return Ok(cert_verified);
+            }
+            Err(e) => {
+                debug!("Server certificate path failed to verify: {:?}", e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    let err = first_error.expect("at least one path is always tried");
+    Err(send_cert_error_alert(cx.common, Error::CoreError(err)).await?)
+}
+
+/// The signature schemes to offer: those of `config.signature_schemes`
+/// that the verifier supports, or else everything the verifier supports.
+pub(super) fn offered_signature_schemes(config: &ClientConfig) -> Vec<SignatureScheme> {
+    let supported = config.verifier.supported_verify_schemes();
+    match &config.signature_schemes {
+        Some(preferred) => preferred
+            .iter()
+            .filter(|scheme| supported.contains(scheme))
+            .copied()
+            .collect(),
+        None => supported,
+    }
+}
+
 pub(super) fn sct_list_is_invalid(scts: &SCTList) -> bool {
     scts.is_empty() || scts.iter().any(|sct| sct.0.is_empty())
 }
@@ -411,6 +776,8 @@ impl State<ClientConnectionData> for ExpectServerHello {
 
         use crate::ProtocolVersion::{TLSv1_2, TLSv1_3};
         let tls13_supported = self.config.supports_version(TLSv1_3);
+        // A transform may offer versions the config does not support.
+        let tls13_offered = self.hello.offered_versions.contains(&TLSv1_3);
 
         let server_version = if server_hello.legacy_version == TLSv1_2 {
             server_hello
@@ -452,6 +819,15 @@ impl State<ClientConnectionData> for ExpectServerHello {
             }
         };
 
+        if let Some(min_version) = cx.data.min_version {
+            if version.get_u16() < min_version.get_u16() {
+                cx.common
+                    .send_fatal_alert(AlertDescription::ProtocolVersion)
+                    .await?;
+                return Err(Error::UnsupportedProtocolVersion);
+            }
+        }
+
         cx.common.backend.set_protocol_version(version).await?;
 
         if server_hello.compression_method != Compression::Null {
@@ -470,7 +846,11 @@ impl State<ClientConnectionData> for ExpectServerHello {
             ));
         }
 
-        let allowed_unsolicited = [ExtensionType::RenegotiationInfo];
+        let mut allowed_unsolicited = vec![ExtensionType::RenegotiationInfo];
+        if !self.config.reject_unsolicited_extensions {
+            // An unrequested OCSP staple is harmless: we ignore it.
+            allowed_unsolicited.push(ExtensionType::StatusRequest);
+        }
         if self
             .hello
             .server_sent_unsolicited_extensions(&server_hello.extensions, &allowed_unsolicited)
@@ -489,6 +869,17 @@ impl State<ClientConnectionData> for ExpectServerHello {
         if !cx.common.is_tls13() {
             process_alpn_protocol(cx.common, &self.config, server_hello.get_alpn_protocol())
                 .await?;
+            process_max_fragment_length(cx, &self.config, server_hello.get_max_fragment_length())
+                .await?;
+            process_record_size_limit(
+                cx,
+                &self.config,
+                server_hello.get_record_size_limit(),
+                server_hello.get_max_fragment_length().is_some(),
+            )
+            .await?;
+            process_client_cert_type(cx, &self.config, server_hello.get_client_certificate_type())
+                .await?;
         }
 
         // If ECPointFormats extension is supplied by the server, it must contain
@@ -504,14 +895,20 @@ impl State<ClientConnectionData> for ExpectServerHello {
             }
         }
 
+        // The suites we sent may be fewer than those configured, for
+        // instance after a ClientHello transform.
+        let offered = cx
+            .data
+            .offered_cipher_suites
+            .contains(&server_hello.cipher_suite);
         let suite = match self.config.find_cipher_suite(server_hello.cipher_suite) {
-            Some(suite) => suite,
-            None => {
+            Some(suite) if offered => suite,
+            _ => {
                 cx.common
                     .send_fatal_alert(AlertDescription::HandshakeFailure)
                     .await?;
                 return Err(Error::PeerMisbehavedError(
-                    "server chose non-offered ciphersuite".to_string(),
+                    "server chose unoffered cipher suite".to_string(),
                 ));
             }
         };
@@ -534,6 +931,9 @@ impl State<ClientConnectionData> for ExpectServerHello {
                 debug!("Using ciphersuite {:?}", suite);
                 self.suite = Some(suite);
                 cx.common.suite = Some(suite);
+                cx.common
+                    .record_layer
+                    .set_aead_limits(suite.aead_algorithm());
                 cx.common.backend.set_cipher_suite(suite).await?;
             }
         }
@@ -542,6 +942,12 @@ impl State<ClientConnectionData> for ExpectServerHello {
         let mut transcript = self.transcript_buffer.start_hash(suite.hash_algorithm());
         transcript.add_message(&m);
 
+        if let Some(check) = &self.config.server_random_check {
+            if !check(&server_hello.random.0) {
+                return Err(cx.common.illegal_param("server random rejected").await?);
+            }
+        }
+
         let randoms = ConnectionRandoms::new(self.random, server_hello.random);
         cx.common
             .backend
@@ -592,7 +998,7 @@ impl State<ClientConnectionData> for ExpectServerHello {
                     using_ems: self.using_ems,
                     transcript,
                 }
-                .handle_server_hello(cx, suite, server_hello, tls13_supported)
+                .handle_server_hello(cx, suite, server_hello, tls13_offered)
                 .await
             }
         }
@@ -696,6 +1102,7 @@ impl ExpectServerHelloOrHelloRetryRequest {
 
         // HRR selects the ciphersuite.
         cx.common.suite = Some(cs);
+        cx.common.record_layer.set_aead_limits(cs.aead_algorithm());
 
         // This is the draft19 change where the transcript became a tree
         let transcript = self.next.transcript_buffer.start_hash(cs.hash_algorithm());
@@ -0,0 +1,299 @@
+use crate::{
+    anchors::RootCertStore,
+    client::{ClientConfig, PathPolicy},
+    error::Error,
+    kx,
+};
+use tls_core::{
+    anchors::OwnedTrustAnchor,
+    msgs::{
+        base::PayloadU8,
+        codec::{self, Codec, Reader},
+        enums::{
+            CertificateType, CipherSuite, Compression, ExtensionType, MaxFragmentLength,
+            NamedGroup, ProtocolVersion, SignatureScheme,
+        },
+    },
+    suites::{self, SupportedCipherSuite},
+    versions,
+};
+
+/// The version of the format written by [`ClientConfig::to_bytes`].
+const CONFIG_FORMAT: u8 = 1;
+
+/// Every cipher suite a serialized config may name.
+static KNOWN_CIPHER_SUITES: &[SupportedCipherSuite] = &[
+    suites::TLS13_AES_256_GCM_SHA384,
+    suites::TLS13_AES_128_GCM_SHA256,
+    suites::TLS13_CHACHA20_POLY1305_SHA256,
+    #[cfg(feature = "tls12")]
+    suites::TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+    #[cfg(feature = "tls12")]
+    suites::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+    #[cfg(feature = "tls12")]
+    suites::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+    #[cfg(feature = "tls12")]
+    suites::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+    #[cfg(feature = "tls12")]
+    suites::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+    #[cfg(feature = "tls12")]
+    suites::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+];
+
+/// Every key exchange group a serialized config may name.
+static KNOWN_KX_GROUPS: &[&kx::SupportedKxGroup] = &[&kx::X25519, &kx::SECP256R1, &kx::SECP384R1];
+
+impl ClientConfig {
+    /// Serializes the parts of this config that are plain data, so that
+    /// an equivalent config can be rebuilt elsewhere with
+    /// [`ClientConfig::from_bytes`].
+    ///
+    /// These fields round-trip: the cipher suites, key exchange groups
+    /// and protocol versions, the root certificates (if the verifier is
+    /// the default one, or otherwise reports its trust anchors), and
+    /// every public field that is not a callback or trait object.
+    ///
+    /// These do not, and take their defaults when rebuilt:
+    /// `session_storage`, `client_auth_cert_resolver`, `key_log`,
+    /// `server_random_check`, `transcript_byte_observer`,
+    /// `client_hello_transform`, `client_hello_inspector`,
+    /// `sigalg_reduction`, any Certificate Transparency policy, and any
+    /// custom verifier.  Set them again on the rebuilt config as needed.
+    ///
+    /// Nothing secret is written.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        CONFIG_FORMAT.encode(&mut bytes);
+
+        let suites: Vec<CipherSuite> = self.cipher_suites.iter().map(|cs| cs.suite()).collect();
+        codec::encode_vec_u16(&mut bytes, &suites);
+        let groups: Vec<NamedGroup> = self.kx_groups.iter().map(|kx| kx.name).collect();
+        codec::encode_vec_u16(&mut bytes, &groups);
+        let versions: Vec<ProtocolVersion> = [ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2]
+            .into_iter()
+            .filter(|v| self.versions.contains(*v))
+            .collect();
+        codec::encode_vec_u8(&mut bytes, &versions);
+
+        match self.verifier.trust_anchors() {
+            Some(roots) => {
+                1u8.encode(&mut bytes);
+                codec::encode_vec_u24(&mut bytes, &roots.roots);
+            }
+            None => 0u8.encode(&mut bytes),
+        }
+
+        encode_protocols(&mut bytes, &self.alpn_protocols);
+        encode_option(&mut bytes, &self.max_fragment_size.map(|sz| sz as u32));
+        encode_bool(&mut bytes, self.enable_tickets);
+        encode_bool(&mut bytes, self.enable_sni);
+        encode_bool(&mut bytes, self.enable_early_data);
+        encode_bool(&mut bytes, self.strict_post_handshake);
+        codec::encode_vec_u16(&mut bytes, &self.extension_order);
+        encode_bool(&mut bytes, self.enable_grease);
+        codec::encode_vec_u8(&mut bytes, &self.compression_methods);
+        encode_bool(&mut bytes, self.request_ocsp_stapling);
+        encode_bool(&mut bytes, self.reject_unsolicited_extensions);
+        encode_bool(&mut bytes, self.strict_record_parsing);
+        encode_bool(&mut bytes, self.coalesce_writes);
+        encode_option(&mut bytes, &self.max_fragment_length);
+        match &self.required_alpn {
+            Some(protocols) => {
+                1u8.encode(&mut bytes);
+                encode_protocols(&mut bytes, protocols);
+            }
+            None => 0u8.encode(&mut bytes),
+        }
+        encode_bool(&mut bytes, self.enforce_downgrade_protection);
+        (self.max_handshake_fragments as u64).encode(&mut bytes);
+        encode_bool(&mut bytes, self.tolerate_unordered_chain);
+        encode_bool(&mut bytes, self.path_building == PathPolicy::TryAlternates);
+        encode_bool(&mut bytes, self.report_post_handshake_events);
+        encode_option(
+            &mut bytes,
+            &self.max_handshake_message_size.map(|sz| sz as u64),
+        );
+        encode_bool(&mut bytes, self.send_tls13_compat_ccs);
+        match &self.signature_schemes {
+            Some(schemes) => {
+                1u8.encode(&mut bytes);
+                codec::encode_vec_u16(&mut bytes, schemes);
+            }
+            None => 0u8.encode(&mut bytes),
+        }
+        encode_bool(&mut bytes, self.alpn_strict_client_preference);
+        codec::encode_vec_u8(&mut bytes, &self.client_cert_type_preference);
+        encode_bool(&mut bytes, self.allow_unclean_eof);
+        encode_bool(&mut bytes, self.enable_ech_grease);
+        encode_option(&mut bytes, &self.record_size_limit);
+        encode_bool(&mut bytes, self.require_ems);
+
+        bytes
+    }
+
+    /// Rebuilds a config from the output of [`ClientConfig::to_bytes`].
+    ///
+    /// Server certificates are verified by the default verifier, using
+    /// the serialized root certificates.  If none were serialized the
+    /// root store is empty, and so every server certificate is rejected
+    /// until another verifier is set.
+    ///
+    /// Fails with [`Error::General`] if `bytes` are malformed, use an
+    /// unknown format version, or name a cipher suite, key exchange
+    /// group or protocol version this library does not support.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = || Error::General("invalid client config encoding".to_string());
+        let r = &mut Reader::init(bytes);
+
+        if u8::read(r).ok_or_else(invalid)? != CONFIG_FORMAT {
+            return Err(invalid());
+        }
+
+        let suites = codec::read_vec_u16::<CipherSuite>(r)
+            .ok_or_else(invalid)?
+            .into_iter()
+            .map(|suite| {
+                KNOWN_CIPHER_SUITES
+                    .iter()
+                    .find(|known| known.suite() == suite)
+                    .copied()
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        let kx_groups = codec::read_vec_u16::<NamedGroup>(r)
+            .ok_or_else(invalid)?
+            .into_iter()
+            .map(|name| KNOWN_KX_GROUPS.iter().find(|kx| kx.name == name).copied())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        let protocol_versions = codec::read_vec_u8::<ProtocolVersion>(r)
+            .ok_or_else(invalid)?
+            .into_iter()
+            .map(|version| match version {
+                ProtocolVersion::TLSv1_3 => Some(&versions::TLS13),
+                #[cfg(feature = "tls12")]
+                ProtocolVersion::TLSv1_2 => Some(&versions::TLS12),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+
+        let mut root_store = RootCertStore::empty();
+        match u8::read(r).ok_or_else(invalid)? {
+            0 => {}
+            1 => root_store.add_server_trust_anchors(
+                codec::read_vec_u24_limited::<OwnedTrustAnchor>(r, 0xff_ffff)
+                    .ok_or_else(invalid)?
+                    .into_iter(),
+            ),
+            _ => return Err(invalid()),
+        }
+
+        let mut config = Self::builder()
+            .with_cipher_suites(&suites)
+            .with_kx_groups(&kx_groups)
+            .with_protocol_versions(&protocol_versions)?
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+
+        config.alpn_protocols = read_protocols(r).ok_or_else(invalid)?;
+        config.max_fragment_size = read_option::<u32>(r)
+            .ok_or_else(invalid)?
+            .map(|sz| sz as usize);
+        config.enable_tickets = read_bool(r).ok_or_else(invalid)?;
+        config.enable_sni = read_bool(r).ok_or_else(invalid)?;
+        config.enable_early_data = read_bool(r).ok_or_else(invalid)?;
+        config.strict_post_handshake = read_bool(r).ok_or_else(invalid)?;
+        config.extension_order = codec::read_vec_u16::<ExtensionType>(r).ok_or_else(invalid)?;
+        config.enable_grease = read_bool(r).ok_or_else(invalid)?;
+        config.compression_methods = codec::read_vec_u8::<Compression>(r).ok_or_else(invalid)?;
+        config.request_ocsp_stapling = read_bool(r).ok_or_else(invalid)?;
+        config.reject_unsolicited_extensions = read_bool(r).ok_or_else(invalid)?;
+        config.strict_record_parsing = read_bool(r).ok_or_else(invalid)?;
+        config.coalesce_writes = read_bool(r).ok_or_else(invalid)?;
+        config.max_fragment_length = read_option::<MaxFragmentLength>(r).ok_or_else(invalid)?;
+        config.required_alpn = match u8::read(r).ok_or_else(invalid)? {
+            0 => None,
+            1 => Some(read_protocols(r).ok_or_else(invalid)?),
+            _ => return Err(invalid()),
+        };
+        config.enforce_downgrade_protection = read_bool(r).ok_or_else(invalid)?;
+        config.max_handshake_fragments = u64::read(r).ok_or_else(invalid)? as usize;
+        config.tolerate_unordered_chain = read_bool(r).ok_or_else(invalid)?;
+        config.path_building = if read_bool(r).ok_or_else(invalid)? {
+            PathPolicy::TryAlternates
+        } else {
+            PathPolicy::FirstPathOnly
+        };
+        config.report_post_handshake_events = read_bool(r).ok_or_else(invalid)?;
+        config.max_handshake_message_size = read_option::<u64>(r)
+            .ok_or_else(invalid)?
+            .map(|sz| sz as usize);
+        config.send_tls13_compat_ccs = read_bool(r).ok_or_else(invalid)?;
+        config.signature_schemes = match u8::read(r).ok_or_else(invalid)? {
+            0 => None,
+            1 => Some(codec::read_vec_u16::<SignatureScheme>(r).ok_or_else(invalid)?),
+            _ => return Err(invalid()),
+        };
+        config.alpn_strict_client_preference = read_bool(r).ok_or_else(invalid)?;
+        config.client_cert_type_preference =
+            codec::read_vec_u8::<CertificateType>(r).ok_or_else(invalid)?;
+        config.allow_unclean_eof = read_bool(r).ok_or_else(invalid)?;
+        config.enable_ech_grease = read_bool(r).ok_or_else(invalid)?;
+        config.record_size_limit = read_option::<u16>(r).ok_or_else(invalid)?;
+        config.require_ems = read_bool(r).ok_or_else(invalid)?;
+
+        if r.any_left() {
+            return Err(invalid());
+        }
+
+        Ok(config)
+    }
+}
+
+fn encode_bool(bytes: &mut Vec<u8>, value: bool) {
+    u8::from(value).encode(bytes);
+}
+
+fn read_bool(r: &mut Reader) -> Option<bool> {
+    match u8::read(r)? {
+        0 => Some(false),
+        1 => Some(true),
+        _ => None,
+    }
+}
+
+fn encode_option<T: Codec>(bytes: &mut Vec<u8>, value: &Option<T>) {
+    match value {
+        Some(value) => {
+            1u8.encode(bytes);
+            value.encode(bytes);
+        }
+        None => 0u8.encode(bytes),
+    }
+}
+
+fn read_option<T: Codec>(r: &mut Reader) -> Option<Option<T>> {
+    match u8::read(r)? {
+        0 => Some(None),
+        1 => Some(Some(T::read(r)?)),
+        _ => None,
+    }
+}
+
+fn encode_protocols(bytes: &mut Vec<u8>, protocols: &[Vec<u8>]) {
+    let protocols: Vec<PayloadU8> = protocols
+        .iter()
+        .map(|protocol| PayloadU8::new(protocol.clone()))
+        .collect();
+    codec::encode_vec_u16(bytes, &protocols);
+}
+
+fn read_protocols(r: &mut Reader) -> Option<Vec<Vec<u8>>> {
+    Some(
+        codec::read_vec_u16::<PayloadU8>(r)?
+            .into_iter()
+            .map(|protocol| protocol.0)
+            .collect(),
+    )
+}
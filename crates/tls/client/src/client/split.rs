@@ -0,0 +1,128 @@
+use crate::{client::ClientConnection, conn::IoState, error::Error};
+use futures::lock::Mutex;
+use std::{error::Error as StdError, fmt, io, sync::Arc};
+
+/// The receiving half of a [`ClientConnection`], made by
+/// [`ClientConnection::split`].
+///
+/// This reads TLS records from the peer, processes them, and gives out
+/// the plaintext received.
+pub struct ReadHalf {
+    conn: Arc<Mutex<ClientConnection>>,
+}
+
+/// The sending half of a [`ClientConnection`], made by
+/// [`ClientConnection::split`].
+///
+/// This takes plaintext to send, and gives out the TLS records to write
+/// to the peer.
+pub struct WriteHalf {
+    conn: Arc<Mutex<ClientConnection>>,
+}
+
+impl ClientConnection {
+    /// Splits the connection into halves which can be used from separate
+    /// tasks: one receiving from the peer and one sending to it.
+    ///
+    /// Both halves use the same backend, which holds the keys of both
+    /// directions, so each call on a half locks the connection for its
+    /// whole duration, including any time spent waiting on the backend.
+    /// Calls on one half therefore wait for a call on the other to
+    /// finish: none of them wait for the peer's records, but if the
+    /// backend suspends, for example while it runs a protocol with
+    /// another party, the other half waits for it too.
+    ///
+    /// Records which the receiving half processes may queue messages to
+    /// send, such as alerts; these are written out by
+    /// [`WriteHalf::write_tls`].  Use [`ReadHalf::reunite`] to rebuild
+    /// the connection.
+    pub fn split(self) -> (ReadHalf, WriteHalf) {
+        let conn = Arc::new(Mutex::new(self));
+        (
+            ReadHalf {
+                conn: Arc::clone(&conn),
+            },
+            WriteHalf { conn },
+        )
+    }
+}
+
+impl ReadHalf {
+    /// Reads TLS content from `rd`, as [`crate::ConnectionCommon::read_tls`]
+    /// does.
+    pub async fn read_tls<R: io::Read>(&mut self, rd: &mut R) -> io::Result<usize> {
+        self.conn.lock().await.read_tls(rd)
+    }
+
+    /// Processes the TLS content read so far, as
+    /// [`crate::ConnectionCommon::process_new_packets`] does.
+    pub async fn process_new_packets(&mut self) -> Result<IoState, Error> {
+        self.conn.lock().await.process_new_packets().await
+    }
+
+    /// Reads plaintext received from the peer into `buf`, as
+    /// [`crate::Reader`] does.
+    pub async fn read_plaintext(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::Read::read(&mut self.conn.lock().await.reader(), buf)
+    }
+
+    /// Rebuilds the connection from its halves.
+    ///
+    /// Fails, returning both halves, if they came from different
+    /// connections.
+    pub fn reunite(self, write: WriteHalf) -> Result<ClientConnection, ReuniteError> {
+        if !Arc::ptr_eq(&self.conn, &write.conn) {
+            return Err(ReuniteError(self, write));
+        }
+
+        drop(write);
+        match Arc::try_unwrap(self.conn) {
+            Ok(conn) => Ok(conn.into_inner()),
+            Err(_) => unreachable!("both halves were given"),
+        }
+    }
+}
+
+impl WriteHalf {
+    /// Queues plaintext to send, as
+    /// [`crate::ConnectionCommon::write_plaintext`] does.
+    pub async fn write_plaintext(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.conn.lock().await.write_plaintext(buf).await
+    }
+
+    /// Queues any plaintext held back, as
+    /// [`crate::ConnectionCommon::flush_plaintext`] does.
+    pub async fn flush_plaintext(&mut self) -> Result<(), Error> {
+        self.conn.lock().await.flush_plaintext().await
+    }
+
+    /// Writes TLS records to `wr`, as [`crate::CommonState::write_tls`]
+    /// does.
+    pub async fn write_tls<W: io::Write>(&mut self, wr: &mut W) -> io::Result<usize> {
+        self.conn.lock().await.write_tls(wr)
+    }
+
+    /// Returns true if there are TLS records waiting for
+    /// [`WriteHalf::write_tls`].
+    pub async fn wants_write(&self) -> bool {
+        self.conn.lock().await.wants_write()
+    }
+}
+
+/// The error from [`ReadHalf::reunite`] when the halves came from
+/// different connections.  It gives both halves back.
+pub struct ReuniteError(pub ReadHalf, pub WriteHalf);
+
+impl fmt::Debug for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReuniteError")
+    }
+}
+
+impl fmt::Display for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tried to reunite halves of different connections")
+    }
+}
+
+impl StdError for ReuniteError {}
@@ -1,6 +1,9 @@
-use super::{client_conn::ClientConnectionData, hs::ClientContext};
+use super::{
+    client_conn::{ClientConnectionData, PostHandshakeEvent},
+    hs::ClientContext,
+};
 #[cfg(feature = "logging")]
-use crate::log::{debug, trace};
+use crate::log::{debug, trace, warn};
 use crate::{
     check::{inappropriate_handshake_message, inappropriate_message},
     client::{
@@ -10,6 +13,7 @@ use crate::{
     conn::{CommonState, ConnectionRandoms, State},
     error::Error,
     hash_hs::HandshakeHash,
+    kx::KeyExchange,
     msgs::persist,
     sign::Signer,
     ticketer::TimeBase,
@@ -23,10 +27,10 @@ use tls_core::{
     ke::ServerKxDetails,
     key::PublicKey,
     msgs::{
-        base::{Payload, PayloadU8},
+        base::{Payload, PayloadU24, PayloadU8},
         ccs::ChangeCipherSpecPayload,
         codec::Codec,
-        enums::{AlertDescription, ContentType, HandshakeType, ProtocolVersion},
+        enums::{AlertDescription, CertificateType, ContentType, HandshakeType, ProtocolVersion},
         handshake::{
             CertificatePayload, DecomposedSignatureScheme, DigitallySignedStruct,
             HandshakeMessagePayload, HandshakePayload, NewSessionTicketPayload, SCTList,
@@ -62,7 +66,7 @@ mod server_hello {
             cx: &mut ClientContext<'_>,
             suite: &'static Tls12CipherSuite,
             server_hello: &ServerHelloPayload,
-            tls13_supported: bool,
+            tls13_offered: bool,
         ) -> hs::NextStateOrError {
             server_hello.random.write_slice(&mut self.randoms.server);
 
@@ -70,15 +74,26 @@ mod server_hello {
             // both the server random and TLS12_DOWNGRADE_SENTINEL are
             // public values and don't require constant time comparison
             let has_downgrade_marker = self.randoms.server[24..] == tls12::DOWNGRADE_SENTINEL;
-            if tls13_supported && has_downgrade_marker {
-                return Err(cx
-                    .common
-                    .illegal_param("downgrade to TLS1.2 when TLS1.3 is supported")
-                    .await?);
+            if tls13_offered && has_downgrade_marker {
+                if self.config.enforce_downgrade_protection {
+                    return Err(cx
+                        .common
+                        .illegal_param("downgrade to TLS1.2 when TLS1.3 is supported")
+                        .await?);
+                }
+                warn!("Ignoring TLS1.3 downgrade sentinel in server random");
             }
 
             // Doing EMS?
             self.using_ems = server_hello.ems_support_acked();
+            if self.config.require_ems && !self.using_ems {
+                cx.common
+                    .send_fatal_alert(AlertDescription::HandshakeFailure)
+                    .await?;
+                return Err(Error::PeerIncompatibleError(
+                    "EMS required but not negotiated".to_string(),
+                ));
+            }
 
             // Might the server send a ticket?
             let must_issue_new_ticket = if server_hello
@@ -224,6 +239,8 @@ impl State<ClientConnectionData> for ExpectCertificate {
             HandshakeType::Certificate,
             HandshakePayload::Certificate
         )?;
+        let server_cert_chain =
+            hs::order_server_cert_chain(cx.common, &self.config, server_cert_chain).await?;
 
         if self.may_send_cert_status {
             Ok(Box::new(ExpectCertificateStatusOrServerKx {
@@ -382,6 +399,16 @@ impl State<ClientConnectionData> for ExpectCertificateStatus {
             &server_cert_ocsp_response
         );
 
+        let server_cert_ocsp_response = if self.config.request_ocsp_stapling {
+            server_cert_ocsp_response
+        } else {
+            debug!("Ignoring unsolicited OCSP response");
+            Vec::new()
+        };
+        if !server_cert_ocsp_response.is_empty() {
+            cx.data.stapled_ocsp_response = Some(server_cert_ocsp_response.clone());
+        }
+
         let server_cert = ServerCertDetails::new(
             self.server_cert_chain,
             server_cert_ocsp_response,
@@ -474,14 +501,14 @@ impl State<ClientConnectionData> for ExpectServerKx {
 
 async fn emit_certificate(
     transcript: &mut HandshakeHash,
-    cert_chain: CertificatePayload,
+    payload: HandshakePayload,
     common: &mut CommonState,
 ) -> Result<(), Error> {
     let cert = Message {
         version: ProtocolVersion::TLSv1_2,
         payload: MessagePayload::Handshake(HandshakeMessagePayload {
             typ: HandshakeType::Certificate,
-            payload: HandshakePayload::Certificate(cert_chain),
+            payload,
         }),
     };
 
@@ -675,7 +702,8 @@ impl State<ClientConnectionData> for ExpectCertificateRequest {
             Some(&certreq.canames),
             &certreq.sigschemes,
             NO_CONTEXT,
-        );
+        )
+        .await;
 
         Ok(Box::new(ExpectServerDone {
             config: self.config,
@@ -753,29 +781,8 @@ impl State<ClientConnectionData> for ExpectServerDone {
         // 6. emit a Finished, our first encrypted message under the new keys.
 
         // 1.
-        let (end_entity, intermediates) = st
-            .server_cert
-            .cert_chain()
-            .split_first()
-            .ok_or(Error::NoCertificatesPresented)?;
-        let now = web_time::SystemTime::now();
-        let cert_verified = match st.config.verifier.verify_server_cert(
-            end_entity,
-            intermediates,
-            &st.server_name,
-            &mut st
-                .server_cert
-                .scts()
-                .map(|sct| sct.as_slice())
-                .unwrap_or(&[])
-                .iter()
-                .map(|sct| sct.0.as_slice()),
-            st.server_cert.ocsp_response(),
-            now,
-        ) {
-            Ok(cert_verified) => cert_verified,
-            Err(e) => return Err(hs::send_cert_error_alert(cx.common, Error::CoreError(e)).await?),
-        };
+        let cert_verified =
+            hs::verify_server_cert_chain(cx, &st.config, &st.server_name, &st.server_cert).await?;
 
         // 3.
         // Build up the contents of the signed message.
@@ -788,7 +795,20 @@ impl State<ClientConnectionData> for ExpectServerDone {
 
             // Check the signature is compatible with the ciphersuite.
             let sig = st.server_kx.kx_sig();
-            if !SupportedCipherSuite::from(suite).usable_for_signature_algorithm(sig.scheme.sign())
+            if let Some(reduce) = &st.config.sigalg_reduction {
+                let allowed = reduce(
+                    suite.common.suite,
+                    &hs::offered_signature_schemes(&st.config),
+                );
+                if !allowed.contains(&sig.scheme) {
+                    let error_message = format!(
+                        "peer signed kx with wrong algorithm (got {:?} expect {:?})",
+                        sig.scheme, allowed
+                    );
+                    return Err(Error::PeerMisbehavedError(error_message));
+                }
+            } else if !SupportedCipherSuite::from(suite)
+                .usable_for_signature_algorithm(sig.scheme.sign())
             {
                 let error_message = format!(
                     "peer signed kx with wrong algorithm (got {:?} expect {:?})",
@@ -813,11 +833,26 @@ impl State<ClientConnectionData> for ExpectServerDone {
 
         // 4.
         if let Some(client_auth) = &st.client_auth {
-            let certs = match client_auth {
-                ClientAuthDetails::Empty { .. } => Vec::new(),
-                ClientAuthDetails::Verify { certkey, .. } => certkey.cert.clone(),
+            let raw_public_key = cx.data.client_cert_type == Some(CertificateType::RawPublicKey);
+            let cert = match client_auth {
+                ClientAuthDetails::Empty { .. } => HandshakePayload::Certificate(Vec::new()),
+                ClientAuthDetails::Verify { certkey, .. } if raw_public_key => {
+                    match &certkey.raw_public_key {
+                        Some(spki) => {
+                            HandshakePayload::RawPublicKeyCertificate(PayloadU24::new(spki.clone()))
+                        }
+                        None => {
+                            return Err(Error::General(
+                                "client credential has no raw public key".to_string(),
+                            ))
+                        }
+                    }
+                }
+                ClientAuthDetails::Verify { certkey, .. } => {
+                    HandshakePayload::Certificate(certkey.cert.clone())
+                }
             };
-            emit_certificate(&mut st.transcript, certs, cx.common).await?;
+            emit_certificate(&mut st.transcript, cert, cx.common).await?;
         }
 
         // 5a.
@@ -838,17 +873,22 @@ impl State<ClientConnectionData> for ExpectServerDone {
                 "peer chose an unsupported group".to_string(),
             ));
         }
+        cx.data.negotiated_kx_group = KeyExchange::choose(key_share.group, &st.config.kx_groups);
 
         // 5b.
         let mut transcript = st.transcript;
         emit_clientkx(&mut transcript, cx.common, &key_share).await?;
-        // nb. EMS handshake hash only runs up to ClientKeyExchange.
-        let ems_seed = transcript.get_current_hash();
+        // nb. EMS handshake hash only runs up to ClientKeyExchange.  The
+        // backend is only given it when EMS is in use, and then derives
+        // the extended master secret from it.
+        if st.using_ems {
+            let ems_seed = transcript.get_current_hash();
 
-        cx.common
-            .backend
-            .set_hs_hash_client_key_exchange(ems_seed.as_ref().to_vec())
-            .await?;
+            cx.common
+                .backend
+                .set_hs_hash_client_key_exchange(ems_seed.as_ref().to_vec())
+                .await?;
+        }
 
         // 5c.
         if let Some(ClientAuthDetails::Verify { signer, .. }) = &st.client_auth {
@@ -933,7 +973,7 @@ struct ExpectNewTicket {
 impl State<ClientConnectionData> for ExpectNewTicket {
     async fn handle(
         mut self: Box<Self>,
-        _cx: &mut ClientContext<'_>,
+        cx: &mut ClientContext<'_>,
         m: Message,
     ) -> hs::NextStateOrError {
         self.transcript.add_message(&m);
@@ -943,6 +983,10 @@ impl State<ClientConnectionData> for ExpectNewTicket {
             HandshakeType::NewSessionTicket,
             HandshakePayload::NewSessionTicket
         )?;
+        cx.data
+            .report_post_handshake_event(PostHandshakeEvent::NewSessionTicket {
+                lifetime: nst.lifetime_hint,
+            });
 
         Ok(Box::new(ExpectCcs {
             config: self.config,
@@ -1113,6 +1157,8 @@ impl State<ClientConnectionData> for ExpectFinished {
                 }
             };
 
+        cx.data.server_finished_verify_data = Some(finished.0.clone());
+
         // Hash this message too.
         st.transcript.add_message(&m);
 
@@ -1,4 +1,7 @@
-use super::{client_conn::ClientConnectionData, hs::ClientContext};
+use super::{
+    client_conn::{ClientConnectionData, PostHandshakeEvent},
+    hs::ClientContext,
+};
 #[cfg(feature = "logging")]
 use crate::log::{debug, trace, warn};
 use crate::{
@@ -6,11 +9,12 @@ use crate::{
     check::inappropriate_handshake_message,
     client::{
         common::{ClientAuthDetails, ClientHelloDetails, ServerCertDetails},
-        hs, ClientConfig, ServerName, StoresClientSessions,
+        hs, ClientConfig, ServerName, StoresClientSessionsAsync,
     },
     conn::{CommonState, ConnectionRandoms, State},
     error::Error,
     hash_hs::{HandshakeHash, HandshakeHashBuffer},
+    kx::KeyExchange,
     msgs::persist,
     sign, verify, KeyLog,
 };
@@ -84,6 +88,7 @@ pub(super) async fn handle_server_hello(
     if our_key_share.group != their_key_share.group {
         return Err(cx.common.illegal_param("wrong group for key share").await?);
     }
+    cx.data.negotiated_kx_group = KeyExchange::choose(their_key_share.group, &config.kx_groups);
 
     cx.common
         .backend
@@ -218,6 +223,16 @@ async fn validate_server_hello(
 //     cx.common.suite = Some(resuming_suite.into());
 //     cx.data.resumption_ciphersuite = Some(resuming_suite.into());
 
+//     // A ticket without the early_data extension does not allow 0-RTT at
+//     // all; one advertising zero bytes allows none to be sent.
+//     match resuming_session.max_early_data_size() {
+//         Some(max) if config.enable_early_data && max > 0 && !doing_retry => {
+//             cx.data.early_data.enable(max as usize);
+//             exts.push(ClientExtension::EarlyData);
+//         }
+//         _ => {}
+//     }
+
 //     // Finally, and only for TLS1.3 with a ticket resumption, include a binder
 //     // for our ticket.  This must go last.
 //     //
@@ -312,6 +327,14 @@ impl State<ClientConnectionData> for ExpectEncryptedExtensions {
 
         validate_encrypted_extensions(cx.common, &self.hello, exts).await?;
         hs::process_alpn_protocol(cx.common, &self.config, exts.get_alpn_protocol()).await?;
+        hs::process_max_fragment_length(cx, &self.config, exts.get_max_fragment_length()).await?;
+        hs::process_record_size_limit(
+            cx,
+            &self.config,
+            exts.get_record_size_limit(),
+            exts.get_max_fragment_length().is_some(),
+        )
+        .await?;
 
         if let Some(resuming_session) = self.resuming_session {
             let was_early_traffic = cx.common.early_traffic;
@@ -344,6 +367,7 @@ impl State<ClientConnectionData> for ExpectEncryptedExtensions {
                 client_auth: None,
                 cert_verified,
                 sig_verified,
+                resuming: true,
             }))
         } else {
             if exts.early_data_extension_offered() {
@@ -485,7 +509,8 @@ impl State<ClientConnectionData> for ExpectCertificateRequest {
             certreq.get_authorities_extension(),
             &compat_sigschemes,
             Some(certreq.context.0.clone()),
-        );
+        )
+        .await;
 
         Ok(Box::new(ExpectCertificate {
             config: self.config,
@@ -544,11 +569,25 @@ impl State<ClientConnectionData> for ExpectCertificate {
             ));
         }
 
-        let server_cert = ServerCertDetails::new(
-            cert_chain.convert(),
-            cert_chain.get_end_entity_ocsp(),
-            cert_chain.get_end_entity_scts(),
-        );
+        let mut ocsp_response = cert_chain.get_end_entity_ocsp();
+        if !ocsp_response.is_empty() && !self.config.request_ocsp_stapling {
+            if self.config.reject_unsolicited_extensions {
+                cx.common
+                    .send_fatal_alert(AlertDescription::UnsupportedExtension)
+                    .await?;
+                return Err(Error::PeerMisbehavedError(
+                    "server sent unsolicited OCSP response".to_string(),
+                ));
+            }
+
+            debug!("Ignoring unsolicited OCSP response");
+            ocsp_response.clear();
+        }
+
+        let chain =
+            hs::order_server_cert_chain(cx.common, &self.config, cert_chain.convert()).await?;
+        let server_cert =
+            ServerCertDetails::new(chain, ocsp_response, cert_chain.get_end_entity_scts());
 
         if let Some(sct_list) = server_cert.scts() {
             if hs::sct_list_is_invalid(sct_list) {
@@ -601,29 +640,9 @@ impl State<ClientConnectionData> for ExpectCertificateVerify {
         trace!("Server cert is {:?}", self.server_cert.cert_chain());
 
         // 1. Verify the certificate chain.
-        let (end_entity, intermediates) = self
-            .server_cert
-            .cert_chain()
-            .split_first()
-            .ok_or(Error::NoCertificatesPresented)?;
-        let now = web_time::SystemTime::now();
-        let cert_verified = match self.config.verifier.verify_server_cert(
-            end_entity,
-            intermediates,
-            &self.server_name,
-            &mut self
-                .server_cert
-                .scts()
-                .map(|sct| sct.as_slice())
-                .unwrap_or(&[])
-                .iter()
-                .map(|sct| sct.0.as_slice()),
-            self.server_cert.ocsp_response(),
-            now,
-        ) {
-            Ok(cert_verified) => cert_verified,
-            Err(e) => return Err(hs::send_cert_error_alert(cx.common, Error::CoreError(e)).await?),
-        };
+        let cert_verified =
+            hs::verify_server_cert_chain(cx, &self.config, &self.server_name, &self.server_cert)
+                .await?;
 
         // 2. Verify their signature on the handshake.
         let handshake_hash = self.transcript.get_current_hash();
@@ -648,6 +667,7 @@ impl State<ClientConnectionData> for ExpectCertificateVerify {
             client_auth: self.client_auth,
             cert_verified,
             sig_verified,
+            resuming: false,
         }))
     }
 }
@@ -751,6 +771,9 @@ struct ExpectFinished {
     client_auth: Option<ClientAuthDetails>,
     cert_verified: verify::ServerCertVerified,
     sig_verified: verify::HandshakeSignatureValid,
+    /// Whether the server accepted our PSK, in which case it must not
+    /// authenticate itself with a certificate.
+    resuming: bool,
 }
 
 #[async_trait]
@@ -761,6 +784,15 @@ impl State<ClientConnectionData> for ExpectFinished {
         m: Message,
     ) -> hs::NextStateOrError {
         let mut st = *self;
+        if st.resuming && m.is_handshake_type(HandshakeType::Certificate) {
+            cx.common
+                .send_fatal_alert(AlertDescription::UnexpectedMessage)
+                .await?;
+            return Err(Error::PeerMisbehavedError(
+                "unexpected certificate in resumed handshake".to_string(),
+            ));
+        }
+
         let finished =
             require_handshake_msg!(m, HandshakeType::Finished, HandshakePayload::Finished)?;
 
@@ -785,6 +817,7 @@ impl State<ClientConnectionData> for ExpectFinished {
             }
         };
 
+        cx.data.server_finished_verify_data = Some(finished.0.clone());
         st.transcript.add_message(&m);
 
         /* The EndOfEarlyData message to server is still encrypted with early data keys,
@@ -867,7 +900,7 @@ impl State<ClientConnectionData> for ExpectFinished {
 // In this state we can be sent tickets, key updates,
 // and application data.
 struct ExpectTraffic {
-    session_storage: Arc<dyn StoresClientSessions>,
+    session_storage: Arc<dyn StoresClientSessionsAsync>,
     server_name: ServerName,
     suite: &'static Tls13CipherSuite,
     transcript: HandshakeHash,
@@ -893,6 +926,11 @@ impl ExpectTraffic {
             ));
         }
 
+        cx.data
+            .report_post_handshake_event(PostHandshakeEvent::NewSessionTicket {
+                lifetime: nst.lifetime,
+            });
+
         // let handshake_hash = self.transcript.get_current_hash();
         // let secret = self
         //     .key_schedule
@@ -915,14 +953,14 @@ impl ExpectTraffic {
         //     time_now,
         //     nst.lifetime,
         //     nst.age_add,
-        //     nst.get_max_early_data_size().unwrap_or_default(),
+        //     nst.get_max_early_data_size(),
         // );
 
         // let key = persist::ClientSessionKey::session_for_server_name(&self.server_name);
         // #[allow(unused_mut)]
         // let mut ticket = value.get_encoding();
 
-        // let worked = self.session_storage.put(key.get_encoding(), ticket);
+        // let worked = self.session_storage.put(key.get_encoding(), ticket).await;
 
         // if worked {
         //     debug!("Ticket saved");
@@ -1,35 +1,70 @@
 #[cfg(feature = "logging")]
 use crate::log::{debug, error, trace, warn};
+#[cfg(feature = "notary")]
+use crate::notary::{NotaryLog, RecordDirection};
 use crate::{
     backend::{Backend, RustCryptoBackend},
-    client::ClientConnectionData,
+    client::{ClientConnectionData, Direction, RecordObserver},
     error::Error,
     record_layer,
-    vecbuf::ChunkVecBuffer,
+    vecbuf::{Chunk, ChunkVecBuffer},
 };
 use async_trait::async_trait;
-use futures::{AsyncRead, AsyncWrite};
+use futures::{future, ready, AsyncRead, AsyncWrite};
 use std::{
     backtrace::Backtrace,
+    cmp,
     collections::VecDeque,
     convert::TryFrom,
+    future::Future,
     io, mem,
     ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::Arc,
+    task::{self, Poll},
 };
 use tls_backend::BackendNotify;
 use tls_core::{
     msgs::{
         alert::AlertMessagePayload,
         base::Payload,
+        codec::Codec,
         deframer::MessageDeframer,
         enums::{AlertDescription, AlertLevel, ContentType, HandshakeType, ProtocolVersion},
-        fragmenter::MessageFragmenter,
+        fragmenter::{MessageFragmenter, MAX_FRAGMENT_LEN},
         handshake::Random,
         hsjoiner::HandshakeJoiner,
-        message::{Message, MessagePayload, OpaqueMessage, PlainMessage},
+        message::{Message, MessageError, MessagePayload, OpaqueMessage, PlainMessage},
     },
     suites::SupportedCipherSuite,
 };
+use web_time::Instant;
+
+/// Runs `op`, failing with an `io::ErrorKind::TimedOut` error if it is
+/// still waiting at `deadline`.
+///
+/// Transport operations leave the connection unchanged until they
+/// complete, and backend operations started through [`ConnectionCommon::poll_op`]
+/// are kept by the connection, so either can be abandoned.
+async fn before<F: Future>(deadline: Option<Instant>, op: F) -> io::Result<F::Output> {
+    match deadline {
+        #[cfg(feature = "tokio")]
+        Some(deadline) => {
+            tokio::time::timeout(deadline.saturating_duration_since(Instant::now()), op)
+                .await
+                .map_err(|_| io::ErrorKind::TimedOut.into())
+        }
+        _ => Ok(op.await),
+    }
+}
+
+/// Runs `op`, which waits only on the transport, as [`before`] does.
+async fn io_before<F, O>(deadline: Option<Instant>, op: F) -> io::Result<O>
+where
+    F: Future<Output = io::Result<O>>,
+{
+    before(deadline, op).await?
+}
 
 /// Values of this structure are returned from
 /// [`Connection::process_new_packets`] and tell the caller the current I/O
@@ -38,7 +73,10 @@ use tls_core::{
 pub struct IoState {
     tls_bytes_to_write: usize,
     plaintext_bytes_to_read: usize,
+    plaintext_bytes_buffered: usize,
     peer_has_closed: bool,
+    made_progress: bool,
+    has_unprocessed_records: bool,
 }
 
 impl IoState {
@@ -54,6 +92,21 @@ impl IoState {
         self.plaintext_bytes_to_read
     }
 
+    /// How many bytes of application plaintext are buffered and not yet
+    /// encrypted: data written before the handshake completed, or held
+    /// back by [`ClientConfig::coalesce_writes`].
+    ///
+    /// Writers can stop calling [`write_plaintext`] while this is above
+    /// some threshold, rather than having writes truncated by
+    /// [`set_buffer_limit`].
+    ///
+    /// [`ClientConfig::coalesce_writes`]: crate::ClientConfig::coalesce_writes
+    /// [`write_plaintext`]: ConnectionCommon::write_plaintext
+    /// [`set_buffer_limit`]: CommonState::set_buffer_limit
+    pub fn plaintext_bytes_buffered(&self) -> usize {
+        self.plaintext_bytes_buffered
+    }
+
     /// True if the peer has sent us a close_notify alert.  This is
     /// the TLS mechanism to securely half-close a TLS connection,
     /// and signifies that the peer will not send any further data
@@ -65,6 +118,49 @@ impl IoState {
     pub fn peer_has_closed(&self) -> bool {
         self.peer_has_closed
     }
+
+    /// True if the call that returned this processed any records or
+    /// moved any messages to or from the backend.
+    ///
+    /// Processing when nothing new has been read returns false here.  If
+    /// the backend is also idle (see [`Backend::is_idle`]) such a call
+    /// neither involves the backend nor allocates, so it is cheap to call
+    /// speculatively from a poll loop.
+    pub fn made_progress(&self) -> bool {
+        self.made_progress
+    }
+
+    /// True if records read so far were left unprocessed, because
+    /// [`process_new_packets_batched`] reached its limit.  Call it again
+    /// to continue.
+    ///
+    /// [`process_new_packets_batched`]: ConnectionCommon::process_new_packets_batched
+    pub fn has_unprocessed_records(&self) -> bool {
+        self.has_unprocessed_records
+    }
+}
+
+/// Counters describing the traffic a connection has handled, returned
+/// from [`CommonState::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Bytes of application data received from the peer, whether or not
+    /// they have been read yet.
+    pub plaintext_bytes_read: u64,
+    /// Bytes of application data put into records for the peer.  Data
+    /// written before the handshake completes is counted once it is sent.
+    pub plaintext_bytes_written: u64,
+    /// Bytes read from the transport by `read_tls`.
+    pub tls_bytes_read: u64,
+    /// Bytes written to the transport by `write_tls`.
+    pub tls_bytes_written: u64,
+    /// Complete records read from the transport.
+    pub records_received: u64,
+    /// Records queued to be written to the transport, including
+    /// handshake records such as the ClientHello.
+    pub records_sent: u64,
+    /// Calls to `process_new_packets`, including batched calls.
+    pub process_new_packets_calls: u64,
 }
 
 /// A structure that implements [`std::io::Read`] for reading plaintext.
@@ -72,6 +168,7 @@ pub struct Reader<'a> {
     received_plaintext: &'a mut ChunkVecBuffer,
     peer_cleanly_closed: bool,
     has_seen_eof: bool,
+    allow_unclean_eof: bool,
 }
 
 impl<'a> io::Read for Reader<'a> {
@@ -103,22 +200,49 @@ impl<'a> io::Read for Reader<'a> {
         let len = self.received_plaintext.read(buf)?;
 
         if len == 0 && !buf.is_empty() {
-            // No bytes available:
-            match (self.peer_cleanly_closed, self.has_seen_eof) {
-                // cleanly closed; don't care about TCP EOF: express this as Ok(0)
-                (true, _) => {}
-                // unclean closure
-                (false, true) => return Err(io::ErrorKind::UnexpectedEof.into()),
-                // connection still going, but need more data: signal `WouldBlock` so that
-                // the caller knows this
-                (false, false) => return Err(io::ErrorKind::WouldBlock.into()),
-            }
+            self.check_no_bytes_state()?;
         }
 
         Ok(len)
     }
 }
 
+impl<'a> Reader<'a> {
+    /// Checks the state of the connection when no plaintext is buffered.
+    fn check_no_bytes_state(&self) -> io::Result<()> {
+        match (self.peer_cleanly_closed, self.has_seen_eof) {
+            // cleanly closed; don't care about TCP EOF: express this as Ok(0)
+            (true, _) => Ok(()),
+            // unclean closure, but the config treats it as clean
+            (false, true) if self.allow_unclean_eof => Ok(()),
+            // unclean closure
+            (false, true) => Err(io::ErrorKind::UnexpectedEof.into()),
+            // connection still going, but need more data: signal `WouldBlock` so that
+            // the caller knows this
+            (false, false) => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl<'a> io::BufRead for Reader<'a> {
+    /// Returns the next contiguous run of received plaintext, without
+    /// copying it.
+    ///
+    /// Returns an empty slice once the peer has cleanly closed the
+    /// connection, and otherwise fails like [`io::Read::read`] when no
+    /// plaintext is buffered.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.received_plaintext.is_empty() {
+            self.check_no_bytes_state()?;
+        }
+        Ok(self.received_plaintext.chunk())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.received_plaintext.consume(amt)
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub(crate) enum Protocol {
     Tcp,
@@ -159,49 +283,99 @@ enum Limit {
 
 /// Interface shared by client and server connections.
 pub struct ConnectionCommon {
+    pub(crate) core: ConnectionCore,
+    message_deframer: MessageDeframer,
+    /// The transport error that last ended [`Self::complete_io`].
+    io_error: Option<io::Error>,
+    /// The backend operation a poll method is waiting on, if the backend
+    /// suspended it.  It holds `core` until it completes.
+    in_flight: Option<(Op, InFlight)>,
+    /// The placeholder `core` is swapped for while an operation owns it,
+    /// kept between operations so that it is only built once.
+    spare_core: Option<Box<ConnectionCore>>,
+}
+
+/// The parts of a connection which backend operations work on.
+pub(crate) struct ConnectionCore {
     state: Result<Box<dyn State<ClientConnectionData>>, Error>,
     pub(crate) data: ClientConnectionData,
-    pub(crate) common_state: CommonState,
-    message_deframer: MessageDeframer,
+    common_state: CommonState,
     handshake_joiner: HandshakeJoiner,
+    /// How many records the incomplete handshake message in
+    /// `handshake_joiner` has arrived in so far.
+    handshake_fragments: usize,
 }
 
+/// A backend operation which can be started by a poll method.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Process(Option<usize>),
+    Flush,
+    CloseNotify,
+}
+
+/// A suspended backend operation.  It gives back the connection's core,
+/// and whether it made progress.
+type InFlight = Pin<Box<dyn Future<Output = (Box<ConnectionCore>, Result<bool, Error>)> + Send>>;
+
 impl ConnectionCommon {
     pub(crate) fn new(
         state: Box<dyn State<ClientConnectionData>>,
         data: ClientConnectionData,
         common_state: CommonState,
     ) -> Self {
+        let handshake_joiner =
+            HandshakeJoiner::with_max_message_size(common_state.max_handshake_message_size);
         Self {
-            state: Ok(state),
-            data,
-            common_state,
+            core: ConnectionCore {
+                state: Ok(state),
+                data,
+                common_state,
+                handshake_joiner,
+                handshake_fragments: 0,
+            },
             message_deframer: MessageDeframer::new(),
-            handshake_joiner: HandshakeJoiner::new(),
+            io_error: None,
+            in_flight: None,
+            spare_core: None,
         }
     }
 
     /// Returns an object that allows reading plaintext.
     pub fn reader(&mut self) -> Reader {
         Reader {
-            received_plaintext: &mut self.common_state.received_plaintext,
+            received_plaintext: &mut self.core.common_state.received_plaintext,
             // Are we done? i.e., have we processed all received messages, and received a
             // close_notify to indicate that no new messages will arrive?
-            peer_cleanly_closed: self.common_state.has_received_close_notify
+            peer_cleanly_closed: self.core.common_state.has_received_close_notify
                 && !self.message_deframer.has_pending(),
-            has_seen_eof: self.common_state.has_seen_eof,
+            has_seen_eof: self.core.common_state.has_seen_eof,
+            allow_unclean_eof: self.core.common_state.allow_unclean_eof,
         }
     }
 
+    /// Returns the next contiguous run of received plaintext, like
+    /// [`io::BufRead::fill_buf`] on [`Self::reader`], but borrowed from
+    /// the connection.
+    pub(crate) fn fill_plaintext_buf(&mut self) -> io::Result<&[u8]> {
+        let reader = self.reader();
+        if reader.received_plaintext.is_empty() {
+            reader.check_no_bytes_state()?;
+        }
+        Ok(self.core.common_state.received_plaintext.chunk())
+    }
+
     /// Reads out any buffered plaintext received from the peer. Returns the
     /// number of bytes read.
     pub fn read_plaintext(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.common_state.received_plaintext.read(buf)
+        self.core.common_state.received_plaintext.read(buf)
     }
 
     /// Returns whether there are buffered data.
     pub async fn is_empty(&mut self) -> Result<bool, Error> {
-        self.common_state
+        self.settle().await?;
+        self.core
+            .common_state
             .backend
             .is_empty()
             .await
@@ -210,24 +384,26 @@ impl ConnectionCommon {
 
     /// Initiate the TLS protocol
     pub async fn start(&mut self) -> Result<(), Error> {
-        let state = match mem::replace(&mut self.state, Err(Error::HandshakeNotComplete)) {
+        self.settle().await?;
+        let state = match mem::replace(&mut self.core.state, Err(Error::HandshakeNotComplete)) {
             Ok(state) => state,
             Err(e) => {
-                self.state = Err(e.clone());
+                self.core.state = Err(e.clone());
                 return Err(e);
             }
         };
         let mut cx = Context {
-            common: &mut self.common_state,
-            data: &mut self.data,
+            common: &mut self.core.common_state,
+            data: &mut self.core.data,
         };
-        self.state = state.start(&mut cx).await;
+        self.core.state = state.start(&mut cx).await;
         Ok(())
     }
 
     /// Signals that the server has closed the connection.
     pub async fn server_closed(&mut self) -> Result<(), Error> {
-        self.common_state.backend.server_closed().await?;
+        self.settle().await?;
+        self.core.common_state.backend.server_closed().await?;
         Ok(())
     }
 
@@ -251,7 +427,24 @@ impl ConnectionCommon {
     /// This function will block if `io` blocks.
     ///
     /// Errors from TLS record handling (i.e., from [`process_new_packets`])
-    /// are wrapped in an `io::ErrorKind::InvalidData`-kind error.
+    /// are wrapped in an `io::ErrorKind::InvalidData`-kind error.  Errors
+    /// from `io` itself, and reaching EOF before the handshake completes,
+    /// are returned as they are and also kept for [`take_io_error`], so
+    /// the two can be told apart.
+    ///
+    /// # Cancellation safety
+    ///
+    /// The future returned by this function may be dropped while it is
+    /// waiting on `io`, for example in a `select!` or under a timeout.
+    /// The connection's buffers are only changed once `io` has reported
+    /// how many bytes it took or gave, so no TLS data is lost or
+    /// duplicated, and calling `complete_io` again carries on where the
+    /// dropped call left off.  Only the byte counts of the dropped call
+    /// are lost.
+    ///
+    /// This extends to waits on the connection's backend: an operation
+    /// it suspends is kept by the connection, as by [`poll_process`], and
+    /// completed by the next call.
     ///
     /// [`is_handshaking`]: CommonState::is_handshaking
     /// [`wants_read`]: CommonState::wants_read
@@ -259,19 +452,76 @@ impl ConnectionCommon {
     /// [`write_tls`]: CommonState::write_tls
     /// [`read_tls`]: ConnectionCommon::read_tls
     /// [`process_new_packets`]: ConnectionCommon::process_new_packets
+    /// [`poll_process`]: ConnectionCommon::poll_process
+    /// [`take_io_error`]: ConnectionCommon::take_io_error
     pub async fn complete_io<T>(&mut self, io: &mut T) -> Result<(usize, usize), io::Error>
     where
         Self: Sized,
         T: AsyncRead + AsyncWrite + Unpin,
     {
+        self.complete_io_until(io, None).await
+    }
+
+    /// Like [`complete_io`], but fails with an `io::ErrorKind::TimedOut`
+    /// error if it has not finished by `deadline`.
+    ///
+    /// Waits on `io` and on the backend are both cut short.  A backend
+    /// operation cut short is kept by the connection, as by
+    /// [`poll_process`], so the connection is left consistent, and
+    /// calling this or [`complete_io`] again carries on where this left
+    /// off.  The error is also kept for [`take_io_error`].
+    ///
+    /// `deadline` is a [`web_time::Instant`], which is
+    /// [`std::time::Instant`] except on the web, where that is not
+    /// available.
+    ///
+    /// [`complete_io`]: ConnectionCommon::complete_io
+    /// [`poll_process`]: ConnectionCommon::poll_process
+    /// [`take_io_error`]: ConnectionCommon::take_io_error
+    #[cfg(feature = "tokio")]
+    pub async fn complete_io_with_deadline<T>(
+        &mut self,
+        io: &mut T,
+        deadline: Instant,
+    ) -> Result<(usize, usize), io::Error>
+    where
+        Self: Sized,
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.complete_io_until(io, Some(deadline)).await
+    }
+
+    async fn complete_io_until<T>(
+        &mut self,
+        io: &mut T,
+        deadline: Option<Instant>,
+    ) -> Result<(usize, usize), io::Error>
+    where
+        Self: Sized,
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.io_error = None;
+        before(deadline, self.settle())
+            .await
+            .map_err(|e| self.note_io_error(e))?
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
         let until_handshaked = self.is_handshaking();
         let mut eof = false;
         let mut wrlen = 0;
         let mut rdlen = 0;
 
         loop {
+            if self.core.common_state.coalesce_writes {
+                before(deadline, self.flush_plaintext())
+                    .await
+                    .map_err(|e| self.note_io_error(e))?
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            }
+
             while self.wants_write() {
-                wrlen += self.write_tls_async(io).await?;
+                wrlen += io_before(deadline, self.write_tls_async(io))
+                    .await
+                    .map_err(|e| self.note_io_error(e))?;
             }
 
             if !until_handshaked && wrlen > 0 {
@@ -279,13 +529,19 @@ impl ConnectionCommon {
             }
 
             if !eof && self.wants_read() {
-                match self.read_tls_async(io).await? {
+                match io_before(deadline, self.read_tls_async(io))
+                    .await
+                    .map_err(|e| self.note_io_error(e))?
+                {
                     0 => eof = true,
                     n => rdlen += n,
                 }
             }
 
-            match self.process_new_packets().await {
+            match before(deadline, self.process_new_packets())
+                .await
+                .map_err(|e| self.note_io_error(e))?
+            {
                 Ok(_) => {}
                 Err(e) => {
                     // In case we have an alert to send describing this error,
@@ -300,10 +556,62 @@ impl ConnectionCommon {
             match (eof, until_handshaked, self.is_handshaking()) {
                 (_, true, false) => return Ok((rdlen, wrlen)),
                 (_, false, _) => return Ok((rdlen, wrlen)),
-                (true, true, true) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                (true, true, true) => {
+                    return Err(self.note_io_error(io::Error::from(io::ErrorKind::UnexpectedEof)))
+                }
                 (..) => {}
             }
+
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                return Err(self.note_io_error(io::ErrorKind::TimedOut.into()));
+            }
+        }
+    }
+
+    /// Closes the TLS session cleanly, sending a close_notify alert to
+    /// the peer over `io`.
+    ///
+    /// This queues the alert after anything already written, writes it
+    /// all to `io`, and flushes `io`.  It returns once the send buffer is
+    /// empty, without waiting for the peer's own close_notify and without
+    /// closing `io`.
+    ///
+    /// Errors from the connection are wrapped in an
+    /// `io::ErrorKind::InvalidData`-kind error.
+    pub async fn close<T>(&mut self, io: &mut T) -> Result<(), io::Error>
+    where
+        T: AsyncWrite + Unpin,
+    {
+        let invalid_data = |e: Error| io::Error::new(io::ErrorKind::InvalidData, e);
+        self.send_close_notify().await.map_err(invalid_data)?;
+        self.flush_plaintext().await.map_err(invalid_data)?;
+
+        while self.wants_write() {
+            if self.write_tls_async(io).await? == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
         }
+
+        futures::AsyncWriteExt::flush(io).await
+    }
+
+    /// Takes the transport error that last caused [`complete_io`] to fail.
+    ///
+    /// Returns `None` if that call failed because of a TLS protocol error,
+    /// or if there is no such error or it was already taken.
+    ///
+    /// [`complete_io`]: ConnectionCommon::complete_io
+    pub fn take_io_error(&mut self) -> Option<io::Error> {
+        self.io_error.take()
+    }
+
+    /// Keeps a copy of `err` for [`Self::take_io_error`], and returns it.
+    fn note_io_error(&mut self, err: io::Error) -> io::Error {
+        self.io_error = Some(match err.raw_os_error() {
+            Some(code) => io::Error::from_raw_os_error(code),
+            None => io::Error::new(err.kind(), err.to_string()),
+        });
+        err
     }
 
     /// Extract the first handshake message.
@@ -322,25 +630,436 @@ impl ConnectionCommon {
         };
 
         let msg = msg.into_plain_message();
-        if !self.handshake_joiner.want_message(&msg) {
+        if !self.core.handshake_joiner.want_message(&msg) {
             return Err(Error::CorruptMessagePayload(ContentType::Handshake));
         }
 
-        if self.handshake_joiner.take_message(msg).is_none() {
-            self.common_state
-                .send_fatal_alert(AlertDescription::DecodeError)
-                .await?;
-            return Err(Error::CorruptMessagePayload(ContentType::Handshake));
+        if self.core.handshake_joiner.take_message(msg).is_none() {
+            return Err(self.core.handshake_joiner_error().await);
         }
 
-        self.common_state.aligned_handshake = self.handshake_joiner.is_empty();
-        Ok(self.handshake_joiner.frames.pop_front())
+        self.core.common_state.aligned_handshake = self.core.handshake_joiner.is_empty();
+        Ok(self.core.handshake_joiner.frames.pop_front())
     }
 
     pub(crate) fn replace_state(&mut self, new: Box<dyn State<ClientConnectionData>>) {
-        self.state = Ok(new);
+        self.core.state = Ok(new);
+    }
+
+    /// Returns a notification future which resolves when the backend has
+    /// messages ready to decrypt.
+    pub async fn get_notify(&mut self) -> Result<BackendNotify, Error> {
+        self.settle().await?;
+        self.core
+            .common_state
+            .backend
+            .get_notify()
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Processes any new packets read by a previous call to
+    /// [`Connection::read_tls`].
+    ///
+    /// Errors from this function relate to TLS protocol errors, and
+    /// are fatal to the connection.  Future calls after an error will do
+    /// no new work and will return the same error. After an error is
+    /// received from [`process_new_packets`], you should not call [`read_tls`]
+    /// any more (it will fill up buffers to no purpose). However, you
+    /// may call the other methods on the connection, including `write`,
+    /// `send_close_notify`, and `write_tls`. Most likely you will want to
+    /// call `write_tls` to send any alerts queued by the error and then
+    /// close the underlying connection.
+    ///
+    /// Success from this function comes with some sundry state data
+    /// about the connection.
+    ///
+    /// [`read_tls`]: Connection::read_tls
+    /// [`process_new_packets`]: Connection::process_new_packets
+    pub async fn process_new_packets(&mut self) -> Result<IoState, Error> {
+        future::poll_fn(|cx| self.poll_process(cx)).await
+    }
+
+    /// Like [`process_new_packets`], but processes at most `max_records`
+    /// of the records read so far before returning.
+    ///
+    /// This bounds the decryption work done by one call, so that a peer
+    /// sending many records cannot starve other connections served by the
+    /// same task.  Records beyond the limit stay buffered for the next
+    /// call to either method; [`has_unprocessed_records`] says whether
+    /// there are any.
+    ///
+    /// [`process_new_packets`]: Connection::process_new_packets
+    /// [`has_unprocessed_records`]: ConnectionCommon::has_unprocessed_records
+    pub async fn process_new_packets_batched(
+        &mut self,
+        max_records: usize,
+    ) -> Result<IoState, Error> {
+        let made_progress =
+            future::poll_fn(|cx| self.poll_op(cx, Op::Process(Some(max_records)))).await?;
+        Ok(self.io_state(made_progress))
+    }
+
+    /// Returns true if records read by [`read_tls`] are still waiting to
+    /// be processed, for example after
+    /// [`process_new_packets_batched`] reached its limit.
+    ///
+    /// [`read_tls`]: Connection::read_tls
+    /// [`process_new_packets_batched`]: ConnectionCommon::process_new_packets_batched
+    pub fn has_unprocessed_records(&self) -> bool {
+        !self.message_deframer.frames.is_empty()
+    }
+
+    /// Polls the processing of new packets, like [`process_new_packets`],
+    /// without requiring an executor.
+    ///
+    /// If the backend suspends, this returns [`Poll::Pending`] and keeps
+    /// the suspended work, which the next call to this resumes.  Until
+    /// then that work holds the connection's state, so the connection's
+    /// other accessors describe a connection which has not started: call
+    /// this again, or any async method, which completes it first.
+    ///
+    /// [`process_new_packets`]: Connection::process_new_packets
+    pub fn poll_process(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<IoState, Error>> {
+        let made_progress = ready!(self.poll_op(cx, Op::Process(None)))?;
+        Poll::Ready(Ok(self.io_state(made_progress)))
+    }
+
+    /// Polls the encryption of buffered plaintext, like
+    /// [`flush_plaintext`].  A suspended backend is handled as by
+    /// [`poll_process`].
+    ///
+    /// [`flush_plaintext`]: ConnectionCommon::flush_plaintext
+    /// [`poll_process`]: ConnectionCommon::poll_process
+    pub fn poll_flush_plaintext(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Error>> {
+        ready!(self.poll_op(cx, Op::Flush))?;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Polls sending a close_notify alert, like [`send_close_notify`].
+    /// A suspended backend is handled as by [`poll_process`].
+    ///
+    /// [`send_close_notify`]: ConnectionCommon::send_close_notify
+    /// [`poll_process`]: ConnectionCommon::poll_process
+    pub fn poll_send_close_notify(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<(), Error>> {
+        ready!(self.poll_op(cx, Op::CloseNotify))?;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Polls `op`, after completing any other operation a poll method
+    /// left suspended.  Returns whether `op` made progress.
+    fn poll_op(&mut self, cx: &mut task::Context<'_>, op: Op) -> Poll<Result<bool, Error>> {
+        if matches!(&self.in_flight, Some((started, _)) if *started != op) {
+            ready!(self.poll_settle(cx))?;
+        }
+
+        // With no records to process and nothing buffered in the backend,
+        // there is nothing to do.
+        if self.in_flight.is_none()
+            && matches!(op, Op::Process(_))
+            && !self.message_deframer.desynced
+            && self.message_deframer.frames.is_empty()
+            && self.core.common_state.backend.is_idle()
+        {
+            self.core.common_state.stats.process_new_packets_calls += 1;
+            return Poll::Ready(match &self.core.state {
+                Ok(_) => Ok(false),
+                Err(e) => Err(e.clone()),
+            });
+        }
+
+        let mut fut = match self.in_flight.take() {
+            Some((_, fut)) => fut,
+            None => self.start_op(op),
+        };
+        match fut.as_mut().poll(cx) {
+            Poll::Ready((mut core, result)) => {
+                mem::swap(&mut self.core, &mut core);
+                self.spare_core = Some(core);
+                Poll::Ready(result)
+            }
+            Poll::Pending => {
+                self.in_flight = Some((op, fut));
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Completes the operation a poll method left suspended, if any.
+    /// Its error, if it failed, is returned here.
+    pub(crate) fn poll_settle(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Error>> {
+        if let Some((op, _)) = self.in_flight {
+            ready!(self.poll_op(cx, op))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    async fn settle(&mut self) -> Result<(), Error> {
+        future::poll_fn(|cx| self.poll_settle(cx)).await
+    }
+
+    /// Starts `op` on the connection's core, which the returned future
+    /// owns until it completes.
+    fn start_op(&mut self, op: Op) -> InFlight {
+        let mut frames = VecDeque::new();
+        let mut desync = None;
+        if let Op::Process(max_records) = op {
+            if self.message_deframer.desynced {
+                desync = Some(self.desync_reason());
+            } else {
+                let count = max_records.map_or(usize::MAX, |max| max);
+                let count = count.min(self.message_deframer.frames.len());
+                frames.extend(self.message_deframer.frames.drain(..count));
+            }
+        }
+
+        // The spare's box is reused to hold the core, so that swapping it
+        // out does not allocate.
+        let mut core = match self.spare_core.take() {
+            Some(spare) => spare,
+            None => Box::new(self.core.placeholder()),
+        };
+        mem::swap(&mut self.core, &mut core);
+        Box::pin(async move {
+            let result = match op {
+                Op::Process(_) => core.process_frames(frames, desync).await,
+                Op::Flush => core.flush_plaintext().await.map(|()| false),
+                Op::CloseNotify => core.send_close_notify().await.map(|()| false),
+            };
+            (core, result)
+        })
+    }
+
+    /// Describes why the deframer desynced, for strict record parsing:
+    /// the alert to send and what was wrong.
+    fn desync_reason(&self) -> Option<(AlertDescription, &'static str)> {
+        match self.message_deframer.error {
+            Some(MessageError::IllegalLength) => {
+                Some((AlertDescription::DecodeError, "illegal length"))
+            }
+            Some(MessageError::IllegalContentType) => {
+                Some((AlertDescription::UnexpectedMessage, "unknown content type"))
+            }
+            Some(MessageError::IllegalProtocolVersion) => Some((
+                AlertDescription::ProtocolVersion,
+                "illegal protocol version",
+            )),
+            _ => None,
+        }
+    }
+
+    fn io_state(&self, made_progress: bool) -> IoState {
+        self.core
+            .common_state
+            .current_io_state(made_progress, self.has_unprocessed_records())
     }
 
+    /// Write buffer into connection.
+    pub async fn write_plaintext(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.settle().await?;
+        if let Ok(st) = &mut self.core.state {
+            st.perhaps_write_key_update(&mut self.core.common_state)
+                .await;
+        }
+        self.core.common_state.send_some_plaintext(buf).await
+    }
+
+    /// Write the buffers in `bufs` into connection, as if they were one
+    /// buffer, so that they may share records.
+    ///
+    /// Like [`write_plaintext`], this accepts only as many bytes as the
+    /// limit set by [`CommonState::set_buffer_limit`] allows, even if it
+    /// is reached part way through a buffer, and returns the number of
+    /// bytes accepted.
+    ///
+    /// [`write_plaintext`]: ConnectionCommon::write_plaintext
+    pub async fn write_vectored_plaintext(
+        &mut self,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Result<usize, Error> {
+        self.settle().await?;
+        // Copy only what the limit lets in, rather than every buffer.
+        let total = bufs.iter().map(|buf| buf.len()).sum();
+        let mut remaining = self.core.common_state.apply_plaintext_limit(total);
+        let mut data = Vec::with_capacity(remaining);
+        for buf in bufs {
+            let take = cmp::min(buf.len(), remaining);
+            data.extend_from_slice(&buf[..take]);
+            remaining -= take;
+            if remaining == 0 {
+                break;
+            }
+        }
+        self.write_plaintext(&data).await
+    }
+
+    /// Write entire buffer into connection.
+    ///
+    /// Unlike [`write_plaintext`], this does not return a short count.
+    /// Nothing drains the outgoing buffers while this runs, so if the
+    /// limit set by [`CommonState::set_buffer_limit`] is reached this
+    /// fails with [`Error::General`].  The part of `buf` accepted before
+    /// the limit was reached is still queued for [`write_tls`], so
+    /// nothing accepted is dropped.
+    ///
+    /// [`write_plaintext`]: ConnectionCommon::write_plaintext
+    /// [`write_tls`]: CommonState::write_tls
+    pub async fn write_all_plaintext(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            let written = self.write_plaintext(&buf[pos..]).await?;
+            // Move the records out of the backend, so the limit counts
+            // them on the next pass and none are left behind on error.
+            self.core.common_state.queue_backend_records().await?;
+            if written == 0 {
+                return Err(Error::General("plaintext would exceed buffer limit".into()));
+            }
+            pos += written;
+        }
+        Ok(pos)
+    }
+
+    /// Encrypt any application data held back by
+    /// [`ClientConfig::coalesce_writes`], and queue the resulting
+    /// records for [`CommonState::write_tls`].
+    ///
+    /// This does nothing if write coalescing is disabled, or if the
+    /// handshake has not yet completed.
+    ///
+    /// [`ClientConfig::coalesce_writes`]: crate::ClientConfig::coalesce_writes
+    pub async fn flush_plaintext(&mut self) -> Result<(), Error> {
+        future::poll_fn(|cx| self.poll_flush_plaintext(cx)).await
+    }
+
+    /// Queues a close_notify alert, like [`CommonState::send_close_notify`],
+    /// and moves it out of the backend for [`CommonState::write_tls`].
+    pub async fn send_close_notify(&mut self) -> Result<(), Error> {
+        future::poll_fn(|cx| self.poll_send_close_notify(cx)).await
+    }
+
+    /// Read TLS content from `rd`.  This method does internal
+    /// buffering, so `rd` can supply TLS messages in arbitrary-
+    /// sized chunks (like a socket or pipe might).
+    ///
+    /// You should call [`process_new_packets`] each time a call to
+    /// this function succeeds.
+    ///
+    /// The returned error only relates to IO on `rd`.  TLS-level
+    /// errors are emitted from [`process_new_packets`].
+    ///
+    /// This function returns `Ok(0)` when the underlying `rd` does
+    /// so.  This typically happens when a socket is cleanly closed,
+    /// or a file is at EOF.
+    ///
+    /// [`process_new_packets`]: Connection::process_new_packets
+    pub fn read_tls(&mut self, rd: &mut dyn io::Read) -> Result<usize, io::Error> {
+        let complete = self.message_deframer.frames.len();
+        let res = self.message_deframer.read(rd);
+        if let Ok(0) = res {
+            self.core.common_state.has_seen_eof = true;
+        }
+        self.observe_read_records(complete, &res);
+        res
+    }
+
+    /// Read TLS content from `rd`.  This method does internal
+    /// buffering, so `rd` can supply TLS messages in arbitrary-
+    /// sized chunks (like a socket or pipe might).
+    ///
+    /// You should call [`process_new_packets`] each time a call to
+    /// this function succeeds.
+    ///
+    /// The returned error only relates to IO on `rd`.  TLS-level
+    /// errors are emitted from [`process_new_packets`].
+    ///
+    /// This function returns `Ok(0)` when the underlying `rd` does
+    /// so.  This typically happens when a socket is cleanly closed,
+    /// or a file is at EOF.
+    ///
+    /// [`process_new_packets`]: Connection::process_new_packets
+    pub async fn read_tls_async<T: AsyncRead + Unpin>(
+        &mut self,
+        rd: &mut T,
+    ) -> Result<usize, io::Error> {
+        let complete = self.message_deframer.frames.len();
+        let res = self.message_deframer.read_async(rd).await;
+        if let Ok(0) = res {
+            self.core.common_state.has_seen_eof = true;
+        }
+        self.observe_read_records(complete, &res);
+        res
+    }
+
+    /// Read TLS content already held in `buf`, such as bytes received
+    /// into a caller-owned ring buffer.
+    ///
+    /// Like [`read_tls`], but without an `io::Read` to read from.  It
+    /// takes as much of `buf` as it can and returns how many bytes that
+    /// was; that is all of them unless the peer is found not to be
+    /// talking TLS, which [`process_new_packets`] then reports.  An empty
+    /// `buf` is not taken to mean EOF.
+    ///
+    /// Fails with [`Error::CorruptMessage`] if a previous read already
+    /// found the peer is not talking TLS.
+    ///
+    /// [`read_tls`]: Connection::read_tls
+    /// [`process_new_packets`]: Connection::process_new_packets
+    pub fn read_tls_from_slice(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if self.message_deframer.desynced {
+            return Err(Error::CorruptMessage);
+        }
+        let complete = self.message_deframer.frames.len();
+        let len = self.message_deframer.read_slice(buf);
+        self.observe_read_records(complete, &Ok(len));
+        Ok(len)
+    }
+
+    /// Counts the bytes read by `res`, and reports the records deframed
+    /// since there were `complete` of them.
+    fn observe_read_records(&mut self, complete: usize, res: &Result<usize, io::Error>) {
+        let stats = &mut self.core.common_state.stats;
+        if let Ok(len) = res {
+            stats.tls_bytes_read += *len as u64;
+        }
+        stats.records_received += (self.message_deframer.frames.len() - complete) as u64;
+
+        if let Some(observer) = &self.core.common_state.record_observer {
+            for msg in self.message_deframer.frames.iter().skip(complete) {
+                observer.on_record(Direction::Inbound, msg.typ, msg.payload.0.len());
+            }
+        }
+    }
+
+    /// Derives key material from the agreed connection secrets.
+    ///
+    /// This function fills in `output` with `output.len()` bytes of key
+    /// material derived from the master session secret using `label`
+    /// and `context` for diversification.
+    ///
+    /// See RFC5705 for more details on what this does and is for.
+    ///
+    /// For TLS1.3 connections, this function does not use the
+    /// "early" exporter at any point.
+    ///
+    /// This function fails if called prior to the handshake completing;
+    /// check with [`CommonState::is_handshaking`] first.
+    pub fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        match self.core.state.as_ref() {
+            Ok(st) => st.export_keying_material(output, label, context),
+            Err(e) => Err(e.clone()),
+        }
+    }
+}
+
+impl ConnectionCore {
     async fn process_incoming_opaque(
         &mut self,
         msg: OpaqueMessage,
@@ -379,26 +1098,85 @@ impl ConnectionCommon {
         }
     }
 
+    /// Alerts the peer that the records it sent could not be deframed,
+    /// for strict record parsing, and returns the error to report.
+    async fn desynced_error(&mut self, reason: Option<(AlertDescription, &str)>) -> Error {
+        let (alert, reason) = match reason {
+            Some(reason) => reason,
+            None => return Error::CorruptMessage,
+        };
+
+        if let Err(e) = self.common_state.send_fatal_alert(alert).await {
+            return e;
+        }
+        Error::PeerMisbehavedError(format!("received record with {}", reason))
+    }
+
+    /// Checks a record's header against what we expect at this point of
+    /// the connection, for strict record parsing.
+    async fn check_record(&mut self, msg: &OpaqueMessage) -> Result<(), Error> {
+        // Once a version is negotiated, all records carry the TLS1.2 legacy
+        // version, whether we negotiated TLS1.2 or TLS1.3.
+        let version_ok = match self.common_state.negotiated_version {
+            Some(_) => msg.version == ProtocolVersion::TLSv1_2,
+            None => matches!(
+                msg.version,
+                ProtocolVersion::TLSv1_0 | ProtocolVersion::TLSv1_1 | ProtocolVersion::TLSv1_2
+            ),
+        };
+        if !version_ok {
+            self.common_state
+                .send_fatal_alert(AlertDescription::ProtocolVersion)
+                .await?;
+            return Err(Error::PeerMisbehavedError(format!(
+                "received record with unexpected version {:?}",
+                msg.version
+            )));
+        }
+
+        if !self.common_state.record_layer.is_decrypting() && msg.payload.0.len() > MAX_FRAGMENT_LEN
+        {
+            self.common_state
+                .send_fatal_alert(AlertDescription::RecordOverflow)
+                .await?;
+            return Err(Error::PeerMisbehavedError(
+                "received plaintext record exceeding maximum length".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
     async fn process_incoming_plain(
         &mut self,
         msg: PlainMessage,
         state: Box<dyn State<ClientConnectionData>>,
     ) -> Result<Box<dyn State<ClientConnectionData>>, Error> {
+        // Handshake messages must not be interleaved with other records.
+        if self.common_state.strict_record_parsing
+            && msg.typ != ContentType::Handshake
+            && !self.handshake_joiner.is_empty()
+        {
+            self.common_state
+                .send_fatal_alert(AlertDescription::UnexpectedMessage)
+                .await?;
+            return Err(Error::PeerMisbehavedError(format!(
+                "received {:?} record while a handshake message was incomplete",
+                msg.typ
+            )));
+        }
+
         // For handshake messages, we need to join them before parsing
         // and processing.
         if self.handshake_joiner.want_message(&msg) {
             // First decryptable handshake message concludes trial decryption
             self.common_state.record_layer.finish_trial_decryption();
 
-            match self.handshake_joiner.take_message(msg) {
-                Some(_) => {}
-                None => {
-                    self.common_state
-                        .send_fatal_alert(AlertDescription::DecodeError)
-                        .await?;
-                    return Err(Error::CorruptMessagePayload(ContentType::Handshake));
-                }
-            }
+            let joined = match self.handshake_joiner.take_message(msg) {
+                Some(joined) => joined,
+                None => return Err(self.handshake_joiner_error().await),
+            };
+            self.check_handshake_fragments(joined).await?;
             return self.process_new_handshake_messages(state).await;
         }
 
@@ -411,40 +1189,97 @@ impl ConnectionCommon {
             return Ok(state);
         }
 
-        self.common_state
-            .process_main_protocol(msg, state, &mut self.data)
-            .await
+        self.common_state
+            .process_main_protocol(msg, state, &mut self.data)
+            .await
+    }
+
+    /// Alerts the peer after the handshake joiner rejected its data, and
+    /// returns the error to report.
+    async fn handshake_joiner_error(&mut self) -> Error {
+        if let Err(e) = self
+            .common_state
+            .send_fatal_alert(AlertDescription::DecodeError)
+            .await
+        {
+            return e;
+        }
+
+        if self.handshake_joiner.is_message_too_large() {
+            Error::HandshakeMessageTooLarge
+        } else {
+            Error::CorruptMessagePayload(ContentType::Handshake)
+        }
+    }
+
+    /// Counts the records the pending handshake message has been split
+    /// across, given that the latest one completed `joined` messages.
+    async fn check_handshake_fragments(&mut self, joined: usize) -> Result<(), Error> {
+        self.handshake_fragments = match (self.handshake_joiner.is_empty(), joined) {
+            // Nothing is pending.
+            (true, _) => 0,
+            // The pending message continued into this record.
+            (false, 0) => self.handshake_fragments + 1,
+            // The pending message started in this record.
+            (false, _) => 1,
+        };
+
+        if self.handshake_fragments > self.common_state.max_handshake_fragments {
+            self.common_state
+                .send_fatal_alert(AlertDescription::UnexpectedMessage)
+                .await?;
+            return Err(Error::PeerMisbehavedError(format!(
+                "handshake message split across more than {} records",
+                self.common_state.max_handshake_fragments
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn process_new_handshake_messages(
+        &mut self,
+        mut state: Box<dyn State<ClientConnectionData>>,
+    ) -> Result<Box<dyn State<ClientConnectionData>>, Error> {
+        self.common_state.aligned_handshake = self.handshake_joiner.is_empty();
+        while let Some(msg) = self.handshake_joiner.frames.pop_front() {
+            self.common_state.add_to_transcript(&msg);
+            state = self
+                .common_state
+                .process_main_protocol(msg, state, &mut self.data)
+                .await?;
+        }
+
+        Ok(state)
     }
 
-    /// Returns a notification future which resolves when the backend has
-    /// messages ready to decrypt.
-    pub async fn get_notify(&mut self) -> Result<BackendNotify, Error> {
-        self.common_state
-            .backend
-            .get_notify()
-            .await
-            .map_err(Error::from)
+    /// Returns a core for the connection to hold while this one is
+    /// owned by a suspended operation.
+    fn placeholder(&self) -> Self {
+        let common_state = CommonState::new(
+            None,
+            self.common_state.side,
+            Box::new(RustCryptoBackend::new()),
+        )
+        .expect("no maximum fragment size is always valid");
+        Self {
+            state: Err(Error::HandshakeNotComplete),
+            data: self.data.placeholder(),
+            common_state,
+            handshake_joiner: HandshakeJoiner::new(),
+            handshake_fragments: 0,
+        }
     }
 
-    /// Processes any new packets read by a previous call to
-    /// [`Connection::read_tls`].
-    ///
-    /// Errors from this function relate to TLS protocol errors, and
-    /// are fatal to the connection.  Future calls after an error will do
-    /// no new work and will return the same error. After an error is
-    /// received from [`process_new_packets`], you should not call [`read_tls`]
-    /// any more (it will fill up buffers to no purpose). However, you
-    /// may call the other methods on the connection, including `write`,
-    /// `send_close_notify`, and `write_tls`. Most likely you will want to
-    /// call `write_tls` to send any alerts queued by the error and then
-    /// close the underlying connection.
-    ///
-    /// Success from this function comes with some sundry state data
-    /// about the connection.
-    ///
-    /// [`read_tls`]: Connection::read_tls
-    /// [`process_new_packets`]: Connection::process_new_packets
-    pub async fn process_new_packets(&mut self) -> Result<IoState, Error> {
+    /// Processes `frames`, the records taken from the deframer, or
+    /// fails if it desynced for the reason in `desync`.  Returns whether
+    /// any progress was made.
+    async fn process_frames(
+        &mut self,
+        mut frames: VecDeque<OpaqueMessage>,
+        desync: Option<Option<(AlertDescription, &'static str)>>,
+    ) -> Result<bool, Error> {
+        self.common_state.stats.process_new_packets_calls += 1;
         let mut state = match mem::replace(&mut self.state, Err(Error::HandshakeNotComplete)) {
             Ok(state) => state,
             Err(e) => {
@@ -453,12 +1288,34 @@ impl ConnectionCommon {
             }
         };
 
-        if self.message_deframer.desynced {
+        if let Some(reason) = desync {
+            if self.common_state.strict_record_parsing {
+                let err = self.desynced_error(reason).await;
+                self.state = Err(err.clone());
+                return Err(err);
+            }
             return Err(Error::CorruptMessage);
         }
 
         // Process new messages.
-        while let Some(msg) = self.message_deframer.frames.pop_front() {
+        let processed = frames.len();
+        while let Some(msg) = frames.pop_front() {
+            self.common_state.observe_record_version(msg.version);
+            #[cfg(feature = "notary")]
+            self.common_state.notary_log.add_record(
+                RecordDirection::Received,
+                msg.typ,
+                msg.payload.0.len(),
+                self.common_state.record_layer.is_decrypting(),
+            );
+
+            if self.common_state.strict_record_parsing {
+                if let Err(e) = self.check_record(&msg).await {
+                    self.state = Err(e.clone());
+                    return Err(e);
+                }
+            }
+
             // If we're not decrypting yet, we process it immediately. Otherwise it will be
             // pushed to the backend.
             if let Some(plain) = self.process_incoming_opaque(msg).await? {
@@ -471,10 +1328,12 @@ impl ConnectionCommon {
                 }
             }
         }
-        self.backend.flush().await?;
+        self.common_state.backend.flush().await?;
 
         // Process pending decrypted messages.
-        while let Some(msg) = self.backend.next_incoming().await? {
+        let mut made_progress = processed > 0;
+        while let Some(msg) = self.common_state.backend.next_incoming().await? {
+            made_progress = true;
             match self.process_incoming_plain(msg, state).await {
                 Ok(new) => state = new,
                 Err(e) => {
@@ -484,123 +1343,25 @@ impl ConnectionCommon {
             }
         }
 
-        while let Some(msg) = self.backend.next_outgoing().await? {
-            self.queue_tls_message(msg);
+        while let Some(msg) = self.common_state.backend.next_outgoing().await? {
+            made_progress = true;
+            self.common_state.queue_tls_message(msg);
         }
 
         self.state = Ok(state);
-
-        Ok(self.common_state.current_io_state())
-    }
-
-    async fn process_new_handshake_messages(
-        &mut self,
-        mut state: Box<dyn State<ClientConnectionData>>,
-    ) -> Result<Box<dyn State<ClientConnectionData>>, Error> {
-        self.common_state.aligned_handshake = self.handshake_joiner.is_empty();
-        while let Some(msg) = self.handshake_joiner.frames.pop_front() {
-            state = self
-                .common_state
-                .process_main_protocol(msg, state, &mut self.data)
-                .await?;
-        }
-
-        Ok(state)
-    }
-
-    /// Write buffer into connection.
-    pub async fn write_plaintext(&mut self, buf: &[u8]) -> Result<usize, Error> {
-        if let Ok(st) = &mut self.state {
-            st.perhaps_write_key_update(&mut self.common_state).await;
-        }
-        self.common_state.send_some_plaintext(buf).await
-    }
-
-    /// Write entire buffer into connection.
-    pub async fn write_all_plaintext(&mut self, buf: &[u8]) -> Result<usize, Error> {
-        let mut pos = 0;
-        while pos < buf.len() {
-            pos += self.write_plaintext(&buf[pos..]).await?;
-        }
-        self.backend.flush().await?;
-        while let Some(msg) = self.backend.next_outgoing().await? {
-            self.queue_tls_message(msg);
-        }
-        Ok(pos)
-    }
-
-    /// Read TLS content from `rd`.  This method does internal
-    /// buffering, so `rd` can supply TLS messages in arbitrary-
-    /// sized chunks (like a socket or pipe might).
-    ///
-    /// You should call [`process_new_packets`] each time a call to
-    /// this function succeeds.
-    ///
-    /// The returned error only relates to IO on `rd`.  TLS-level
-    /// errors are emitted from [`process_new_packets`].
-    ///
-    /// This function returns `Ok(0)` when the underlying `rd` does
-    /// so.  This typically happens when a socket is cleanly closed,
-    /// or a file is at EOF.
-    ///
-    /// [`process_new_packets`]: Connection::process_new_packets
-    pub fn read_tls(&mut self, rd: &mut dyn io::Read) -> Result<usize, io::Error> {
-        let res = self.message_deframer.read(rd);
-        if let Ok(0) = res {
-            self.common_state.has_seen_eof = true;
-        }
-        res
+        Ok(made_progress)
     }
 
-    /// Read TLS content from `rd`.  This method does internal
-    /// buffering, so `rd` can supply TLS messages in arbitrary-
-    /// sized chunks (like a socket or pipe might).
-    ///
-    /// You should call [`process_new_packets`] each time a call to
-    /// this function succeeds.
-    ///
-    /// The returned error only relates to IO on `rd`.  TLS-level
-    /// errors are emitted from [`process_new_packets`].
-    ///
-    /// This function returns `Ok(0)` when the underlying `rd` does
-    /// so.  This typically happens when a socket is cleanly closed,
-    /// or a file is at EOF.
-    ///
-    /// [`process_new_packets`]: Connection::process_new_packets
-    pub async fn read_tls_async<T: AsyncRead + Unpin>(
-        &mut self,
-        rd: &mut T,
-    ) -> Result<usize, io::Error> {
-        let res = self.message_deframer.read_async(rd).await;
-        if let Ok(0) = res {
-            self.common_state.has_seen_eof = true;
-        }
-        res
+    /// Encrypts buffered plaintext, as by [`ConnectionCommon::flush_plaintext`].
+    async fn flush_plaintext(&mut self) -> Result<(), Error> {
+        self.common_state.flush_plaintext().await?;
+        self.common_state.queue_backend_records().await
     }
 
-    /// Derives key material from the agreed connection secrets.
-    ///
-    /// This function fills in `output` with `output.len()` bytes of key
-    /// material derived from the master session secret using `label`
-    /// and `context` for diversification.
-    ///
-    /// See RFC5705 for more details on what this does and is for.
-    ///
-    /// For TLS1.3 connections, this function does not use the
-    /// "early" exporter at any point.
-    ///
-    /// This function fails if called prior to the handshake completing;
-    /// check with [`CommonState::is_handshaking`] first.
-    pub fn export_keying_material(
-        &self,
-        output: &mut [u8],
-        label: &[u8],
-        context: Option<&[u8]>,
-    ) -> Result<(), Error> {
-        match self.state.as_ref() {
-            Ok(st) => st.export_keying_material(output, label, context),
-            Err(e) => Err(e.clone()),
-        }
+    /// Queues a close_notify alert, as by [`ConnectionCommon::send_close_notify`].
+    async fn send_close_notify(&mut self) -> Result<(), Error> {
+        self.common_state.send_close_notify().await?;
+        self.common_state.queue_backend_records().await
     }
 }
 
@@ -608,13 +1369,13 @@ impl Deref for ConnectionCommon {
     type Target = CommonState;
 
     fn deref(&self) -> &Self::Target {
-        &self.common_state
+        &self.core.common_state
     }
 }
 
 impl DerefMut for ConnectionCommon {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.common_state
+        &mut self.core.common_state
     }
 }
 
@@ -638,12 +1399,41 @@ pub struct CommonState {
     received_middlebox_ccs: u8,
     pub(crate) peer_certificates: Option<Vec<tls_core::key::Certificate>>,
     message_fragmenter: MessageFragmenter,
+    /// The most plaintext the peer accepts in a record, from its
+    /// `record_size_limit` extension.
+    pub(crate) record_size_limit: Option<usize>,
     received_plaintext: ChunkVecBuffer,
     sendable_plaintext: ChunkVecBuffer,
     pub(crate) sendable_tls: ChunkVecBuffer,
     #[allow(dead_code)]
     /// Protocol whose key schedule should be used. Unused for TLS < 1.3.
     pub(crate) protocol: Protocol,
+    /// Whether unexpected handshake messages after the handshake are fatal.
+    pub(crate) strict_post_handshake: bool,
+    /// Whether malformed or unexpected record framing is reported precisely.
+    pub(crate) strict_record_parsing: bool,
+    /// Whether application data is held back until flushed.
+    pub(crate) coalesce_writes: bool,
+    /// The last alert we queued, until it has been written out.
+    pending_alert: Option<AlertDescription>,
+    /// The most records one handshake message may be split across.
+    pub(crate) max_handshake_fragments: usize,
+    /// The largest handshake message body we accept, if limited.
+    pub(crate) max_handshake_message_size: Option<usize>,
+    /// Told about every record read or written, if set.
+    pub(crate) record_observer: Option<Arc<dyn RecordObserver>>,
+    /// Whether EOF without close_notify is reported like a clean close.
+    pub(crate) allow_unclean_eof: bool,
+    /// Distinct legacy versions seen in incoming record headers.
+    observed_record_versions: Vec<ProtocolVersion>,
+    /// Traffic counters.
+    stats: ConnectionStats,
+    /// The handshake messages sent and received until the handshake
+    /// completed, in order.
+    pub(crate) handshake_transcript: Vec<u8>,
+    /// Record boundaries, for the notary bundle.
+    #[cfg(feature = "notary")]
+    pub(crate) notary_log: NotaryLog,
 }
 
 impl CommonState {
@@ -670,16 +1460,33 @@ impl CommonState {
             peer_certificates: None,
             message_fragmenter: MessageFragmenter::new(max_fragment_size)
                 .map_err(|_| Error::BadMaxFragmentSize)?,
+            record_size_limit: None,
             received_plaintext: ChunkVecBuffer::new(Some(0)),
             sendable_plaintext: ChunkVecBuffer::new(Some(DEFAULT_BUFFER_LIMIT)),
             sendable_tls: ChunkVecBuffer::new(Some(DEFAULT_BUFFER_LIMIT)),
 
             protocol: Protocol::Tcp,
+            strict_post_handshake: false,
+            strict_record_parsing: false,
+            coalesce_writes: false,
+            pending_alert: None,
+            max_handshake_fragments: usize::MAX,
+            max_handshake_message_size: Some(0xffff),
+            record_observer: None,
+            allow_unclean_eof: false,
+            observed_record_versions: Vec::new(),
+            stats: ConnectionStats::default(),
+            handshake_transcript: Vec::new(),
+            #[cfg(feature = "notary")]
+            notary_log: NotaryLog::default(),
         })
     }
 
     /// Returns true if the caller should call [`CommonState::write_tls`] as
     /// soon as possible.
+    ///
+    /// See [`CommonState::wants_read`] for how the two change over the
+    /// life of a connection.
     pub fn wants_write(&self) -> bool {
         !self.sendable_tls.is_empty()
     }
@@ -720,6 +1527,17 @@ impl CommonState {
         self.peer_certificates.as_deref()
     }
 
+    /// Retrieves the DER encodings of the certificate chain used by the
+    /// peer, in the same order as [`Self::peer_certificates`].
+    ///
+    /// The encodings are borrowed from the connection, so nothing is
+    /// copied.  The return value is None until this value is available.
+    pub fn peer_certificates_der(&self) -> Option<impl ExactSizeIterator<Item = &[u8]>> {
+        self.peer_certificates
+            .as_ref()
+            .map(|certs| certs.iter().map(|cert| cert.0.as_slice()))
+    }
+
     /// Retrieves the protocol agreed with the peer via ALPN.
     ///
     /// A return value of `None` after handshake completion
@@ -731,18 +1549,75 @@ impl CommonState {
 
     /// Retrieves the ciphersuite agreed with the peer.
     ///
-    /// This returns None until the ciphersuite is agreed.
+    /// This returns None until the ciphersuite is agreed.  The suite
+    /// is agreed as soon as the server's ServerHello is processed, so
+    /// this is available while [`is_handshaking`] is still true, before
+    /// the server's Finished has been checked.
+    ///
+    /// [`is_handshaking`]: CommonState::is_handshaking
     pub fn negotiated_cipher_suite(&self) -> Option<SupportedCipherSuite> {
         self.suite
     }
 
     /// Retrieves the protocol version agreed with the peer.
     ///
-    /// This returns `None` until the version is agreed.
+    /// This returns `None` until the version is agreed.  Like
+    /// [`negotiated_cipher_suite`], it is available once the server's
+    /// ServerHello is processed, while still handshaking.
+    ///
+    /// [`negotiated_cipher_suite`]: CommonState::negotiated_cipher_suite
     pub fn protocol_version(&self) -> Option<ProtocolVersion> {
         self.negotiated_version
     }
 
+    /// Returns counters of the bytes and records this connection has
+    /// read and written so far.
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats
+    }
+
+    /// Returns how many records have been encrypted under our current
+    /// traffic key.
+    ///
+    /// One record before the confidentiality limit of the negotiated
+    /// cipher a close_notify alert is sent, and writing after that fails
+    /// with [`Error::RecordLimitReached`].  The client never updates its
+    /// keys, so this count only grows.
+    pub fn record_encryption_count(&self) -> u64 {
+        self.record_layer.write_seq()
+    }
+
+    /// Returns how many records have been decrypted under the server's
+    /// current traffic key.
+    ///
+    /// Records beyond the integrity limit of the negotiated cipher fail
+    /// with [`Error::RecordLimitReached`].
+    pub fn record_decryption_count(&self) -> u64 {
+        self.record_layer.read_seq()
+    }
+
+    /// Returns how much plaintext we put in each record we send.
+    ///
+    /// Once records are encrypted, this leaves room for the negotiated
+    /// suite's overhead so whole records fit within
+    /// [`ClientConfig::max_fragment_size`](crate::ClientConfig::max_fragment_size).
+    /// It is no more than the server's record size limit either, if it
+    /// sent one.
+    pub fn max_plaintext_per_record(&self) -> usize {
+        let mut len = self.message_fragmenter.max_fragment_len();
+        if self.message_fragmenter.max_fragment_size().is_some()
+            && self.record_layer.is_encrypting()
+        {
+            if let Some(suite) = self.suite {
+                len = len.saturating_sub(suite.record_overhead()).max(1);
+            }
+        }
+        match self.record_size_limit {
+            Some(limit) => len.min(limit),
+            None => len,
+        }
+    }
+
     pub(crate) fn is_tls13(&self) -> bool {
         matches!(self.negotiated_version, Some(ProtocolVersion::TLSv1_3))
     }
@@ -753,6 +1628,27 @@ impl CommonState {
         mut state: Box<dyn State<ClientConnectionData>>,
         data: &mut ClientConnectionData,
     ) -> Result<Box<dyn State<ClientConnectionData>>, Error> {
+        // In strict mode, only a handful of handshake messages may arrive
+        // once the handshake is complete.
+        if self.may_receive_application_data && self.strict_post_handshake {
+            if let MessagePayload::Handshake(hs) = &msg.payload {
+                if !matches!(
+                    hs.typ,
+                    HandshakeType::NewSessionTicket
+                        | HandshakeType::KeyUpdate
+                        | HandshakeType::CertificateRequest
+                ) {
+                    let typ = hs.typ;
+                    self.send_fatal_alert(AlertDescription::UnexpectedMessage)
+                        .await?;
+                    return Err(Error::PeerMisbehavedError(format!(
+                        "received unexpected post-handshake {:?} message",
+                        typ
+                    )));
+                }
+            }
+        }
+
         // For TLS1.2, outside of the handshake, send rejection alerts for
         // renegotiation requests.  These can occur any time.
         if self.may_receive_application_data && !self.is_tls13() {
@@ -818,6 +1714,11 @@ impl CommonState {
             self.send_close_notify().await?;
         }
 
+        // The peer's key can no longer be trusted to authenticate records.
+        if self.record_layer.integrity_exhausted() {
+            return Err(Error::RecordLimitReached);
+        }
+
         self.record_layer
             .decrypt_incoming(self.backend.as_mut(), encr)
             .await?;
@@ -846,19 +1747,31 @@ impl CommonState {
     /// the encrypted fragments for sending.
     pub(crate) async fn send_msg_encrypt(&mut self, m: PlainMessage) -> Result<(), Error> {
         let mut plain_messages = VecDeque::new();
-        self.message_fragmenter.fragment(m, &mut plain_messages);
+        self.message_fragmenter.fragment_to(
+            m,
+            self.max_plaintext_per_record(),
+            &mut plain_messages,
+        );
+
+        for m in plain_messages {
+            self.perhaps_close_before_encrypt().await?;
+            self.send_single_fragment(m).await?;
+        }
+        Ok(())
+    }
 
-        // Close connection once we start to run out of
-        // sequence space.
+    /// Close connection once we start to run out of sequence space, or
+    /// near the confidentiality limit of our key.
+    ///
+    /// A TLS1.3 peer would send a KeyUpdate here instead, but the backend
+    /// cannot move on to the next traffic secret, so the client closes
+    /// whichever version was negotiated.
+    async fn perhaps_close_before_encrypt(&mut self) -> Result<(), Error> {
         if self.record_layer.wants_close_before_encrypt() {
             debug!("Sending warning alert {:?}", AlertDescription::CloseNotify);
             let m = Message::build_alert(AlertLevel::Warning, AlertDescription::CloseNotify);
             self.send_single_fragment(m.into()).await?;
         }
-
-        for m in plain_messages {
-            self.send_single_fragment(m).await?;
-        }
         Ok(())
     }
 
@@ -874,18 +1787,21 @@ impl CommonState {
         };
 
         let mut plain_messages = VecDeque::new();
-        self.message_fragmenter.fragment(
+        self.message_fragmenter.fragment_to(
             PlainMessage {
                 typ: ContentType::ApplicationData,
                 version: ProtocolVersion::TLSv1_2,
                 payload: Payload::new(&payload[..len]),
             },
+            self.max_plaintext_per_record(),
             &mut plain_messages,
         );
 
         for m in plain_messages {
+            self.perhaps_close_before_encrypt().await?;
             self.send_single_fragment(m).await?;
         }
+        self.stats.plaintext_bytes_written += len as u64;
 
         Ok(len)
     }
@@ -896,6 +1812,9 @@ impl CommonState {
         if self.record_layer.encrypt_exhausted() {
             return Err(Error::EncryptError);
         }
+        if self.record_layer.confidentiality_exhausted() {
+            return Err(Error::RecordLimitReached);
+        }
 
         self.record_layer
             .encrypt_outgoing(self.backend.as_mut(), m)
@@ -913,7 +1832,12 @@ impl CommonState {
     /// flushed. The [`CommonState::wants_write`] function can be used to
     /// check if the output buffer is empty.
     pub fn write_tls(&mut self, wr: &mut dyn io::Write) -> Result<usize, io::Error> {
-        self.sendable_tls.write_to(wr)
+        let len = self.sendable_tls.write_to(wr)?;
+        self.stats.tls_bytes_written += len as u64;
+        if self.sendable_tls.is_empty() {
+            self.pending_alert = None;
+        }
+        Ok(len)
     }
 
     /// Writes TLS messages to `wr`.
@@ -928,7 +1852,12 @@ impl CommonState {
         &mut self,
         wr: &mut T,
     ) -> Result<usize, io::Error> {
-        self.sendable_tls.write_to_async(wr).await
+        let len = self.sendable_tls.write_to_async(wr).await?;
+        self.stats.tls_bytes_written += len as u64;
+        if self.sendable_tls.is_empty() {
+            self.pending_alert = None;
+        }
+        Ok(len)
     }
 
     /// Encrypt and send some plaintext `data`.  `limit` controls
@@ -954,12 +1883,67 @@ impl CommonState {
             return Ok(0);
         }
 
+        if self.coalesce_writes {
+            // Hold the data back so that it shares a record with whatever
+            // is written next; a full record's worth is sent straight away.
+            let len = match limit {
+                Limit::Yes => self.sendable_plaintext.append_limited_copy(data),
+                Limit::No => self.sendable_plaintext.append(data.to_vec()),
+            };
+            if len < data.len() || self.sendable_plaintext.len() >= MAX_FRAGMENT_LEN {
+                self.send_coalesced().await?;
+            }
+            return Ok(len);
+        }
+
         self.send_appdata_encrypt(data, limit).await
     }
 
+    /// Buffers as much of `data` as the limit set by
+    /// [`Self::set_buffer_limit`] allows, for
+    /// [`ConnectionCommon::flush_plaintext`] to encrypt.  Returns the
+    /// number of bytes buffered.
+    pub(crate) fn buffer_plaintext(&mut self, data: &[u8]) -> usize {
+        let len = self.sendable_tls.apply_limit(data.len());
+        self.sendable_plaintext.append_limited_copy(&data[..len])
+    }
+
+    /// Queues the records the backend has encrypted for
+    /// [`CommonState::write_tls`].
+    async fn queue_backend_records(&mut self) -> Result<(), Error> {
+        self.backend.flush().await?;
+        while let Some(msg) = self.backend.next_outgoing().await? {
+            self.queue_tls_message(msg);
+        }
+        Ok(())
+    }
+
+    /// Encrypt all buffered plaintext as few records as possible.
+    async fn send_coalesced(&mut self) -> Result<(), Error> {
+        let mut data = Chunk::default();
+        data.reserve(self.sendable_plaintext.len());
+        while let Some(buf) = self.sendable_plaintext.pop() {
+            data.extend_from_slice(&buf);
+        }
+
+        if !data.is_empty() {
+            self.send_appdata_encrypt(&data, Limit::No).await?;
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn start_outgoing_traffic(&mut self) -> Result<(), Error> {
         self.may_send_application_data = true;
-        self.flush_plaintext().await
+        if self.sendable_plaintext.is_empty() {
+            return Ok(());
+        }
+
+        // Encrypt what was written during the handshake now, so that it
+        // is sent without waiting for a `flush_plaintext`.
+        self.flush_plaintext().await?;
+        self.backend.flush().await?;
+        Ok(())
     }
 
     pub(crate) async fn start_traffic(&mut self) -> Result<(), Error> {
@@ -1012,6 +1996,17 @@ impl CommonState {
         self.sendable_tls.set_limit(limit);
     }
 
+    /// Returns how many of `len` bytes of plaintext
+    /// [`Self::send_some_plaintext`] would accept under the limit set by
+    /// [`Self::set_buffer_limit`].
+    pub(crate) fn apply_plaintext_limit(&self, len: usize) -> usize {
+        if !self.may_send_application_data || self.coalesce_writes {
+            self.sendable_plaintext.apply_limit(len)
+        } else {
+            self.sendable_tls.apply_limit(len)
+        }
+    }
+
     /// Send any buffered plaintext.  Plaintext is buffered if
     /// written during handshake.
     async fn flush_plaintext(&mut self) -> Result<(), Error> {
@@ -1019,6 +2014,10 @@ impl CommonState {
             return Ok(());
         }
 
+        if self.coalesce_writes {
+            return self.send_coalesced().await;
+        }
+
         while let Some(buf) = self.sendable_plaintext.pop() {
             self.send_plain(&buf, Limit::No).await?;
         }
@@ -1026,16 +2025,44 @@ impl CommonState {
         Ok(())
     }
 
+    /// Appends `m` to the handshake transcript, if it is a handshake
+    /// message and the handshake has not completed.
+    fn add_to_transcript(&mut self, m: &Message) {
+        if !self.is_handshaking() {
+            return;
+        }
+        if let MessagePayload::Handshake(hs) = &m.payload {
+            hs.encode(&mut self.handshake_transcript);
+        }
+    }
+
     // Put m into sendable_tls for writing.
     pub(crate) fn queue_tls_message(&mut self, m: OpaqueMessage) {
+        #[cfg(feature = "notary")]
+        self.notary_log.add_record(
+            RecordDirection::Sent,
+            m.typ,
+            m.payload.0.len(),
+            self.record_layer.is_encrypting(),
+        );
+        if let Some(observer) = &self.record_observer {
+            observer.on_record(Direction::Outbound, m.typ, m.payload.0.len());
+        }
+        self.stats.records_sent += 1;
         self.sendable_tls.append(m.encode());
     }
 
     /// Send a raw TLS message, fragmenting it if needed.
     pub(crate) async fn send_msg(&mut self, m: Message, must_encrypt: bool) -> Result<(), Error> {
+        self.add_to_transcript(&m);
+
         if !must_encrypt {
             let mut to_send = VecDeque::new();
-            self.message_fragmenter.fragment(m.into(), &mut to_send);
+            self.message_fragmenter.fragment_to(
+                m.into(),
+                self.max_plaintext_per_record(),
+                &mut to_send,
+            );
             for mm in to_send {
                 self.queue_tls_message(mm.into_unencrypted_opaque());
             }
@@ -1046,6 +2073,7 @@ impl CommonState {
     }
 
     pub(crate) fn take_received_plaintext(&mut self, bytes: Payload) {
+        self.stats.plaintext_bytes_read += bytes.0.len() as u64;
         self.received_plaintext.append(bytes.0);
     }
 
@@ -1089,6 +2117,7 @@ impl CommonState {
         let m = Message::build_alert(AlertLevel::Fatal, desc);
         self.send_msg(m, self.record_layer.is_encrypting()).await?;
         self.sent_fatal_alert = true;
+        self.pending_alert = Some(desc);
         Ok(())
     }
 
@@ -1103,7 +2132,38 @@ impl CommonState {
 
     async fn send_warning_alert_no_log(&mut self, desc: AlertDescription) -> Result<(), Error> {
         let m = Message::build_alert(AlertLevel::Warning, desc);
-        self.send_msg(m, self.record_layer.is_encrypting()).await
+        self.send_msg(m, self.record_layer.is_encrypting()).await?;
+        self.pending_alert = Some(desc);
+        Ok(())
+    }
+
+    /// Returns the distinct legacy `version` values seen in the headers of
+    /// records received from the peer, in the order first seen.
+    ///
+    /// The record layer version is not meaningful to TLS itself, so
+    /// unusual values are accepted; they may however help identify
+    /// the peer's implementation.  At most eight distinct values are
+    /// kept.
+    pub fn observed_record_versions(&self) -> Vec<ProtocolVersion> {
+        self.observed_record_versions.clone()
+    }
+
+    fn observe_record_version(&mut self, version: ProtocolVersion) {
+        if self.observed_record_versions.len() < MAX_OBSERVED_RECORD_VERSIONS
+            && !self.observed_record_versions.contains(&version)
+        {
+            self.observed_record_versions.push(version);
+        }
+    }
+
+    /// Returns the description of the alert most recently queued for
+    /// sending, such as one sent after a certificate verification
+    /// failure.
+    ///
+    /// Returns `None` once the alert has been written out by
+    /// [`CommonState::write_tls`], or if no alert has been queued.
+    pub fn pending_alert(&self) -> Option<AlertDescription> {
+        self.pending_alert
     }
 
     pub(crate) fn set_max_fragment_size(&mut self, new: Option<usize>) -> Result<(), Error> {
@@ -1122,17 +2182,37 @@ impl CommonState {
     /// If there is pending plaintext data to read with [`Connection::reader`],
     /// this returns false.  If your application respects this mechanism,
     /// only one full TLS message will be buffered by rustls.
+    ///
+    /// Together with [`CommonState::wants_write`] this tells an event loop
+    /// which readiness to wait for, without using `complete_io`:
+    ///
+    /// * During the handshake, the two alternate.  While our flight is
+    ///   waiting in the send buffer this returns false and `wants_write`
+    ///   true; once it is written, this returns true until
+    ///   [`Connection::process_new_packets`] has processed the server's
+    ///   next flight, which usually queues ours.
+    /// * After the handshake, this is true whenever all received plaintext
+    ///   has been read, whatever is waiting to be written.  `wants_write`
+    ///   is true while records are waiting to be written, including those
+    ///   queued by `flush_plaintext`.
+    /// * Once the peer has sent close_notify, or `read_tls` has returned
+    ///   `Ok(0)`, this is false for good: nothing more can be read.
+    ///
+    /// So wait for readability when this is true and writability when
+    /// `wants_write` is, call `process_new_packets` after every successful
+    /// `read_tls`, and stop once both are false.
     pub fn wants_read(&self) -> bool {
         // We want to read more data all the time, except when we have unprocessed
         // plaintext. This provides back-pressure to the TCP buffers. We also
         // don't want to read more after the peer has sent us a close
-        // notification.
+        // notification, or after the transport reached EOF.
         //
         // In the handshake case we don't have readable plaintext before the handshake
         // has completed, but also don't want to read if we still have sendable
         // tls.
         self.received_plaintext.is_empty()
             && !self.has_received_close_notify
+            && !self.has_seen_eof
             && (self.may_send_application_data || self.sendable_tls.is_empty())
     }
 
@@ -1151,11 +2231,14 @@ impl CommonState {
         self.backend.as_mut()
     }
 
-    fn current_io_state(&self) -> IoState {
+    fn current_io_state(&self, made_progress: bool, has_unprocessed_records: bool) -> IoState {
         IoState {
             tls_bytes_to_write: self.sendable_tls.len(),
             plaintext_bytes_to_read: self.received_plaintext.len(),
+            plaintext_bytes_buffered: self.sendable_plaintext.len(),
             peer_has_closed: self.has_received_close_notify,
+            made_progress,
+            has_unprocessed_records,
         }
     }
 }
@@ -1201,3 +2284,5 @@ pub(crate) enum Side {
 pub trait SideData {}
 
 const DEFAULT_BUFFER_LIMIT: usize = 64 * 1024;
+/// The most distinct record versions [`CommonState::observed_record_versions`] keeps.
+const MAX_OBSERVED_RECORD_VERSIONS: usize = 8;
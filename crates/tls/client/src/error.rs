@@ -52,9 +52,19 @@ pub enum Error {
     /// The peer didn't give us any certificates.
     NoCertificatesPresented,
 
+    /// The server's certificate chain was not in the order TLS requires,
+    /// and [`ClientConfig::tolerate_unordered_chain`] was not set.
+    ///
+    /// [`ClientConfig::tolerate_unordered_chain`]: crate::ClientConfig::tolerate_unordered_chain
+    CertificateChainOutOfOrder,
+
     /// The certificate verifier doesn't support the given type of name.
     UnsupportedNameType,
 
+    /// The server selected a protocol version lower than the one
+    /// required for this connection.
+    UnsupportedProtocolVersion,
+
     /// The peer doesn't support a protocol version/feature we require.
     /// The parameter gives a hint as to what version/feature it is.
     PeerIncompatibleError(String),
@@ -103,6 +113,19 @@ pub enum Error {
     /// The `max_fragment_size` value supplied in configuration was too small,
     /// or too large.
     BadMaxFragmentSize,
+
+    /// The peer sent a handshake message larger than
+    /// [`ClientConfig::max_handshake_message_size`] allows.
+    ///
+    /// [`ClientConfig::max_handshake_message_size`]: crate::ClientConfig::max_handshake_message_size
+    HandshakeMessageTooLarge,
+
+    /// So many records have been sent or received under the current
+    /// traffic keys that the cipher's confidentiality or integrity limit
+    /// would be exceeded by another.
+    ///
+    /// Before this happens a close_notify alert is sent.
+    RecordLimitReached,
 }
 
 fn join<T: fmt::Debug>(items: &[T]) -> String {
@@ -160,7 +183,16 @@ impl fmt::Display for Error {
             }
             Self::CorruptMessage => write!(f, "received corrupt message"),
             Self::NoCertificatesPresented => write!(f, "peer sent no certificates"),
+            Self::CertificateChainOutOfOrder => {
+                write!(f, "peer sent certificate chain out of order")
+            }
             Self::UnsupportedNameType => write!(f, "presented server name type wasn't supported"),
+            Self::UnsupportedProtocolVersion => {
+                write!(
+                    f,
+                    "server selected a protocol version below the required minimum"
+                )
+            }
             Self::DecryptError => write!(f, "cannot decrypt peer's message"),
             Self::EncryptError => write!(f, "cannot encrypt message"),
             Self::PeerSentOversizedRecord => write!(f, "peer sent excess record size"),
@@ -172,6 +204,12 @@ impl fmt::Display for Error {
             Self::BadMaxFragmentSize => {
                 write!(f, "the supplied max_fragment_size was too small or large")
             }
+            Self::HandshakeMessageTooLarge => {
+                write!(f, "peer sent an oversized handshake message")
+            }
+            Self::RecordLimitReached => {
+                write!(f, "record limit of the traffic keys reached")
+            }
             Self::General(ref err) => write!(f, "unexpected error: {}", err),
         }
     }
@@ -227,6 +265,8 @@ mod tests {
             Error::CorruptMessage,
             Error::CorruptMessagePayload(ContentType::Alert),
             Error::NoCertificatesPresented,
+            Error::CertificateChainOutOfOrder,
+            Error::UnsupportedProtocolVersion,
             Error::PeerIncompatibleError("no tls1.2".to_string()),
             Error::PeerMisbehavedError("inconsistent something".to_string()),
             Error::AlertReceived(AlertDescription::ExportRestriction),
@@ -242,6 +282,8 @@ mod tests {
             Error::PeerSentOversizedRecord,
             Error::NoApplicationProtocol,
             Error::BadMaxFragmentSize,
+            Error::HandshakeMessageTooLarge,
+            Error::RecordLimitReached,
         ];
 
         for err in all {
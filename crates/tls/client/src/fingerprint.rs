@@ -0,0 +1,125 @@
+use crate::rand::{GetRandomFailed, SecureRandomProvider};
+use tls_core::msgs::{
+    enums::{CipherSuite, Compression, ExtensionType, NamedGroup},
+    handshake::ClientExtension,
+};
+
+/// A description of the shape of a ClientHello, as produced by some
+/// well-known client.
+///
+/// A profile bundles the cipher suite order, extension order, key
+/// exchange groups, compression methods and whether GREASE values
+/// are sent. Apply one with [`ConfigBuilder::with_profile`].
+///
+/// Only cipher suites and key exchange groups which this crate implements
+/// are offered; the rest of the profile's entries are skipped, but the
+/// relative order of those which remain is preserved.
+///
+/// [`ConfigBuilder::with_profile`]: crate::ConfigBuilder::with_profile
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientProfile {
+    /// A human readable name for this profile.
+    pub name: &'static str,
+
+    /// Cipher suites, in preference order.
+    pub cipher_suites: Vec<CipherSuite>,
+
+    /// The order in which ClientHello extensions are emitted.
+    ///
+    /// Extensions we send which aren't listed here follow the listed
+    /// ones, in their usual order.
+    pub extension_order: Vec<ExtensionType>,
+
+    /// Whether to send GREASE values (RFC 8701).
+    pub grease: bool,
+
+    /// Key exchange groups, in preference order.
+    pub kx_groups: Vec<NamedGroup>,
+
+    /// Compression methods to offer.  This must include
+    /// [`Compression::Null`], which is the only method we accept from
+    /// the server.
+    pub compression_methods: Vec<Compression>,
+}
+
+impl ClientProfile {
+    /// A profile resembling the ClientHello sent by recent Firefox releases.
+    pub fn firefox() -> Self {
+        Self {
+            name: "firefox",
+            cipher_suites: vec![
+                CipherSuite::TLS13_AES_128_GCM_SHA256,
+                CipherSuite::TLS13_CHACHA20_POLY1305_SHA256,
+                CipherSuite::TLS13_AES_256_GCM_SHA384,
+                CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+                CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+                CipherSuite::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
+                CipherSuite::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+                CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
+                CipherSuite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+            ],
+            extension_order: vec![
+                ExtensionType::ServerName,
+                ExtensionType::ExtendedMasterSecret,
+                ExtensionType::RenegotiationInfo,
+                ExtensionType::EllipticCurves,
+                ExtensionType::ECPointFormats,
+                ExtensionType::SessionTicket,
+                ExtensionType::ALProtocolNegotiation,
+                ExtensionType::StatusRequest,
+                ExtensionType::KeyShare,
+                ExtensionType::SupportedVersions,
+                ExtensionType::SignatureAlgorithms,
+                ExtensionType::PSKKeyExchangeModes,
+            ],
+            grease: false,
+            kx_groups: vec![
+                NamedGroup::X25519,
+                NamedGroup::secp256r1,
+                NamedGroup::secp384r1,
+            ],
+            compression_methods: vec![Compression::Null],
+        }
+    }
+}
+
+/// Returns the GREASE value (RFC 8701) selected by `seed`.
+pub(crate) fn grease_value(seed: u32) -> u16 {
+    let nibble = (seed & 0xf) as u16;
+    (nibble << 12) | 0x0a00 | (nibble << 4) | 0x0a
+}
+
+/// Builds the body of a GREASE `encrypted_client_hello` extension, as
+/// sent by a client with no ECH configuration for the server.
+///
+/// This is an outer ECHClientHello naming HKDF-SHA256 and AES-128-GCM,
+/// with a random config id, a random 32 byte `enc` and a random 144 byte
+/// `payload`, so it cannot be told apart from a real one by its shape.
+pub(crate) fn grease_ech(rng: &dyn SecureRandomProvider) -> Result<Vec<u8>, GetRandomFailed> {
+    const ENC_LEN: usize = 32;
+    const PAYLOAD_LEN: usize = 144;
+
+    let mut random = [0u8; 1 + ENC_LEN + PAYLOAD_LEN];
+    rng.fill(&mut random)?;
+    let (config_id, rest) = random.split_at(1);
+    let (enc, payload) = rest.split_at(ENC_LEN);
+
+    let mut body = Vec::with_capacity(1 + 4 + 1 + 2 + ENC_LEN + 2 + PAYLOAD_LEN);
+    // ECHClientHelloType outer, then the HPKE KDF and AEAD ids.
+    body.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x01]);
+    body.extend_from_slice(config_id);
+    body.extend_from_slice(&(ENC_LEN as u16).to_be_bytes());
+    body.extend_from_slice(enc);
+    body.extend_from_slice(&(PAYLOAD_LEN as u16).to_be_bytes());
+    body.extend_from_slice(payload);
+    Ok(body)
+}
+
+/// Reorders `exts` so that types listed in `order` come first, in that
+/// order, followed by the remaining extensions in their original order.
+pub(crate) fn order_extensions(exts: &mut [ClientExtension], order: &[ExtensionType]) {
+    exts.sort_by_key(|ext| {
+        let typ = ext.get_type();
+        order.iter().position(|&t| t == typ).unwrap_or(order.len())
+    });
+}
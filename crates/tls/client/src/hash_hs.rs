@@ -1,5 +1,5 @@
 use ring::digest;
-use std::mem;
+use std::{mem, sync::Arc};
 use tls_core::{
     msgs::{
         codec::Codec,
@@ -17,9 +17,10 @@ use tls_core::{
 pub(crate) struct HandshakeHashBuffer {
     buffer: Vec<u8>,
     client_auth_enabled: bool,
+    observer: Option<Arc<dyn Fn(&[u8]) + Send + Sync>>,
 }
 
-fn map_algorithm(algorithm: &'static HashAlgorithm) -> &'static digest::Algorithm {
+pub(crate) fn map_algorithm(algorithm: &'static HashAlgorithm) -> &'static digest::Algorithm {
     match algorithm {
         HashAlgorithm::SHA1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
         HashAlgorithm::SHA256 => &digest::SHA256,
@@ -34,6 +35,7 @@ impl HandshakeHashBuffer {
         Self {
             buffer: Vec::new(),
             client_auth_enabled: false,
+            observer: None,
         }
     }
 
@@ -43,16 +45,26 @@ impl HandshakeHashBuffer {
         self.client_auth_enabled = true;
     }
 
+    /// Report every byte slice that goes into the hash to `observer`.
+    ///
+    /// Buffered bytes are reported as they are buffered, which is
+    /// the order in which they are later hashed.
+    pub(crate) fn set_observer(&mut self, observer: Arc<dyn Fn(&[u8]) + Send + Sync>) {
+        self.observer = Some(observer);
+    }
+
     /// Hash/buffer a handshake message.
     pub(crate) fn add_message(&mut self, m: &Message) {
         if let MessagePayload::Handshake(hs) = &m.payload {
-            self.buffer.extend_from_slice(&hs.get_encoding());
+            self.update_raw(&hs.get_encoding());
         }
     }
 
     /// Hash or buffer a byte slice.
-    #[cfg(test)]
     fn update_raw(&mut self, buf: &[u8]) {
+        if let Some(observer) = &self.observer {
+            observer(buf);
+        }
         self.buffer.extend_from_slice(buf);
     }
 
@@ -78,6 +90,7 @@ impl HandshakeHashBuffer {
                 true => Some(self.buffer),
                 false => None,
             },
+            observer: self.observer,
         }
     }
 }
@@ -95,6 +108,9 @@ pub(crate) struct HandshakeHash {
 
     /// buffer for client-auth.
     client_auth: Option<Vec<u8>>,
+
+    /// called with everything we hash.
+    observer: Option<Arc<dyn Fn(&[u8]) + Send + Sync>>,
 }
 
 impl HandshakeHash {
@@ -115,6 +131,9 @@ impl HandshakeHash {
 
     /// Hash or buffer a byte slice.
     fn update_raw(&mut self, buf: &[u8]) -> &mut Self {
+        if let Some(observer) = &self.observer {
+            observer(buf);
+        }
         self.ctx.update(buf);
 
         if let Some(buffer) = &mut self.client_auth {
@@ -137,10 +156,13 @@ impl HandshakeHash {
         let old_handshake_hash_msg =
             HandshakeMessagePayload::build_handshake_hash(old_hash.as_ref());
 
-        HandshakeHashBuffer {
+        let mut buffer = HandshakeHashBuffer {
             client_auth_enabled: self.client_auth.is_some(),
-            buffer: old_handshake_hash_msg.get_encoding(),
-        }
+            buffer: Vec::new(),
+            observer: self.observer,
+        };
+        buffer.update_raw(&old_handshake_hash_msg.get_encoding());
+        buffer
     }
 
     /// Take the current hash value, and encapsulate it in a
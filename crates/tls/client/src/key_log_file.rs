@@ -29,6 +29,10 @@ impl KeyLogFileInner {
             }
         };
 
+        Self::open(path)
+    }
+
+    fn open(path: &Path) -> Self {
         #[cfg_attr(not(feature = "logging"), allow(unused_variables))]
         let file = match OpenOptions::new().append(true).create(true).open(path) {
             Ok(f) => Some(f),
@@ -70,7 +74,8 @@ impl KeyLogFileInner {
 /// given by the `SSLKEYLOGFILE` environment variable, and writes
 /// keys into it.
 ///
-/// If `SSLKEYLOGFILE` is not set, this does nothing.
+/// If `SSLKEYLOGFILE` is not set, this does nothing.  Use
+/// [`KeyLogFile::with_path`] to name the file explicitly instead.
 ///
 /// If such a file cannot be opened, or cannot be written then
 /// this does nothing but logs errors at warning-level.
@@ -83,6 +88,13 @@ impl KeyLogFile {
         let var = env::var("SSLKEYLOGFILE");
         Self(Mutex::new(KeyLogFileInner::new(var)))
     }
+
+    /// Makes a new `KeyLogFile` which appends keys to the file at
+    /// `path`, regardless of `SSLKEYLOGFILE`.  The file is created
+    /// if it does not exist, and opened during this call.
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
+        Self(Mutex::new(KeyLogFileInner::open(path.as_ref())))
+    }
 }
 
 impl KeyLog for KeyLogFile {
@@ -139,4 +151,34 @@ mod test {
         let mut inner = KeyLogFileInner::new(Ok("/dev/full".into()));
         assert!(inner.try_write("label", b"random", b"secret").is_err());
     }
+
+    #[test]
+    fn test_with_path_appends_lines() {
+        init();
+        let path = env::temp_dir().join(format!("keylogfilepathtest-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let key_log = KeyLogFile::with_path(&path);
+        key_log.log(
+            "CLIENT_HANDSHAKE_TRAFFIC_SECRET",
+            &[0x01, 0xab],
+            &[0xff, 0x00],
+        );
+        key_log.log("SERVER_HANDSHAKE_TRAFFIC_SECRET", &[0x01, 0xab], &[0x12]);
+        drop(key_log);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<Vec<&str>> = contents
+            .lines()
+            .map(|line| line.split(' ').collect())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                vec!["CLIENT_HANDSHAKE_TRAFFIC_SECRET", "01ab", "ff00"],
+                vec!["SERVER_HANDSHAKE_TRAFFIC_SECRET", "01ab", "12"],
+            ]
+        );
+    }
 }
@@ -239,12 +239,37 @@
 //!   such as replacing the certificate verification process.  Applications
 //!   requesting this feature should be reviewed carefully.
 //!
+//! - `dangerous_extract_secrets`: adds [`ClientConnection::extract_secrets`],
+//!   which hands the connection's traffic keys to the caller.  Anyone who
+//!   learns them can read and forge the session's application data, so
+//!   applications requesting this feature should be reviewed carefully.
+//!
 //! - `tls12`: enables support for TLS version 1.2. This feature is in the default
 //!   set. Note that, due to the additive nature of Cargo features and because it
 //!   is enabled by default, other crates in your dependency graph could re-enable
 //!   it for your application. If you want to disable TLS 1.2 for security reasons,
 //!   consider explicitly enabling TLS 1.3 only in the config builder API.
 //!
+//! - `server`: adds the [`server`] module, an async wrapper around
+//!   `rustls::ServerConnection` for use as the other end of a [`ClientConnection`].
+//!
+//! - `notary`: adds [`ClientConnection::export_notary_bundle`] and the
+//!   [`notary`] module, which record the handshake transcript and record
+//!   boundaries for offline verification.
+//!
+//! - `pkcs12`: adds `with_client_auth_pkcs12` to the config builder, for
+//!   loading a client certificate chain and key from a PKCS#12 file.
+//!
+//! - `webpki-roots`: adds `with_webpki_roots` to the config builder, which
+//!   trusts the Mozilla root certificates bundled in the `webpki-roots` crate.
+//!
+//! - `test-utils`: adds the [`testing`] module, with helpers for testing
+//!   against real servers over `tokio::net::TcpStream`.
+//!
+//! - `zeroize`: zeroes buffered plaintext, both received and waiting to be
+//!   encrypted, once it is consumed or the connection is dropped.  The
+//!   [`RustCryptoBackend`] likewise zeroes its secrets and keys on drop.
+//!
 //! - `read_buf`: When building with Rust Nightly, adds support for the unstable
 //!   `std::io::ReadBuf` and related APIs. This reduces costs from initializing
 //!   buffers. Will do nothing on non-Nightly releases.
@@ -304,7 +329,11 @@ mod limited_cache;
 mod msgs;
 mod rand;
 mod record_layer;
-//mod stream;
+mod stream;
+#[cfg(feature = "test-utils")]
+pub mod testing;
+#[cfg(feature = "tokio")]
+mod tls_stream;
 mod vecbuf;
 pub(crate) use tls_core::verify;
 #[cfg(test)]
@@ -317,6 +346,8 @@ mod builder;
 mod key_log;
 mod key_log_file;
 mod kx;
+#[cfg(feature = "pkcs12")]
+mod pkcs12;
 mod ticketer;
 
 /// Internal classes which may be useful outside the library.
@@ -332,23 +363,29 @@ pub mod internal {
 pub use crate::{
     anchors::{OwnedTrustAnchor, RootCertStore},
     builder::{ConfigBuilder, WantsCipherSuites, WantsKxGroups, WantsVerifier, WantsVersions},
-    conn::{CommonState, ConnectionCommon, IoState, Reader, SideData},
+    conn::{CommonState, ConnectionCommon, ConnectionStats, IoState, Reader, SideData},
     error::Error,
     key_log::{KeyLog, NoKeyLog},
     key_log_file::KeyLogFile,
     kx::{SupportedKxGroup, ALL_KX_GROUPS},
+    rand::{GetRandomFailed, SecureRandomProvider, SystemRandomProvider},
+    stream::{Stream, StreamOwned},
+};
+pub use backend::{
+    Backend, BackendError, DecryptMode, EncryptMode, RustCryptoBackend, TrafficSecrets,
 };
-pub use backend::{Backend, BackendError, DecryptMode, EncryptMode, RustCryptoBackend};
 pub use cipher::{MessageDecrypter, MessageEncrypter};
 pub use tls_core::{
     key::{Certificate, PrivateKey},
     msgs::{
-        enums::{CipherSuite, ProtocolVersion, SignatureScheme},
+        enums::{CipherSuite, ContentType, MaxFragmentLength, ProtocolVersion, SignatureScheme},
         handshake::DistinguishedNames,
     },
     suites::{SupportedCipherSuite, ALL_CIPHER_SUITES},
     versions::{SupportedProtocolVersion, ALL_VERSIONS},
 };
+#[cfg(feature = "tokio")]
+pub use tls_stream::TlsStream;
 
 /// Items for use in a client.
 pub mod client {
@@ -357,16 +394,25 @@ pub mod client {
     mod common;
     pub(super) mod handy;
     mod hs;
+    mod serialize;
+    mod split;
     #[cfg(feature = "tls12")]
     mod tls12;
     mod tls13;
 
     pub use builder::{WantsClientCert, WantsTransparencyPolicyOrClientCert};
+    #[cfg(feature = "dangerous_configuration")]
+    pub use client_conn::DangerousClientConfig;
+    #[cfg(feature = "dangerous_extract_secrets")]
+    pub use client_conn::ExtractedSecrets;
     pub use client_conn::{
-        ClientConfig, ClientConnection, ClientConnectionData, InvalidDnsNameError,
-        ResolvesClientCert, ServerName, StoresClientSessions,
+        ClientConfig, ClientConnection, ClientConnectionData, Direction, InvalidDnsNameError,
+        PathPolicy, PostHandshakeEvent, RecordObserver, ResolvesClientCert,
+        ResolvesClientCertAsync, ServerCertVerifierAsync, ServerName, StoresClientSessions,
+        StoresClientSessionsAsync, TimeProvider,
     };
-    pub use handy::{ClientSessionMemoryCache, NoClientSessionStorage};
+    pub use handy::{ClientSessionMemoryCache, NoClientSessionStorage, SystemTimeProvider};
+    pub use split::{ReadHalf, ReuniteError, WriteHalf};
 }
 
 pub use client::{ClientConfig, ClientConnection, ServerName};
@@ -387,12 +433,21 @@ pub mod kx_group {
     pub use crate::kx::{SECP256R1, SECP384R1, X25519};
 }
 
+/// ClientHello shaping to match the fingerprint of well-known clients.
+pub mod fingerprint;
+
 /// Message signing interfaces and implementations.
 pub mod sign;
 
 /// This is the rustls manual.
 pub mod manual;
 
+#[cfg(feature = "server")]
+pub mod server;
+
+#[cfg(feature = "notary")]
+pub mod notary;
+
 #[allow(clippy::upper_case_acronyms)]
 #[doc(hidden)]
 #[deprecated(since = "0.20.0", note = "Use Error")]
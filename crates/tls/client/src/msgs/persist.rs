@@ -152,7 +152,9 @@ impl<T> std::ops::Deref for Retrieved<T> {
 pub struct Tls13ClientSessionValue {
     suite: &'static Tls13CipherSuite,
     age_add: u32,
-    max_early_data_size: u32,
+    /// `None` if the ticket had no early_data extension, which is distinct
+    /// from one advertising a limit of zero.
+    max_early_data_size: Option<u32>,
     pub common: ClientSessionCommon,
 }
 
@@ -165,7 +167,7 @@ impl Tls13ClientSessionValue {
         time_now: TimeBase,
         lifetime_secs: u32,
         age_add: u32,
-        max_early_data_size: u32,
+        max_early_data_size: Option<u32>,
     ) -> Self {
         Self {
             suite,
@@ -189,7 +191,11 @@ impl Tls13ClientSessionValue {
         Some(Self {
             suite,
             age_add: u32::read(r)?,
-            max_early_data_size: u32::read(r)?,
+            max_early_data_size: match u8::read(r)? {
+                0 => None,
+                1 => Some(u32::read(r)?),
+                _ => return None,
+            },
             common: ClientSessionCommon::read(r)?,
         })
     }
@@ -201,12 +207,18 @@ impl Tls13ClientSessionValue {
         let mut bytes = Vec::with_capacity(16);
         self.suite.common.suite.encode(&mut bytes);
         self.age_add.encode(&mut bytes);
-        self.max_early_data_size.encode(&mut bytes);
+        match self.max_early_data_size {
+            Some(size) => {
+                1u8.encode(&mut bytes);
+                size.encode(&mut bytes);
+            }
+            None => 0u8.encode(&mut bytes),
+        }
         self.common.encode(&mut bytes);
         bytes
     }
 
-    pub fn max_early_data_size(&self) -> u32 {
+    pub fn max_early_data_size(&self) -> Option<u32> {
         self.max_early_data_size
     }
 
@@ -34,7 +34,7 @@ fn clientsessionvalue_is_debug() {
         TimeBase::now().unwrap(),
         15,
         10,
-        128,
+        Some(128),
     ));
     println!("{:?}", csv);
 }
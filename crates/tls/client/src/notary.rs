@@ -0,0 +1,241 @@
+//! Packaging a connection for offline verification by a notary.
+//!
+//! A [`NotaryBundle`] collects, in one value, everything a verifier needs
+//! to check a session after the fact: the handshake transcript, the
+//! server's certificate chain, the negotiated parameters and the
+//! boundaries of every record sent and received.  Obtain one with
+//! [`ClientConnection::export_notary_bundle`].
+//!
+//! Bundles serialize to a compact binary format with
+//! [`NotaryBundle::to_bytes`].  The format begins with
+//! [`NOTARY_BUNDLE_FORMAT`] and is only ever changed by bumping that
+//! number, so bundles written by one release can be read by later ones.
+//!
+//! [`ClientConnection::export_notary_bundle`]: crate::ClientConnection::export_notary_bundle
+
+use crate::error::Error;
+use tls_core::{
+    key::Certificate,
+    msgs::{
+        base::{PayloadU24, PayloadU8},
+        codec::{Codec, Reader},
+        enums::{CipherSuite, ContentType, ProtocolVersion},
+        handshake::CertificatePayload,
+    },
+};
+
+/// The version of the serialization format written by
+/// [`NotaryBundle::to_bytes`].
+pub const NOTARY_BUNDLE_FORMAT: u8 = 1;
+
+/// Which way a record travelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordDirection {
+    /// The record was sent by us.
+    Sent,
+    /// The record was received from the server.
+    Received,
+}
+
+impl Codec for RecordDirection {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let v: u8 = match self {
+            Self::Sent => 0,
+            Self::Received => 1,
+        };
+        v.encode(bytes);
+    }
+
+    fn read(r: &mut Reader) -> Option<Self> {
+        match u8::read(r)? {
+            0 => Some(Self::Sent),
+            1 => Some(Self::Received),
+            _ => None,
+        }
+    }
+}
+
+/// The position of one record within the connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordBoundary {
+    /// Which way the record travelled.
+    pub direction: RecordDirection,
+    /// The record's content type, as it appeared in the record header.
+    pub typ: ContentType,
+    /// The sequence number the record was protected with, or `None`
+    /// if it was sent in the clear.
+    ///
+    /// This counts the encrypted records in each direction, which for
+    /// TLS1.2 is the record sequence number used to form the AEAD nonce.
+    pub seq: Option<u64>,
+    /// The length of the record's payload on the wire, excluding the
+    /// record header.
+    pub len: u16,
+}
+
+impl Codec for RecordBoundary {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        self.direction.encode(bytes);
+        self.typ.encode(bytes);
+        match self.seq {
+            Some(seq) => {
+                1u8.encode(bytes);
+                seq.encode(bytes);
+            }
+            None => 0u8.encode(bytes),
+        }
+        self.len.encode(bytes);
+    }
+
+    fn read(r: &mut Reader) -> Option<Self> {
+        let direction = RecordDirection::read(r)?;
+        let typ = ContentType::read(r)?;
+        let seq = match u8::read(r)? {
+            0 => None,
+            1 => Some(u64::read(r)?),
+            _ => return None,
+        };
+        let len = u16::read(r)?;
+
+        Some(Self {
+            direction,
+            typ,
+            seq,
+            len,
+        })
+    }
+}
+
+/// Everything needed to verify a connection offline.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotaryBundle {
+    /// The negotiated protocol version.
+    pub version: ProtocolVersion,
+    /// The negotiated cipher suite.
+    pub cipher_suite: CipherSuite,
+    /// The negotiated ALPN protocol, if any.
+    pub alpn_protocol: Option<Vec<u8>>,
+    /// The server's certificate chain, end-entity certificate first.
+    pub certificates: Vec<Certificate>,
+    /// The encodings of the handshake messages sent and received until
+    /// the handshake completed, in order, each including its four byte
+    /// handshake header.  This is the same as
+    /// [`ClientConnection::handshake_transcript`].
+    ///
+    /// [`ClientConnection::handshake_transcript`]: crate::ClientConnection::handshake_transcript
+    pub handshake_transcript: Vec<u8>,
+    /// Every record sent and received, in the order we processed them.
+    pub records: Vec<RecordBoundary>,
+}
+
+impl NotaryBundle {
+    /// Serializes this bundle.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.get_encoding()
+    }
+
+    /// Parses a bundle previously produced by [`NotaryBundle::to_bytes`].
+    ///
+    /// Fails if `bytes` are malformed or use an unknown format version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::read_bytes(bytes).ok_or_else(|| Error::General("invalid notary bundle".to_string()))
+    }
+}
+
+impl Codec for NotaryBundle {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        NOTARY_BUNDLE_FORMAT.encode(bytes);
+        self.version.encode(bytes);
+        self.cipher_suite.encode(bytes);
+        match &self.alpn_protocol {
+            Some(protocol) => {
+                1u8.encode(bytes);
+                PayloadU8::new(protocol.clone()).encode(bytes);
+            }
+            None => 0u8.encode(bytes),
+        }
+        self.certificates.encode(bytes);
+        PayloadU24::new(self.handshake_transcript.clone()).encode(bytes);
+        (self.records.len() as u32).encode(bytes);
+        for record in &self.records {
+            record.encode(bytes);
+        }
+    }
+
+    fn read(r: &mut Reader) -> Option<Self> {
+        if u8::read(r)? != NOTARY_BUNDLE_FORMAT {
+            return None;
+        }
+
+        let version = ProtocolVersion::read(r)?;
+        let cipher_suite = CipherSuite::read(r)?;
+        let alpn_protocol = match u8::read(r)? {
+            0 => None,
+            1 => Some(PayloadU8::read(r)?.0),
+            _ => return None,
+        };
+        let certificates = CertificatePayload::read(r)?;
+        let handshake_transcript = PayloadU24::read(r)?.0;
+
+        let count = u32::read(r)?;
+        let mut records = Vec::new();
+        for _ in 0..count {
+            records.push(RecordBoundary::read(r)?);
+        }
+
+        if r.any_left() {
+            return None;
+        }
+
+        Some(Self {
+            version,
+            cipher_suite,
+            alpn_protocol,
+            certificates,
+            handshake_transcript,
+            records,
+        })
+    }
+}
+
+/// What a connection records for its [`NotaryBundle`], besides the
+/// handshake transcript it keeps anyway.
+#[derive(Default)]
+pub(crate) struct NotaryLog {
+    pub(crate) records: Vec<RecordBoundary>,
+    sent_seq: u64,
+    received_seq: u64,
+}
+
+impl NotaryLog {
+    /// Notes a record of `len` payload bytes.
+    pub(crate) fn add_record(
+        &mut self,
+        direction: RecordDirection,
+        typ: ContentType,
+        len: usize,
+        encrypted: bool,
+    ) {
+        let seq = match encrypted {
+            true => {
+                let counter = match direction {
+                    RecordDirection::Sent => &mut self.sent_seq,
+                    RecordDirection::Received => &mut self.received_seq,
+                };
+                let seq = *counter;
+                *counter += 1;
+                Some(seq)
+            }
+            false => None,
+        };
+
+        self.records.push(RecordBoundary {
+            direction,
+            typ,
+            seq,
+            len: len as u16,
+        });
+    }
+}
@@ -0,0 +1,48 @@
+use crate::error::Error;
+use tls_core::key;
+
+/// Extracts the certificate chain and private key from a DER-encoded
+/// PKCS#12 archive protected by `password`.
+///
+/// The chain is returned in the order the archive stores it, which for
+/// archives produced by common tools starts with the end-entity
+/// certificate.  The archive must contain exactly one key.
+pub(crate) fn parse(
+    der: &[u8],
+    password: &str,
+) -> Result<(Vec<key::Certificate>, key::PrivateKey), Error> {
+    let pfx =
+        p12::PFX::parse(der).map_err(|_| Error::General("invalid PKCS#12 archive".to_string()))?;
+
+    if !pfx.verify_mac(password) {
+        return Err(Error::General(
+            "incorrect PKCS#12 password or corrupt archive".to_string(),
+        ));
+    }
+
+    let certs = pfx
+        .cert_x509_bags(password)
+        .map_err(|_| Error::General("cannot decrypt PKCS#12 certificates".to_string()))?;
+    if certs.is_empty() {
+        return Err(Error::General(
+            "PKCS#12 archive contains no certificates".to_string(),
+        ));
+    }
+
+    let mut keys = pfx
+        .key_bags(password)
+        .map_err(|_| Error::General("cannot decrypt PKCS#12 private key".to_string()))?;
+    let key = match (keys.pop(), keys.is_empty()) {
+        (Some(key), true) => key,
+        _ => {
+            return Err(Error::General(
+                "PKCS#12 archive must contain exactly one private key".to_string(),
+            ))
+        }
+    };
+
+    Ok((
+        certs.into_iter().map(key::Certificate).collect(),
+        key::PrivateKey(key),
+    ))
+}
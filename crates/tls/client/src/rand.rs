@@ -24,5 +24,27 @@ pub(crate) fn random_u32() -> Result<u32, GetRandomFailed> {
     codec::decode_u32(&buf).ok_or(GetRandomFailed)
 }
 
+/// A source of the random material the handshake needs, such as the
+/// session ID.
+///
+/// The client random and key shares come from the [`crate::Backend`]
+/// instead.
+pub trait SecureRandomProvider: Send + Sync {
+    /// Fill the whole of `bytes` with random material.
+    fn fill(&self, bytes: &mut [u8]) -> Result<(), GetRandomFailed>;
+}
+
+/// A [`SecureRandomProvider`] using the system's random number
+/// generator, through *ring*.
+pub struct SystemRandomProvider;
+
+impl SecureRandomProvider for SystemRandomProvider {
+    fn fill(&self, bytes: &mut [u8]) -> Result<(), GetRandomFailed> {
+        fill_random(bytes)
+    }
+}
+
+/// The error from a [`SecureRandomProvider`] which could not produce
+/// random material.
 #[derive(Debug)]
 pub struct GetRandomFailed;
@@ -1,5 +1,8 @@
 use crate::{error::Error, Backend};
-use tls_core::msgs::message::{OpaqueMessage, PlainMessage};
+use tls_core::{
+    msgs::message::{OpaqueMessage, PlainMessage},
+    suites::AEADAlgorithm,
+};
 
 static SEQ_SOFT_LIMIT: u64 = 0xffff_ffff_ffff_0000u64;
 static SEQ_HARD_LIMIT: u64 = 0xffff_ffff_ffff_fffeu64;
@@ -26,6 +29,11 @@ pub(crate) struct RecordLayer {
     // should be swallowed by the caller.  This struct tracks the amount
     // of message size this is allowed for.
     trial_decryption_len: Option<usize>,
+
+    /// How many records may be encrypted under one key.
+    confidentiality_limit: u64,
+    /// How many records may be decrypted under one key.
+    integrity_limit: u64,
 }
 
 impl RecordLayer {
@@ -36,9 +44,27 @@ impl RecordLayer {
             encrypt_state: DirectionState::Invalid,
             decrypt_state: DirectionState::Invalid,
             trial_decryption_len: None,
+            confidentiality_limit: u64::MAX,
+            integrity_limit: u64::MAX,
         }
     }
 
+    /// Apply the usage limits of `aead` to the keys used from now on.
+    pub(crate) fn set_aead_limits(&mut self, aead: &AEADAlgorithm) {
+        self.confidentiality_limit = aead.confidentiality_limit();
+        self.integrity_limit = aead.integrity_limit();
+    }
+
+    /// How many records have been encrypted under the current key.
+    pub(crate) fn write_seq(&self) -> u64 {
+        self.write_seq
+    }
+
+    /// How many records have been decrypted under the current key.
+    pub(crate) fn read_seq(&self) -> u64 {
+        self.read_seq
+    }
+
     pub(crate) fn is_encrypting(&self) -> bool {
         self.encrypt_state == DirectionState::Active
     }
@@ -129,9 +155,24 @@ impl RecordLayer {
     }
 
     /// Return true if we are getting close to encrypting too many
-    /// messages with our encryption key.
+    /// messages with our encryption key: there is room for just one
+    /// more under the AEAD's confidentiality limit, or we are nearing
+    /// the end of sequence space.
     pub(crate) fn wants_close_before_encrypt(&self) -> bool {
         self.write_seq == SEQ_SOFT_LIMIT
+            || self.write_seq == self.confidentiality_limit.saturating_sub(1)
+    }
+
+    /// Return true if encrypting another message with our encryption
+    /// key would exceed the AEAD's confidentiality limit.
+    pub(crate) fn confidentiality_exhausted(&self) -> bool {
+        self.write_seq >= self.confidentiality_limit
+    }
+
+    /// Return true if decrypting another message with the peer's key
+    /// would exceed the AEAD's integrity limit.
+    pub(crate) fn integrity_exhausted(&self) -> bool {
+        self.read_seq >= self.integrity_limit
     }
 
     /// Return true if we outright refuse to do anything with the
@@ -172,3 +213,37 @@ impl RecordLayer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closes_one_record_before_confidentiality_limit() {
+        let mut rl = RecordLayer::new();
+        rl.set_aead_limits(&AEADAlgorithm::AES_128_GCM);
+
+        rl.write_seq = (1 << 24) - 2;
+        assert!(!rl.wants_close_before_encrypt());
+        assert!(!rl.confidentiality_exhausted());
+
+        rl.write_seq += 1;
+        assert!(rl.wants_close_before_encrypt());
+        assert!(!rl.confidentiality_exhausted());
+
+        rl.write_seq += 1;
+        assert!(rl.confidentiality_exhausted());
+    }
+
+    #[test]
+    fn refuses_to_decrypt_past_integrity_limit() {
+        let mut rl = RecordLayer::new();
+        rl.set_aead_limits(&AEADAlgorithm::CHACHA20_POLY1305);
+
+        rl.read_seq = (1 << 36) - 1;
+        assert!(!rl.integrity_exhausted());
+
+        rl.read_seq += 1;
+        assert!(rl.integrity_exhausted());
+    }
+}
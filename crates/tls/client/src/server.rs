@@ -0,0 +1,159 @@
+//! An async server end, for pairing with [`ClientConnection`].
+//!
+//! This crate does not implement the server side of TLS itself.
+//! [`AsyncServerConnection`] wraps `rustls::ServerConnection` and mirrors
+//! the client's async API, so that proxies and tests can drive both ends
+//! of a connection the same way.
+//!
+//! # Callbacks
+//!
+//! The server is configured with a plain `rustls::ServerConfig`, so its
+//! callbacks -- certificate resolution (`ResolvesServerCert`), session
+//! storage (`StoresServerSessions`), ticketing and key logging -- remain
+//! synchronous.  None of them become async: they are called from within
+//! [`AsyncServerConnection::process_new_packets`], which is synchronous.
+//!
+//! [`ClientConnection`]: crate::ClientConnection
+
+use std::{io, sync::Arc};
+
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use rustls::{Reader, ServerConfig, ServerConnection, Writer};
+
+pub use rustls::IoState;
+
+/// A TLS server connection with an async API matching
+/// [`ClientConnection`].
+///
+/// [`ClientConnection`]: crate::ClientConnection
+pub struct AsyncServerConnection {
+    inner: ServerConnection,
+}
+
+impl AsyncServerConnection {
+    /// Make a new server connection with the given configuration.
+    pub fn new(config: Arc<ServerConfig>) -> Result<Self, rustls::Error> {
+        Ok(Self {
+            inner: ServerConnection::new(config)?,
+        })
+    }
+
+    /// Processes any new packets read by a previous call to
+    /// [`AsyncServerConnection::read_tls`].
+    ///
+    /// See [`ConnectionCommon::process_new_packets`] for details.
+    ///
+    /// [`ConnectionCommon::process_new_packets`]: crate::ConnectionCommon::process_new_packets
+    pub fn process_new_packets(&mut self) -> Result<IoState, rustls::Error> {
+        self.inner.process_new_packets()
+    }
+
+    /// Returns an object that allows reading plaintext.
+    pub fn reader(&mut self) -> Reader<'_> {
+        self.inner.reader()
+    }
+
+    /// Returns an object that allows writing plaintext.
+    pub fn writer(&mut self) -> Writer<'_> {
+        self.inner.writer()
+    }
+
+    /// Write buffer into connection.
+    pub async fn write_plaintext(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        io::Write::write(&mut self.inner.writer(), buf)
+    }
+
+    /// Write entire buffer into connection.
+    pub async fn write_all_plaintext(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        io::Write::write_all(&mut self.inner.writer(), buf)?;
+        Ok(buf.len())
+    }
+
+    /// Read TLS content from `rd`.
+    pub fn read_tls(&mut self, rd: &mut dyn io::Read) -> Result<usize, io::Error> {
+        self.inner.read_tls(rd)
+    }
+
+    /// Read TLS content from `rd`, asynchronously.
+    ///
+    /// All the bytes read from `rd` are taken.  If they do not all fit in
+    /// the buffer of undeframed records, the records already read are
+    /// processed to make room, as by
+    /// [`AsyncServerConnection::process_new_packets`], and any error from
+    /// that is returned as an `io::ErrorKind::InvalidData`-kind error.
+    pub async fn read_tls_async<T: AsyncRead + Unpin>(
+        &mut self,
+        rd: &mut T,
+    ) -> Result<usize, io::Error> {
+        let mut buf = [0u8; 4096];
+        let n = rd.read(&mut buf).await?;
+
+        let mut rest = &buf[..n];
+        let mut read = self.inner.read_tls(&mut rest)?;
+        while !rest.is_empty() {
+            self.inner
+                .process_new_packets()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            read += self.inner.read_tls(&mut rest)?;
+        }
+        Ok(read)
+    }
+
+    /// Writes TLS messages to `wr`.
+    pub fn write_tls(&mut self, wr: &mut dyn io::Write) -> Result<usize, io::Error> {
+        self.inner.write_tls(wr)
+    }
+
+    /// Writes TLS messages to `wr`, asynchronously.
+    pub async fn write_tls_async<T: AsyncWrite + Unpin>(
+        &mut self,
+        wr: &mut T,
+    ) -> Result<usize, io::Error> {
+        let mut buf = Vec::new();
+        while self.inner.wants_write() {
+            self.inner.write_tls(&mut buf)?;
+        }
+        wr.write_all(&buf).await?;
+        Ok(buf.len())
+    }
+
+    /// Returns true if the caller should call
+    /// [`AsyncServerConnection::read_tls`] as soon as possible.
+    pub fn wants_read(&self) -> bool {
+        self.inner.wants_read()
+    }
+
+    /// Returns true if the caller should call
+    /// [`AsyncServerConnection::write_tls`] as soon as possible.
+    pub fn wants_write(&self) -> bool {
+        self.inner.wants_write()
+    }
+
+    /// Returns true if the connection is currently performing the TLS
+    /// handshake.
+    pub fn is_handshaking(&self) -> bool {
+        self.inner.is_handshaking()
+    }
+
+    /// Queues a close_notify warning alert to be sent in the next
+    /// [`AsyncServerConnection::write_tls`] call.
+    pub fn send_close_notify(&mut self) {
+        self.inner.send_close_notify()
+    }
+
+    /// Returns a reference to the wrapped `rustls::ServerConnection`.
+    pub fn get_ref(&self) -> &ServerConnection {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped `rustls::ServerConnection`.
+    pub fn get_mut(&mut self) -> &mut ServerConnection {
+        &mut self.inner
+    }
+
+    /// Unwraps this connection, returning the wrapped
+    /// `rustls::ServerConnection`.
+    pub fn into_inner(self) -> ServerConnection {
+        self.inner
+    }
+}
@@ -51,6 +51,10 @@ pub struct CertifiedKey {
     /// certificate is included on those logs.  This must be
     /// a `SignedCertificateTimestampList` encoding; see RFC6962.
     pub sct_list: Option<Vec<u8>>,
+
+    /// The DER-encoded `SubjectPublicKeyInfo` of `key`, sent instead of
+    /// `cert` when the server accepts RFC 7250 raw public keys.
+    pub raw_public_key: Option<Vec<u8>>,
 }
 
 impl CertifiedKey {
@@ -64,6 +68,22 @@ impl CertifiedKey {
             key,
             ocsp: None,
             sct_list: None,
+            raw_public_key: None,
+        }
+    }
+
+    /// Make a new CertifiedKey for RFC 7250 raw public key
+    /// authentication, with no certificate chain.
+    ///
+    /// `spki` is the DER-encoded `SubjectPublicKeyInfo` of `key`.  This
+    /// can only authenticate to servers which accept raw public keys;
+    /// see [`ClientConfig::client_cert_type_preference`].
+    ///
+    /// [`ClientConfig::client_cert_type_preference`]: crate::ClientConfig::client_cert_type_preference
+    pub fn new_raw_public_key(spki: Vec<u8>, key: Arc<dyn SigningKey>) -> Self {
+        Self {
+            raw_public_key: Some(spki),
+            ..Self::new(Vec::new(), key)
         }
     }
 
@@ -1,167 +1,232 @@
-use crate::conn::{ConnectionCommon, SideData};
+use crate::{conn::ConnectionCommon, error::Error};
+use futures::{ready, AsyncBufRead, AsyncRead, AsyncWrite};
 use std::{
-    io::{IoSlice, Read, Result, Write},
+    future::Future,
+    io::{self, Read},
     ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
 };
 
-// /// This type implements `io::Read` and `io::Write`, encapsulating
-// /// a Connection `C` and an underlying transport `T`, such as a socket.
-// ///
-// /// This allows you to use a rustls Connection like a normal stream.
-// #[derive(Debug)]
-// pub struct Stream<'a, C: 'a + ?Sized, T: 'a + Read + Write + ?Sized> {
-//     /// Our TLS connection
-//     pub conn: &'a mut C,
-
-//     /// The underlying transport, like a socket
-//     pub sock: &'a mut T,
-// }
-
-// impl<'a, C, T, S> Stream<'a, C, T>
-// where
-//     C: 'a + DerefMut + Deref<Target = ConnectionCommon<S>>,
-//     T: 'a + Read + Write,
-//     S: SideData,
-// {
-//     /// Make a new Stream using the Connection `conn` and socket-like object
-//     /// `sock`.  This does not fail and does no IO.
-//     pub fn new(conn: &'a mut C, sock: &'a mut T) -> Self {
-//         Self { conn, sock }
-//     }
-
-//     /// If we're handshaking, complete all the IO for that.
-//     /// If we have data to write, write it all.
-//     fn complete_prior_io(&mut self) -> Result<()> {
-//         if self.conn.is_handshaking() {
-//             self.conn.complete_io(self.sock)?;
-//         }
-
-//         if self.conn.wants_write() {
-//             self.conn.complete_io(self.sock)?;
-//         }
-
-//         Ok(())
-//     }
-// }
-
-// impl<'a, C, T, S> Read for Stream<'a, C, T>
-// where
-//     C: 'a + DerefMut + Deref<Target = ConnectionCommon<S>>,
-//     T: 'a + Read + Write,
-//     S: SideData,
-// {
-//     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-//         self.complete_prior_io()?;
-
-//         // We call complete_io() in a loop since a single call may read only
-//         // a partial packet from the underlying transport. A full packet is
-//         // needed to get more plaintext, which we must do if EOF has not been
-//         // hit. Otherwise, we will prematurely signal EOF by returning 0. We
-//         // determine if EOF has actually been hit by checking if 0 bytes were
-//         // read from the underlying transport.
-//         while self.conn.wants_read() {
-//             let at_eof = self.conn.complete_io(self.sock)?.0 == 0;
-//             if at_eof {
-//                 if let Ok(io_state) = self.conn.process_new_packets() {
-//                     if at_eof && io_state.plaintext_bytes_to_read() == 0 {
-//                         return Ok(0);
-//                     }
-//                 }
-//                 break;
-//             }
-//         }
-
-//         self.conn.reader().read(buf)
-//     }
-
-//     #[cfg(read_buf)]
-//     fn read_buf(&mut self, buf: &mut std::io::ReadBuf<'_>) -> Result<()> {
-//         self.complete_prior_io()?;
-
-//         // We call complete_io() in a loop since a single call may read only
-//         // a partial packet from the underlying transport. A full packet is
-//         // needed to get more plaintext, which we must do if EOF has not been
-//         // hit. Otherwise, we will prematurely signal EOF by returning without
-//         // writing anything. We determine if EOF has actually been hit by
-//         // checking if 0 bytes were read from the underlying transport.
-//         while self.conn.wants_read() {
-//             let at_eof = self.conn.complete_io(self.sock)?.0 == 0;
-//             if at_eof {
-//                 if let Ok(io_state) = self.conn.process_new_packets() {
-//                     if at_eof && io_state.plaintext_bytes_to_read() == 0 {
-//                         return Ok(());
-//                     }
-//                 }
-//                 break;
-//             }
-//         }
-
-//         self.conn.reader().read_buf(buf)
-//     }
-// }
-
-// impl<'a, C, T, S> Write for Stream<'a, C, T>
-// where
-//     C: 'a + DerefMut + Deref<Target = ConnectionCommon<S>>,
-//     T: 'a + Read + Write,
-//     S: SideData,
-// {
-//     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-//         self.complete_prior_io()?;
-
-//         let len = self.conn.writer().write(buf)?;
-
-//         // Try to write the underlying transport here, but don't let
-//         // any errors mask the fact we've consumed `len` bytes.
-//         // Callers will learn of permanent errors on the next call.
-//         let _ = self.conn.complete_io(self.sock);
-
-//         Ok(len)
-//     }
-
-//     fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
-//         self.complete_prior_io()?;
-
-//         let len = self.conn.writer().write_vectored(bufs)?;
-
-//         // Try to write the underlying transport here, but don't let
-//         // any errors mask the fact we've consumed `len` bytes.
-//         // Callers will learn of permanent errors on the next call.
-//         let _ = self.conn.complete_io(self.sock);
-
-//         Ok(len)
-//     }
-
-//     fn flush(&mut self) -> Result<()> {
-//         self.complete_prior_io()?;
-
-//         self.conn.writer().flush()?;
-//         if self.conn.wants_write() {
-//             self.conn.complete_io(self.sock)?;
-//         }
-//         Ok(())
-//     }
-// }
-
-/// This type implements `io::Read` and `io::Write`, encapsulating
-/// and owning a Connection `C` and an underlying blocking transport
-/// `T`, such as a socket.
+/// This type implements [`AsyncRead`] and [`AsyncWrite`], encapsulating
+/// a Connection `C` and an underlying transport `T`, such as a socket.
 ///
-/// This allows you to use a rustls Connection like a normal stream.
+/// This allows you to use a connection like a normal stream.  The
+/// handshake is completed the first time data is read, written or
+/// flushed; [`ConnectionCommon::start`] must have been called already.
+///
+/// Both the transport and the connection's backend may return
+/// [`Poll::Pending`].  A backend operation suspended by one call is kept
+/// by the connection, as by [`ConnectionCommon::poll_process`], and
+/// completed by the next.
 #[derive(Debug)]
-pub struct StreamOwned<C: Sized, T: Read + Write + Sized> {
-    /// Our conneciton
+pub struct Stream<'a, C: 'a + ?Sized, T: 'a + AsyncRead + AsyncWrite + Unpin> {
+    /// Our TLS connection
+    pub conn: &'a mut C,
+
+    /// The underlying transport, like a socket
+    pub sock: &'a mut T,
+}
+
+impl<'a, C, T> Stream<'a, C, T>
+where
+    C: 'a + ?Sized + DerefMut + Deref<Target = ConnectionCommon>,
+    T: 'a + AsyncRead + AsyncWrite + Unpin,
+{
+    /// Make a new Stream using the Connection `conn` and socket-like object
+    /// `sock`.  This does not fail and does no IO.
+    pub fn new(conn: &'a mut C, sock: &'a mut T) -> Self {
+        Self { conn, sock }
+    }
+
+    /// Completes the backend operation an earlier call left suspended.
+    /// If we're handshaking, complete all the IO for that.
+    /// If we have data to write, write it all.
+    fn poll_complete_prior_io(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_settle(cx))?;
+        while self.conn.is_handshaking() {
+            ready!(self.poll_write_tls(cx))?;
+            if ready!(self.poll_read_tls(cx))? == 0 && self.conn.is_handshaking() {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+
+        self.poll_write_tls(cx)
+    }
+
+    /// Writes all the TLS messages waiting to be sent to the transport.
+    fn poll_write_tls(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.conn.wants_write() {
+            let written = ready!(poll_once(self.conn.write_tls_async(&mut *self.sock), cx))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Reads from the transport once, and processes any whole records
+    /// received.  Returns the number of bytes read.
+    fn poll_read_tls(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let read = ready!(poll_once(self.conn.read_tls_async(&mut *self.sock), cx))?;
+
+        // If the backend suspends, the next call completes processing
+        // before reading again.
+        let result = ready!(self.conn.poll_process(cx));
+        self.check(cx, result)?;
+
+        Poll::Ready(Ok(read))
+    }
+
+    /// Reads and processes records until there is plaintext to read or
+    /// the transport reaches EOF.
+    fn poll_fill_plaintext(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_complete_prior_io(cx))?;
+
+        // A single read from the transport may bring in only part of a
+        // record, so keep reading until there is plaintext or the
+        // transport reaches EOF.  The reader then says which kind of EOF
+        // it was.
+        while self.conn.wants_read() {
+            if ready!(self.poll_read_tls(cx))? == 0 {
+                break;
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Completes the backend operation an earlier call left suspended,
+    /// if any.
+    fn poll_settle(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let result = ready!(self.conn.poll_settle(cx));
+        Poll::Ready(self.check(cx, result))
+    }
+
+    /// Converts the result of a backend operation.
+    fn check<O>(&mut self, cx: &mut Context<'_>, result: Result<O, Error>) -> io::Result<O> {
+        result.map_err(|e| {
+            // In case we have an alert to send describing this error,
+            // try a last-gasp write -- but don't predate the primary
+            // error.
+            let _ignored = self.poll_write_tls(cx);
+
+            io::Error::new(io::ErrorKind::InvalidData, e)
+        })
+    }
+}
+
+impl<'a, C, T> AsyncRead for Stream<'a, C, T>
+where
+    C: 'a + ?Sized + DerefMut + Deref<Target = ConnectionCommon>,
+    T: 'a + AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_fill_plaintext(cx))?;
+
+        Poll::Ready(this.conn.reader().read(buf))
+    }
+}
+
+impl<'a, C, T> AsyncBufRead for Stream<'a, C, T>
+where
+    C: 'a + ?Sized + DerefMut + Deref<Target = ConnectionCommon>,
+    T: 'a + AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        ready!(this.poll_fill_plaintext(cx))?;
+
+        Poll::Ready(this.conn.fill_plaintext_buf())
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        io::BufRead::consume(&mut self.get_mut().conn.reader(), amt)
+    }
+}
+
+impl<'a, C, T> AsyncWrite for Stream<'a, C, T>
+where
+    C: 'a + ?Sized + DerefMut + Deref<Target = ConnectionCommon>,
+    T: 'a + AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_complete_prior_io(cx))?;
+
+        let len = this.conn.buffer_plaintext(buf);
+
+        // Encrypt it now unless the backend suspends, in which case the
+        // next call completes the encryption.  Either way `len` bytes
+        // have been consumed.
+        if let Poll::Ready(result) = this.conn.poll_flush_plaintext(cx) {
+            this.check(cx, result)?;
+        }
+
+        // Try to write the underlying transport here, but don't let
+        // any errors mask the fact we've consumed `len` bytes.
+        // Callers will learn of permanent errors on the next call.
+        let _ = this.poll_write_tls(cx);
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // This comes first so that it resumes the flush an earlier call
+        // left suspended, rather than completing it and starting another.
+        let result = ready!(this.conn.poll_flush_plaintext(cx));
+        this.check(cx, result)?;
+        ready!(this.poll_complete_prior_io(cx))?;
+
+        Pin::new(&mut *this.sock).poll_flush(cx)
+    }
+
+    /// Writes out any buffered data and closes the transport.
+    ///
+    /// This does not send a close_notify alert: call
+    /// [`CommonState::send_close_notify`] first to close the TLS session
+    /// cleanly.
+    ///
+    /// [`CommonState::send_close_notify`]: crate::CommonState::send_close_notify
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_settle(cx))?;
+        ready!(this.poll_write_tls(cx))?;
+
+        Pin::new(&mut *this.sock).poll_close(cx)
+    }
+}
+
+/// This type implements [`AsyncRead`] and [`AsyncWrite`], encapsulating
+/// and owning a Connection `C` and an underlying transport `T`, such
+/// as a socket.
+///
+/// This allows you to use a connection like a normal stream.  It
+/// behaves like [`Stream`].
+#[derive(Debug)]
+pub struct StreamOwned<C: Sized, T: AsyncRead + AsyncWrite + Unpin + Sized> {
+    /// Our connection
     pub conn: C,
 
     /// The underlying transport, like a socket
     pub sock: T,
 }
 
-impl<C, T, S> StreamOwned<C, T>
+impl<C, T> StreamOwned<C, T>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>>,
-    T: Read + Write,
-    S: SideData,
+    C: DerefMut + Deref<Target = ConnectionCommon>,
+    T: AsyncRead + AsyncWrite + Unpin,
 {
     /// Make a new StreamOwned taking the Connection `conn` and socket-like
     /// object `sock`.  This does not fail and does no IO.
@@ -181,15 +246,8 @@ where
     pub fn get_mut(&mut self) -> &mut T {
         &mut self.sock
     }
-}
 
-impl<'a, C, T, S> StreamOwned<C, T>
-where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>>,
-    T: Read + Write,
-    S: SideData,
-{
-    fn as_stream(&'a mut self) -> Stream<'a, C, T> {
+    fn as_stream(&mut self) -> Stream<'_, C, T> {
         Stream {
             conn: &mut self.conn,
             sock: &mut self.sock,
@@ -197,50 +255,65 @@ where
     }
 }
 
-impl<C, T, S> Read for StreamOwned<C, T>
+impl<C, T> AsyncRead for StreamOwned<C, T>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>>,
-    T: Read + Write,
-    S: SideData,
+    C: DerefMut + Deref<Target = ConnectionCommon> + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
 {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.as_stream().read(buf)
-    }
-
-    #[cfg(read_buf)]
-    fn read_buf(&mut self, buf: &mut std::io::ReadBuf<'_>) -> Result<()> {
-        self.as_stream().read_buf(buf)
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().as_stream()).poll_read(cx, buf)
     }
 }
 
-impl<C, T, S> Write for StreamOwned<C, T>
+impl<C, T> AsyncBufRead for StreamOwned<C, T>
 where
-    C: DerefMut + Deref<Target = ConnectionCommon<S>>,
-    T: Read + Write,
-    S: SideData,
+    C: DerefMut + Deref<Target = ConnectionCommon> + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
 {
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.as_stream().write(buf)
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        ready!(this.as_stream().poll_fill_plaintext(cx))?;
+
+        Poll::Ready(this.conn.fill_plaintext_buf())
     }
 
-    fn flush(&mut self) -> Result<()> {
-        self.as_stream().flush()
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        io::BufRead::consume(&mut self.get_mut().conn.reader(), amt)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{Stream, StreamOwned};
-    use crate::client::ClientConnection;
-    use std::net::TcpStream;
+impl<C, T> AsyncWrite for StreamOwned<C, T>
+where
+    C: DerefMut + Deref<Target = ConnectionCommon> + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().as_stream()).poll_write(cx, buf)
+    }
 
-    #[test]
-    fn stream_can_be_created_for_connection_and_tcpstream() {
-        type _Test<'a> = Stream<'a, ClientConnection, TcpStream>;
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().as_stream()).poll_flush(cx)
     }
 
-    #[test]
-    fn streamowned_can_be_created_for_client_and_tcpstream() {
-        type _Test = StreamOwned<ClientConnection, TcpStream>;
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().as_stream()).poll_close(cx)
     }
 }
+
+/// Polls `fut` once.
+///
+/// This is for operations that wait only on the transport, which leave
+/// the connection unchanged if dropped while pending, so can be started
+/// again on the next poll.
+fn poll_once<F: Future>(fut: F, cx: &mut Context<'_>) -> Poll<F::Output> {
+    futures::pin_mut!(fut);
+    fut.poll(cx)
+}
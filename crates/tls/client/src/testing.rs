@@ -0,0 +1,25 @@
+//! Helpers for testing against real servers.
+
+use crate::{
+    client::{ClientConfig, ServerName},
+    TlsStream,
+};
+use std::{io, sync::Arc};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// Connects to `addr` over TCP and completes a handshake with the server
+/// as `server_name`, using `config` and the [`RustCryptoBackend`].
+///
+/// The connected [`ClientConnection`] is available from the returned
+/// stream, with [`TlsStream::get_ref`] or [`TlsStream::into_inner`].
+///
+/// [`RustCryptoBackend`]: crate::RustCryptoBackend
+/// [`ClientConnection`]: crate::ClientConnection
+pub async fn connect_tcp(
+    config: Arc<ClientConfig>,
+    server_name: ServerName,
+    addr: impl ToSocketAddrs,
+) -> io::Result<TlsStream<TcpStream>> {
+    let socket = TcpStream::connect(addr).await?;
+    TlsStream::connect(config, server_name, socket).await
+}
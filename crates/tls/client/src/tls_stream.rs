@@ -0,0 +1,184 @@
+use crate::{
+    backend::RustCryptoBackend,
+    client::{ClientConfig, ClientConnection, ServerName},
+    stream::Stream,
+};
+use futures::ready;
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A TLS connection to a server over a tokio transport `IO`, such as a
+/// `tokio::net::TcpStream`.
+///
+/// This implements tokio's [`AsyncRead`] and [`AsyncWrite`], reading and
+/// writing plaintext.  It behaves like [`Stream`], so the connection's
+/// backend may suspend.
+#[derive(Debug)]
+pub struct TlsStream<IO> {
+    conn: ClientConnection,
+    io: Compat<IO>,
+    sent_close_notify: bool,
+}
+
+impl<IO> TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Connects to `server_name` over `io` using `config` and the
+    /// [`RustCryptoBackend`], and completes the handshake.
+    ///
+    /// Use [`TlsStream::new`] to connect with another backend.
+    pub async fn connect(
+        config: Arc<ClientConfig>,
+        server_name: ServerName,
+        io: IO,
+    ) -> io::Result<Self> {
+        let mut conn =
+            ClientConnection::new(config, Box::new(RustCryptoBackend::new()), server_name)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        conn.start()
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut stream = Self::new(conn, io);
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.io).await?;
+        }
+
+        Ok(stream)
+    }
+
+    /// Wraps `conn`, which must already have been started, and the
+    /// transport `io`.  This does not fail and does no IO; the handshake
+    /// is completed by the first read, write or flush.
+    pub fn new(conn: ClientConnection, io: IO) -> Self {
+        Self {
+            conn,
+            io: Compat(io),
+            sent_close_notify: false,
+        }
+    }
+
+    /// Returns the transport and the connection.
+    pub fn get_ref(&self) -> (&IO, &ClientConnection) {
+        (&self.io.0, &self.conn)
+    }
+
+    /// Returns the transport and the connection, mutably.
+    pub fn get_mut(&mut self) -> (&mut IO, &mut ClientConnection) {
+        (&mut self.io.0, &mut self.conn)
+    }
+
+    /// Returns the transport and the connection.
+    pub fn into_inner(self) -> (IO, ClientConnection) {
+        (self.io.0, self.conn)
+    }
+
+    fn as_stream(&mut self) -> Stream<'_, ClientConnection, Compat<IO>> {
+        Stream::new(&mut self.conn, &mut self.io)
+    }
+}
+
+impl<IO> AsyncRead for TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut stream = self.get_mut().as_stream();
+        let read = ready!(futures::AsyncRead::poll_read(
+            Pin::new(&mut stream),
+            cx,
+            buf.initialize_unfilled()
+        ))?;
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO> AsyncWrite for TlsStream<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        futures::AsyncWrite::poll_write(Pin::new(&mut self.get_mut().as_stream()), cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures::AsyncWrite::poll_flush(Pin::new(&mut self.get_mut().as_stream()), cx)
+    }
+
+    /// Sends a close_notify alert, writes out any buffered data and shuts
+    /// down the transport.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.sent_close_notify {
+            ready!(this.conn.poll_send_close_notify(cx))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            this.sent_close_notify = true;
+        }
+
+        futures::AsyncWrite::poll_close(Pin::new(&mut this.as_stream()), cx)
+    }
+}
+
+/// Adapts a tokio transport to the `futures` IO traits that [`Stream`]
+/// uses.
+#[derive(Debug)]
+struct Compat<IO>(IO);
+
+impl<IO> futures::AsyncRead for Compat<IO>
+where
+    IO: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(Pin::new(&mut self.get_mut().0).poll_read(cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+impl<IO> futures::AsyncWrite for Compat<IO>
+where
+    IO: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+    }
+}
@@ -2,12 +2,31 @@ use std::{cmp, collections::VecDeque, io, io::Read};
 
 use futures::{AsyncWrite, AsyncWriteExt};
 
+/// One of the byte vectors a [`ChunkVecBuffer`] is built from.
+///
+/// With the `zeroize` feature a chunk is zeroed when dropped, so that
+/// plaintext does not linger in freed memory.
+#[cfg(feature = "zeroize")]
+pub(crate) type Chunk = zeroize::Zeroizing<Vec<u8>>;
+#[cfg(not(feature = "zeroize"))]
+pub(crate) type Chunk = Vec<u8>;
+
+#[cfg(feature = "zeroize")]
+fn to_chunk(bytes: Vec<u8>) -> Chunk {
+    zeroize::Zeroizing::new(bytes)
+}
+
+#[cfg(not(feature = "zeroize"))]
+fn to_chunk(bytes: Vec<u8>) -> Chunk {
+    bytes
+}
+
 /// This is a byte buffer that is built from a vector
 /// of byte vectors.  This avoids extra copies when
 /// appending a new byte vector, at the expense of
 /// more complexity when reading out.
 pub(crate) struct ChunkVecBuffer {
-    chunks: VecDeque<Vec<u8>>,
+    chunks: VecDeque<Chunk>,
     limit: Option<usize>,
 }
 
@@ -69,7 +88,7 @@ impl ChunkVecBuffer {
         let len = bytes.len();
 
         if !bytes.is_empty() {
-            self.chunks.push_back(bytes);
+            self.chunks.push_back(to_chunk(bytes));
         }
 
         len
@@ -77,7 +96,7 @@ impl ChunkVecBuffer {
 
     /// Take one of the chunks from this object.  This
     /// function panics if the object `is_empty`.
-    pub(crate) fn pop(&mut self) -> Option<Vec<u8>> {
+    pub(crate) fn pop(&mut self) -> Option<Chunk> {
         self.chunks.pop_front()
     }
 
@@ -96,10 +115,17 @@ impl ChunkVecBuffer {
         Ok(offs)
     }
 
-    fn consume(&mut self, mut used: usize) {
+    /// The first contiguous run of buffered bytes, or an empty
+    /// slice if we're empty.
+    pub(crate) fn chunk(&self) -> &[u8] {
+        self.chunks.front().map_or(&[], |chunk| chunk.as_slice())
+    }
+
+    /// Discard the first `used` bytes.
+    pub(crate) fn consume(&mut self, mut used: usize) {
         while let Some(mut buf) = self.chunks.pop_front() {
             if used < buf.len() {
-                self.chunks.push_front(buf.split_off(used));
+                self.chunks.push_front(to_chunk(buf.split_off(used)));
                 break;
             } else {
                 used -= buf.len();
@@ -137,6 +163,8 @@ impl ChunkVecBuffer {
             *iov = io::IoSlice::new(chunk);
         }
         let len = cmp::min(bufs.len(), self.chunks.len());
+        // Only consume once the write has completed, so that dropping
+        // this future part-way through loses nothing.
         let used = wr.write_vectored(&bufs[..len]).await?;
         self.consume(used);
         Ok(used)
@@ -159,4 +187,19 @@ mod test {
         assert_eq!(cvb.read(&mut buf).unwrap(), 12);
         assert_eq!(buf.to_vec(), b"helloworldhe".to_vec());
     }
+
+    #[test]
+    fn chunk_and_consume() {
+        let mut cvb = ChunkVecBuffer::new(None);
+        assert_eq!(cvb.chunk(), b"");
+        cvb.append(b"hello".to_vec());
+        cvb.append(b"world".to_vec());
+        assert_eq!(cvb.chunk(), b"hello");
+        cvb.consume(3);
+        assert_eq!(cvb.chunk(), b"lo");
+        cvb.consume(2);
+        assert_eq!(cvb.chunk(), b"world");
+        cvb.consume(5);
+        assert!(cvb.is_empty());
+    }
 }
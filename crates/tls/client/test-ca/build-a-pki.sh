@@ -172,5 +172,29 @@ for kt in rsa ecdsa eddsa ; do
   cat $kt/inter.cert $kt/ca.cert > $kt/client.chain
   cat $kt/client.cert $kt/inter.cert $kt/ca.cert > $kt/client.fullchain
 
+  openssl pkcs12 -export \
+            -inkey $kt/client.key \
+            -in $kt/client.cert \
+            -certfile $kt/client.chain \
+            -name client \
+            -passout pass:test \
+            -certpbe PBE-SHA1-3DES \
+            -keypbe PBE-SHA1-3DES \
+            -macalg sha1 \
+            -out $kt/client.p12
+
   openssl asn1parse -in $kt/ca.cert -out $kt/ca.der > /dev/null
 done
+
+# An expired cross-signature of the RSA intermediate by the ECDSA CA, so
+# that a chain can offer two paths of which only one is valid.
+openssl x509 -req \
+          -in rsa/inter.req \
+          -out rsa/inter.xsigned.cert \
+          -CA ecdsa/ca.cert \
+          -CAkey ecdsa/ca.key \
+          -sha256 \
+          -not_before 20000101000000Z \
+          -not_after 20010101000000Z \
+          -set_serial 321 \
+          -extensions v3_inter -extfile openssl.cnf
@@ -0,0 +1,51 @@
+//! Checks that calls which are meant to be cheap do not allocate.
+//!
+//! These are kept out of `api.rs` because they replace the global
+//! allocator, to count the allocations made on each test's thread.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+mod common;
+use crate::common::*;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[tokio::test]
+async fn client_process_without_new_data_does_not_allocate() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    do_handshake(&mut client, &mut server).await;
+
+    let before = allocations();
+    let first = client.process_new_packets().await.unwrap();
+    let second = client.process_new_packets().await.unwrap();
+    let after = allocations();
+
+    assert!(!first.made_progress());
+    assert!(!second.made_progress());
+    assert_eq!(after, before);
+}
@@ -12,10 +12,13 @@ use std::{
         atomic::{AtomicUsize, Ordering},
         Arc, Mutex,
     },
+    task::{Context, Poll},
 };
 
 use tls_client::{
-    client::ResolvesClientCert, sign, CipherSuite, ClientConfig, ClientConnection, Error, KeyLog,
+    client::{PathPolicy, ResolvesClientCert},
+    fingerprint::ClientProfile,
+    sign, CipherSuite, ClientConfig, ClientConnection, Error, KeyLog, MaxFragmentLength,
     ProtocolVersion, RustCryptoBackend, SignatureScheme, SupportedCipherSuite, ALL_CIPHER_SUITES,
 };
 
@@ -54,6 +57,77 @@ async fn alpn_test_error(
     }
 }
 
+#[tokio::test]
+async fn client_rejects_alpn_outside_required_set() {
+    let mut server_config = make_server_config(KeyType::Rsa);
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    let server_config = Arc::new(server_config);
+
+    for version in tls_client::ALL_VERSIONS {
+        let mut client_config = make_client_config_with_versions(KeyType::Rsa, &[version]);
+        client_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        client_config.required_alpn = Some(vec![b"h2".to_vec()]);
+
+        let (mut client, mut server) =
+            make_pair_for_arc_configs(&Arc::new(client_config), &server_config).await;
+        let error = do_handshake_until_error(&mut client, &mut server).await;
+        assert_eq!(
+            error.err(),
+            Some(ErrorFromPeer::Client(Error::NoApplicationProtocol))
+        );
+    }
+}
+
+#[tokio::test]
+async fn client_accepts_alpn_inside_required_set() {
+    let mut server_config = make_server_config(KeyType::Rsa);
+    server_config.alpn_protocols = vec![b"h2".to_vec()];
+    let server_config = Arc::new(server_config);
+
+    for version in tls_client::ALL_VERSIONS {
+        let mut client_config = make_client_config_with_versions(KeyType::Rsa, &[version]);
+        client_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        client_config.required_alpn = Some(vec![b"h2".to_vec()]);
+
+        let (mut client, mut server) =
+            make_pair_for_arc_configs(&Arc::new(client_config), &server_config).await;
+        do_handshake(&mut client, &mut server).await;
+        assert_eq!(client.alpn_protocol(), Some(&b"h2"[..]));
+    }
+}
+
+#[tokio::test]
+async fn client_enforces_strict_alpn_preference() {
+    for version in tls_client::ALL_VERSIONS {
+        for (server_protos, expect_ok) in [
+            (vec![b"http/1.1".to_vec()], false),
+            (vec![b"h2".to_vec(), b"http/1.1".to_vec()], true),
+            (vec![], true),
+        ] {
+            let mut server_config = make_server_config(KeyType::Rsa);
+            server_config.alpn_protocols = server_protos;
+
+            let mut client_config = make_client_config_with_versions(KeyType::Rsa, &[version]);
+            client_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+            client_config.alpn_strict_client_preference = true;
+
+            let (mut client, mut server) =
+                make_pair_for_configs(client_config, server_config).await;
+            let result = do_handshake_until_error(&mut client, &mut server).await;
+            if expect_ok {
+                assert!(result.is_ok());
+            } else {
+                assert_eq!(
+                    result.err(),
+                    Some(ErrorFromPeer::Client(Error::PeerMisbehavedError(
+                        "ALPN protocol is not the client's preference".into()
+                    )))
+                );
+            }
+        }
+    }
+}
+
 async fn alpn_test(
     server_protos: Vec<Vec<u8>>,
     client_protos: Vec<Vec<u8>>,
@@ -227,6 +301,24 @@ fn config_builder_for_client_rejects_empty_cipher_suites() {
     );
 }
 
+#[test]
+fn config_builder_for_client_rejects_empty_signature_schemes() {
+    assert_eq!(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_signature_schemes(&[])
+            .err(),
+        Some(Error::General("no usable signature schemes".into()))
+    );
+    assert_eq!(
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_signature_schemes(&[SignatureScheme::RSA_PKCS1_SHA1])
+            .err(),
+        Some(Error::General("no usable signature schemes".into()))
+    );
+}
+
 #[cfg(feature = "tls12")]
 #[test]
 fn config_builder_for_client_rejects_incompatible_cipher_suites() {
@@ -240,6 +332,296 @@ fn config_builder_for_client_rejects_incompatible_cipher_suites() {
     );
 }
 
+#[cfg(feature = "tls12")]
+#[test]
+fn client_config_reports_enabled_protocol_versions() {
+    let config_for = |versions: &[&'static tls_client::SupportedProtocolVersion]| {
+        finish_client_config(
+            KeyType::Rsa,
+            ClientConfig::builder()
+                .with_cipher_suites(&[
+                    tls_core::suites::TLS13_AES_256_GCM_SHA384,
+                    tls_core::suites::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+                ])
+                .with_safe_default_kx_groups()
+                .with_protocol_versions(versions)
+                .unwrap(),
+        )
+    };
+
+    assert_eq!(
+        config_for(&[&tls_client::version::TLS13]).enabled_protocol_versions(),
+        vec![ProtocolVersion::TLSv1_3]
+    );
+    assert_eq!(
+        config_for(&[&tls_client::version::TLS12, &tls_client::version::TLS13])
+            .enabled_protocol_versions(),
+        vec![ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2]
+    );
+
+    // Without a usable cipher suite, a selected version is not offered.
+    assert_eq!(
+        make_client_config_with_versions(
+            KeyType::Rsa,
+            &[&tls_client::version::TLS12, &tls_client::version::TLS13]
+        )
+        .enabled_protocol_versions(),
+        vec![ProtocolVersion::TLSv1_2]
+    );
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_version_below_connection_minimum() {
+    let client_config = Arc::new(make_client_config_with_versions(
+        KeyType::Rsa,
+        &[&tls_client::version::TLS12, &tls_client::version::TLS13],
+    ));
+    let server_config = Arc::new(make_server_config_with_versions(
+        KeyType::Rsa,
+        &[&rustls::version::TLS12],
+    ));
+
+    let mut client = ClientConnection::new(
+        client_config.clone(),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    client.require_min_version(ProtocolVersion::TLSv1_3);
+    client.start().await.unwrap();
+    let mut server = ServerConnection::new(server_config.clone()).unwrap();
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server).await,
+        Err(ErrorFromPeer::Client(Error::UnsupportedProtocolVersion))
+    );
+
+    // Other connections using the same config are unaffected.
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config).await;
+    do_handshake(&mut client, &mut server).await;
+    assert_eq!(client.protocol_version(), Some(ProtocolVersion::TLSv1_2));
+}
+
+fn make_server_config_with_misordered_chain(kt: KeyType) -> ServerConfig {
+    let mut chain = kt.get_chain_rustls();
+    chain.swap(0, 1);
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(chain, kt.get_key_rustls())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn client_rejects_misordered_chain_by_default() {
+    let (mut client, mut server) = make_pair_for_configs(
+        make_client_config(KeyType::Rsa),
+        make_server_config_with_misordered_chain(KeyType::Rsa),
+    )
+    .await;
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server).await,
+        Err(ErrorFromPeer::Client(Error::CertificateChainOutOfOrder))
+    );
+}
+
+#[tokio::test]
+async fn client_reorders_misordered_chain_when_tolerated() {
+    for kt in ALL_KEY_TYPES.iter() {
+        let mut client_config = make_client_config(*kt);
+        client_config.tolerate_unordered_chain = true;
+        let (mut client, mut server) =
+            make_pair_for_configs(client_config, make_server_config_with_misordered_chain(*kt))
+                .await;
+        do_handshake(&mut client, &mut server).await;
+        assert_eq!(client.peer_certificates(), Some(kt.get_chain().as_slice()));
+    }
+}
+
+#[tokio::test]
+async fn client_skips_verifying_unrelated_certificates() {
+    let mut chain = KeyType::Rsa.get_chain_rustls();
+    chain.extend(KeyType::Ecdsa.get_chain_rustls().into_iter().skip(1));
+    let server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(chain, KeyType::Rsa.get_key_rustls())
+        .unwrap();
+
+    let (mut client, mut server) =
+        make_pair_for_configs(make_client_config(KeyType::Rsa), server_config).await;
+    assert_eq!(client.certificates_passed_to_verifier(), 0);
+    do_handshake(&mut client, &mut server).await;
+
+    // The end-entity and intermediate certificates; not the CA
+    // certificate, which is a trust anchor, nor the unrelated ECDSA ones.
+    assert_eq!(client.peer_certificates().unwrap().len(), 5);
+    assert_eq!(client.certificates_passed_to_verifier(), 2);
+}
+
+/// Makes a pair where the server's chain offers two paths to a trusted
+/// CA: first through an expired cross-signature, then through the
+/// ordinary intermediate.
+async fn make_pair_with_cross_signed_chain(
+    path_building: PathPolicy,
+) -> (ClientConnection, ServerConnection) {
+    let mut chain = KeyType::Rsa.get_chain_rustls();
+    chain.insert(1, get_expired_cross_signed_inter_rustls());
+    let server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(chain, KeyType::Rsa.get_key_rustls())
+        .unwrap();
+
+    let mut root_store = make_client_root_store(KeyType::Rsa);
+    root_store
+        .roots
+        .extend(make_client_root_store(KeyType::Ecdsa).roots);
+    let mut client_config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    client_config.tolerate_unordered_chain = true;
+    client_config.path_building = path_building;
+
+    make_pair_for_configs(client_config, server_config).await
+}
+
+#[tokio::test]
+async fn client_verifies_alternate_path_past_expired_cross_signature() {
+    let (mut client, mut server) =
+        make_pair_with_cross_signed_chain(PathPolicy::TryAlternates).await;
+    assert_eq!(client.trust_anchor_used(), None);
+    do_handshake(&mut client, &mut server).await;
+
+    let rsa_ca = &KeyType::Rsa.get_chain()[2];
+    let (_, rsa_ca_subject) = tls_core::x509::issuer_and_subject(&rsa_ca.0).unwrap();
+    assert_eq!(client.trust_anchor_used(), Some(rsa_ca_subject.to_vec()));
+}
+
+#[tokio::test]
+async fn client_rejects_expired_first_path_when_not_trying_alternates() {
+    let (mut client, mut server) =
+        make_pair_with_cross_signed_chain(PathPolicy::FirstPathOnly).await;
+    assert!(matches!(
+        do_handshake_until_error(&mut client, &mut server).await,
+        Err(ErrorFromPeer::Client(Error::CoreError(_)))
+    ));
+    assert_eq!(client.trust_anchor_used(), None);
+}
+
+#[tokio::test]
+async fn client_config_round_trips_through_bytes() {
+    for kt in ALL_KEY_TYPES.iter() {
+        let mut client_config = make_client_config(*kt);
+        client_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        client_config.max_handshake_fragments = 16;
+        client_config.tolerate_unordered_chain = true;
+        client_config.send_tls13_compat_ccs = false;
+
+        let bytes = client_config.to_bytes();
+        let rebuilt = ClientConfig::from_bytes(&bytes).unwrap();
+        assert_eq!(rebuilt.to_bytes(), bytes);
+        assert_eq!(rebuilt.alpn_protocols, client_config.alpn_protocols);
+        assert_eq!(rebuilt.max_handshake_fragments, 16);
+        assert!(rebuilt.tolerate_unordered_chain);
+        assert!(!rebuilt.send_tls13_compat_ccs);
+
+        let mut server_config = make_server_config(*kt);
+        server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let (mut client, mut server) = make_pair_for_configs(rebuilt, server_config).await;
+        do_handshake(&mut client, &mut server).await;
+        assert_eq!(client.alpn_protocol(), Some(&b"http/1.1"[..]));
+    }
+}
+
+#[test]
+fn client_config_rejects_malformed_bytes() {
+    let bytes = make_client_config(KeyType::Rsa).to_bytes();
+    assert!(ClientConfig::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+    let mut unknown_format = bytes.clone();
+    unknown_format[0] = 0xff;
+    assert!(ClientConfig::from_bytes(&unknown_format).is_err());
+
+    let mut trailing = bytes;
+    trailing.push(0);
+    assert!(ClientConfig::from_bytes(&trailing).is_err());
+}
+
+#[cfg(feature = "pkcs12")]
+fn make_client_config_with_pkcs12(kt: KeyType, password: &str) -> Result<ClientConfig, Error> {
+    ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(make_client_root_store(kt))
+        .with_client_auth_pkcs12(kt.get_client_pkcs12(), password)
+}
+
+#[cfg(feature = "pkcs12")]
+#[tokio::test]
+async fn client_auth_with_pkcs12_identity() {
+    for kt in ALL_KEY_TYPES.iter() {
+        let client_config = make_client_config_with_pkcs12(*kt, "test").unwrap();
+        let server_config = make_server_config_with_mandatory_client_auth(*kt);
+        let (mut client, mut server) = make_pair_for_configs(client_config, server_config).await;
+        do_handshake(&mut client, &mut server).await;
+
+        let client_certs = server.peer_certificates().unwrap();
+        assert_eq!(client_certs, &kt.get_client_chain_rustls()[..]);
+    }
+}
+
+#[cfg(feature = "pkcs12")]
+#[test]
+fn client_auth_with_pkcs12_rejects_wrong_password() {
+    assert!(matches!(
+        make_client_config_with_pkcs12(KeyType::Rsa, "wrong"),
+        Err(Error::General(_))
+    ));
+}
+
+#[tokio::test]
+async fn client_reports_server_name() {
+    let (client, _) = make_pair(KeyType::Rsa).await;
+    assert_eq!(client.server_name(), &dns_name("localhost"));
+    assert_eq!(client.server_name().sni_hostname(), Some("localhost"));
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_reports_offered_cipher_suites() {
+    let client_config = finish_client_config(
+        KeyType::Rsa,
+        ClientConfig::builder()
+            .with_cipher_suites(&[
+                tls_core::suites::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+                tls_core::suites::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+            ])
+            .with_safe_default_kx_groups()
+            .with_safe_default_protocol_versions()
+            .unwrap(),
+    );
+    let mut client = ClientConnection::new(
+        Arc::new(client_config),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    assert!(client.offered_cipher_suites().is_empty());
+
+    client.start().await.unwrap();
+    assert_eq!(
+        client.offered_cipher_suites(),
+        &[
+            CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+            CipherSuite::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
+            CipherSuite::TLS_EMPTY_RENEGOTIATION_INFO_SCSV,
+        ]
+    );
+    let sent = client_hello(&mut client).cipher_suites;
+    assert_eq!(client.offered_cipher_suites(), &sent[..]);
+}
+
 #[tokio::test]
 #[ignore = "needs to be fixed"]
 async fn servered_client_data_sent() {
@@ -279,6 +661,30 @@ async fn servered_server_data_sent() {
     }
 }
 
+#[tokio::test]
+async fn client_stream_reads_lines() {
+    use futures::AsyncBufReadExt;
+
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    do_handshake(&mut client, &mut server).await;
+
+    server.writer().write_all(b"hello\r\nworld\r\n").unwrap();
+    server.send_close_notify();
+
+    let mut pipe = BlockingIo(ServerSession::new(&mut server));
+    let mut stream = tls_client::Stream::new(&mut client, &mut pipe);
+    let mut line = String::new();
+    stream.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "hello\r\n");
+
+    line.clear();
+    stream.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "world\r\n");
+
+    line.clear();
+    assert_eq!(stream.read_line(&mut line).await.unwrap(), 0);
+}
+
 #[tokio::test]
 async fn servered_both_data_sent() {
     let server_config = Arc::new(make_server_config(KeyType::Rsa));
@@ -314,6 +720,13 @@ async fn client_can_get_server_cert() {
 
             let certs = client.peer_certificates();
             assert_eq!(certs, Some(kt.get_chain().as_slice()));
+
+            let der: Vec<&[u8]> = client.peer_certificates_der().unwrap().collect();
+            let chain = kt.get_chain();
+            assert_eq!(
+                der,
+                chain.iter().map(|c| c.0.as_slice()).collect::<Vec<_>>()
+            );
         }
     }
 }
@@ -358,6 +771,67 @@ async fn server_can_get_client_cert() {
     }
 }
 
+#[tokio::test]
+async fn client_sends_grease_ech_when_enabled() {
+    use tls_client::internal::msgs::{enums::ExtensionType, handshake::ClientExtension};
+
+    let (mut client, _) = make_pair(KeyType::Rsa).await;
+    assert!(client_hello(&mut client)
+        .find_extension(ExtensionType::EncryptedClientHello)
+        .is_none());
+
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.enable_ech_grease = true;
+    let client_config = Arc::new(client_config);
+    let server_config = Arc::new(make_server_config(KeyType::Rsa));
+
+    let (mut client, _) = make_pair_for_arc_configs(&client_config, &server_config).await;
+    match client_hello(&mut client).find_extension(ExtensionType::EncryptedClientHello) {
+        Some(ClientExtension::Unknown(ext)) => {
+            let body = &ext.payload.0;
+            assert_eq!(body.len(), 186);
+            assert_eq!(&body[..5], &[0x00, 0x00, 0x01, 0x00, 0x01]);
+            assert_eq!(&body[6..8], &[0x00, 0x20]);
+            assert_eq!(&body[40..42], &[0x00, 0x90]);
+        }
+        _ => panic!("encrypted_client_hello not sent"),
+    }
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config).await;
+    do_handshake(&mut client, &mut server).await;
+}
+
+#[tokio::test]
+async fn client_falls_back_to_x509_without_raw_public_key_support() {
+    use tls_client::internal::msgs::{
+        enums::{CertificateType, ExtensionType},
+        handshake::ClientExtension,
+    };
+
+    let kt = KeyType::Rsa;
+    let mut client_config = make_client_config_with_auth(kt);
+    client_config.client_cert_type_preference =
+        vec![CertificateType::RawPublicKey, CertificateType::X509];
+    let client_config = Arc::new(client_config);
+    let server_config = Arc::new(make_server_config_with_mandatory_client_auth(kt));
+
+    let (mut client, _) = make_pair_for_arc_configs(&client_config, &server_config).await;
+    match client_hello(&mut client).find_extension(ExtensionType::ClientCertificateType) {
+        Some(ClientExtension::ClientCertificateTypes(types)) => assert_eq!(
+            types,
+            &vec![CertificateType::RawPublicKey, CertificateType::X509]
+        ),
+        _ => panic!("client_certificate_type not offered"),
+    }
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config).await;
+    do_handshake(&mut client, &mut server).await;
+    assert_eq!(
+        server.peer_certificates(),
+        Some(kt.get_client_chain_rustls().as_slice())
+    );
+}
+
 #[tokio::test]
 async fn server_can_get_client_cert_after_resumption() {
     for kt in ALL_KEY_TYPES.iter() {
@@ -477,6 +951,22 @@ async fn client_close_notify() {
     }
 }
 
+#[tokio::test]
+async fn client_close_writes_close_notify() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    do_handshake(&mut client, &mut server).await;
+
+    assert_eq!(12, client.write_plaintext(b"from-client!").await.unwrap());
+    let mut io = Vec::new();
+    client.close(&mut io).await.unwrap();
+    assert!(!client.wants_write());
+
+    server.read_tls(&mut &io[..]).unwrap();
+    let io_state = server.process_new_packets().unwrap();
+    assert!(io_state.peer_has_closed());
+    check_read_and_close(&mut server.reader(), b"from-client!");
+}
+
 #[tokio::test]
 async fn server_closes_uncleanly() {
     let kt = KeyType::Rsa;
@@ -508,6 +998,25 @@ async fn server_closes_uncleanly() {
     }
 }
 
+#[tokio::test]
+async fn client_allows_unclean_eof_when_configured() {
+    let kt = KeyType::Rsa;
+    let mut client_config = make_client_config(kt);
+    client_config.allow_unclean_eof = true;
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(kt)).await;
+    do_handshake(&mut client, &mut server).await;
+
+    assert_eq!(12, server.writer().write(b"from-server!").unwrap());
+    receive(&mut server, &mut client);
+    transfer_eof(&mut client);
+    let io_state = client.process_new_packets().await.unwrap();
+    assert!(!io_state.peer_has_closed());
+    check_read(&mut client.reader(), b"from-server!");
+
+    assert_eq!(client.reader().read(&mut [0u8; 1]).unwrap(), 0);
+}
+
 #[tokio::test]
 async fn client_closes_uncleanly() {
     let kt = KeyType::Rsa;
@@ -730,16 +1239,62 @@ async fn server_cert_resolve_reduces_sigalgs_for_ecdsa_ciphersuite() {
     .await;
 }
 
-struct ServerCheckNoSNI {}
-
-impl ResolvesServerCert for ServerCheckNoSNI {
-    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<rustls::sign::CertifiedKey>> {
-        assert!(client_hello.server_name().is_none());
-
-        None
-    }
-}
-
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_sigalg_reduction_can_refuse_server_scheme() {
+    let seen = Arc::new(Mutex::new(None));
+    let mut client_config = make_client_config(KeyType::Rsa);
+    let record = seen.clone();
+    client_config.sigalg_reduction = Some(Arc::new(
+        move |suite: CipherSuite, offered: &[SignatureScheme]| {
+            *record.lock().unwrap() = Some(suite);
+            offered
+                .iter()
+                .filter(|scheme| !format!("{:?}", scheme).starts_with("RSA_PSS"))
+                .copied()
+                .collect()
+        },
+    ));
+
+    // The server prefers PSS for its RSA key.
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    let err = do_handshake_until_error(&mut client, &mut server).await;
+    assert!(matches!(
+        err,
+        Err(ErrorFromPeer::Client(Error::PeerMisbehavedError(ref why)))
+            if why.starts_with("peer signed kx with wrong algorithm (got RSA_PSS")
+    ));
+    assert_eq!(
+        *seen.lock().unwrap(),
+        client.negotiated_cipher_suite().map(|suite| suite.suite())
+    );
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_sigalg_reduction_accepts_allowed_scheme() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.sigalg_reduction =
+        Some(Arc::new(|_: CipherSuite, offered: &[SignatureScheme]| {
+            offered.to_vec()
+        }));
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    do_handshake(&mut client, &mut server).await;
+}
+
+struct ServerCheckNoSNI {}
+
+impl ResolvesServerCert for ServerCheckNoSNI {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        assert!(client_hello.server_name().is_none());
+
+        None
+    }
+}
+
 #[tokio::test]
 async fn client_with_sni_disabled_does_not_send_sni() {
     for kt in ALL_KEY_TYPES.iter() {
@@ -795,6 +1350,331 @@ async fn client_checks_server_certificate_with_given_name() {
     }
 }
 
+#[tokio::test]
+async fn client_checks_server_certificate_with_given_ip_address() {
+    let server_name = tls_client::ServerName::try_from("192.0.2.1").unwrap();
+    assert_eq!(
+        server_name,
+        tls_client::ServerName::IpAddress("192.0.2.1".parse().unwrap())
+    );
+
+    for kt in ALL_KEY_TYPES.iter() {
+        let server_config = Arc::new(make_server_config(*kt));
+
+        for version in tls_client::ALL_VERSIONS {
+            let client_config = make_client_config_with_versions(*kt, &[version]);
+            let mut client = ClientConnection::new(
+                Arc::new(client_config),
+                Box::new(RustCryptoBackend::new()),
+                server_name.clone(),
+            )
+            .unwrap();
+            client.start().await.unwrap();
+            let mut server = ServerConnection::new(Arc::clone(&server_config)).unwrap();
+
+            let err = do_handshake_until_error(&mut client, &mut server).await;
+            assert_eq!(
+                err,
+                Err(ErrorFromPeer::Client(Error::CoreError(
+                    tls_core::Error::InvalidCertificateData(
+                        "invalid peer certificate: CertNotValidForName".into(),
+                    )
+                )))
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn client_sends_no_sni_for_ip_address() {
+    let mut client = ClientConnection::new(
+        Arc::new(make_client_config(KeyType::Rsa)),
+        Box::new(RustCryptoBackend::new()),
+        tls_client::ServerName::try_from("192.0.2.1").unwrap(),
+    )
+    .unwrap();
+    client.start().await.unwrap();
+    assert!(client_hello(&mut client).get_sni_extension().is_none());
+}
+
+#[cfg(feature = "webpki-roots")]
+#[tokio::test]
+async fn client_with_webpki_roots_rejects_unknown_issuer() {
+    let client_config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_webpki_roots()
+        .with_no_client_auth();
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+
+    let err = do_handshake_until_error(&mut client, &mut server).await;
+    assert_eq!(
+        err,
+        Err(ErrorFromPeer::Client(Error::CoreError(
+            tls_core::Error::InvalidCertificateData(
+                "invalid peer certificate: UnknownIssuer".into(),
+            )
+        )))
+    );
+}
+
+#[tokio::test]
+async fn client_counts_records_under_current_keys() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    assert_eq!(client.record_encryption_count(), 0);
+    assert_eq!(client.record_decryption_count(), 0);
+
+    // the Finished messages are the first records under the new keys
+    do_handshake(&mut client, &mut server).await;
+    assert_eq!(client.record_encryption_count(), 1);
+    assert_eq!(client.record_decryption_count(), 1);
+
+    client.write_plaintext(b"hello").await.unwrap();
+    client.flush_plaintext().await.unwrap();
+    assert_eq!(client.record_encryption_count(), 2);
+}
+
+#[tokio::test]
+async fn client_wants_read_and_write_follow_handshake() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    // The ClientHello is waiting to be written.
+    assert!(client.wants_write());
+    assert!(!client.wants_read());
+
+    while client.is_handshaking() {
+        send(&mut client, &mut server);
+        assert!(!client.wants_write());
+        assert!(client.wants_read());
+        server.process_new_packets().unwrap();
+        receive(&mut server, &mut client);
+        client.process_new_packets().await.unwrap();
+    }
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    assert!(!client.wants_write());
+    assert!(client.wants_read());
+
+    // Unread plaintext holds off further reads.
+    server.writer().write_all(b"hello").unwrap();
+    receive(&mut server, &mut client);
+    client.process_new_packets().await.unwrap();
+    assert!(!client.wants_read());
+    check_read(&mut client.reader(), b"hello");
+    assert!(client.wants_read());
+
+    transfer_eof(&mut client);
+    assert!(!client.wants_read());
+}
+
+#[tokio::test]
+async fn client_reads_tls_from_slice() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+
+    while client.is_handshaking() {
+        send(&mut client, &mut server);
+        server.process_new_packets().unwrap();
+
+        let mut buf = Vec::new();
+        while server.wants_write() {
+            server.write_tls(&mut buf).unwrap();
+        }
+        assert_eq!(client.read_tls_from_slice(&buf).unwrap(), buf.len());
+        client.process_new_packets().await.unwrap();
+    }
+
+    server.writer().write_all(b"hello").unwrap();
+    let mut buf = Vec::new();
+    server.write_tls(&mut buf).unwrap();
+    // Bytes split across calls are reassembled.
+    let (first, second) = buf.split_at(3);
+    assert_eq!(client.read_tls_from_slice(first).unwrap(), 3);
+    assert_eq!(client.read_tls_from_slice(second).unwrap(), second.len());
+    client.process_new_packets().await.unwrap();
+    check_read(&mut client.reader(), b"hello");
+}
+
+#[tokio::test]
+async fn client_read_tls_from_slice_rejects_non_tls() {
+    let (mut client, _) = make_pair(KeyType::Rsa).await;
+    let garbage = [0xffu8; 32];
+    assert!(client.read_tls_from_slice(&garbage).unwrap() > 0);
+    assert!(client.process_new_packets().await.is_err());
+    assert_eq!(
+        client.read_tls_from_slice(&garbage),
+        Err(Error::CorruptMessage)
+    );
+}
+
+#[tokio::test]
+async fn client_reports_connection_stats() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    // Only the ClientHello has been queued so far.
+    assert_eq!(
+        client.stats(),
+        tls_client::ConnectionStats {
+            records_sent: 1,
+            ..Default::default()
+        }
+    );
+
+    let (to_server, to_client) = do_handshake(&mut client, &mut server).await;
+    let stats = client.stats();
+    assert_eq!(stats.tls_bytes_written, to_server as u64);
+    assert_eq!(stats.tls_bytes_read, to_client as u64);
+    assert!(stats.records_sent > 0);
+    assert!(stats.records_received > 0);
+    assert!(stats.process_new_packets_calls > 0);
+
+    client.write_plaintext(b"hello").await.unwrap();
+    client.flush_plaintext().await.unwrap();
+    send(&mut client, &mut server);
+    server.writer().write_all(b"world!").unwrap();
+    receive(&mut server, &mut client);
+    client.process_new_packets().await.unwrap();
+
+    let after = client.stats();
+    assert_eq!(after.plaintext_bytes_written, 5);
+    assert_eq!(after.plaintext_bytes_read, 6);
+    assert_eq!(after.records_sent, stats.records_sent + 1);
+    assert_eq!(after.records_received, stats.records_received + 1);
+    assert_eq!(
+        after.process_new_packets_calls,
+        stats.process_new_packets_calls + 1
+    );
+}
+
+#[tokio::test]
+async fn client_reports_negotiated_key_exchange_group() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    assert!(client.negotiated_key_exchange_group().is_none());
+
+    do_handshake(&mut client, &mut server).await;
+    assert_eq!(
+        client.negotiated_key_exchange_group().map(|skxg| skxg.name),
+        Some(tls_client::kx_group::SECP256R1.name)
+    );
+}
+
+struct FixedTime(std::time::SystemTime);
+
+impl tls_client::client::TimeProvider for FixedTime {
+    fn now(&self) -> std::time::SystemTime {
+        self.0
+    }
+}
+
+#[tokio::test]
+async fn client_checks_server_certificate_at_provided_time() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.time_provider = Arc::new(FixedTime(std::time::UNIX_EPOCH));
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server).await,
+        Err(ErrorFromPeer::Client(Error::CoreError(
+            tls_core::Error::InvalidCertificateData(
+                "invalid peer certificate: CertNotValidYet".into(),
+            )
+        )))
+    );
+
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.time_provider = Arc::new(FixedTime(std::time::SystemTime::now()));
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    do_handshake(&mut client, &mut server).await;
+}
+
+#[tokio::test]
+async fn client_reports_pending_alert_after_verification_failure() {
+    let server_config = Arc::new(make_server_config(KeyType::Rsa));
+    let client_config = make_client_config(KeyType::Rsa);
+    let mut client = ClientConnection::new(
+        Arc::new(client_config),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("not-the-right-hostname.com"),
+    )
+    .unwrap();
+    client.start().await.unwrap();
+    assert_eq!(client.pending_alert(), None);
+    let mut server = ServerConnection::new(Arc::clone(&server_config)).unwrap();
+
+    let err = do_handshake_until_error(&mut client, &mut server).await;
+    assert!(err.is_err());
+    assert_eq!(
+        client.pending_alert(),
+        Some(AlertDescription::BadCertificate)
+    );
+
+    send(&mut client, &mut server);
+    assert_eq!(client.pending_alert(), None);
+}
+
+#[cfg(feature = "dangerous_configuration")]
+struct CountingVerifier {
+    calls: AtomicUsize,
+    accept: bool,
+}
+
+#[cfg(feature = "dangerous_configuration")]
+#[async_trait::async_trait]
+impl tls_client::client::ServerCertVerifierAsync for CountingVerifier {
+    async fn verify_server_cert(
+        &self,
+        _end_entity: &tls_client::Certificate,
+        intermediates: &[tls_client::Certificate],
+        _server_name: &tls_client::ServerName,
+        _scts: &[&[u8]],
+        _ocsp_response: &[u8],
+        _now: web_time::SystemTime,
+    ) -> Result<tls_core::verify::ServerCertVerified, tls_core::Error> {
+        tokio::task::yield_now().await;
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(intermediates.len(), 2);
+        if self.accept {
+            Ok(tls_core::verify::ServerCertVerified::assertion())
+        } else {
+            Err(tls_core::Error::InvalidCertificateData(
+                "pinned certificate mismatch".into(),
+            ))
+        }
+    }
+}
+
+#[cfg(feature = "dangerous_configuration")]
+#[tokio::test]
+async fn client_awaits_custom_certificate_verifier() {
+    for accept in [true, false] {
+        let verifier = Arc::new(CountingVerifier {
+            calls: AtomicUsize::new(0),
+            accept,
+        });
+        let mut client_config = make_client_config(KeyType::Rsa);
+        client_config
+            .dangerous()
+            .set_certificate_verifier(verifier.clone());
+        let (mut client, mut server) =
+            make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+
+        let result = do_handshake_until_error(&mut client, &mut server).await;
+        assert_eq!(verifier.calls.load(Ordering::SeqCst), 1);
+        if accept {
+            assert!(result.is_ok());
+        } else {
+            assert_eq!(
+                result,
+                Err(ErrorFromPeer::Client(Error::CoreError(
+                    tls_core::Error::InvalidCertificateData("pinned certificate mismatch".into())
+                )))
+            );
+            assert_eq!(
+                client.pending_alert(),
+                Some(AlertDescription::BadCertificate)
+            );
+        }
+    }
+}
+
 struct ClientCheckCertResolve {
     query_count: AtomicUsize,
     expect_queries: usize,
@@ -864,6 +1744,47 @@ async fn client_cert_resolve() {
     }
 }
 
+struct AsyncClientCheckCertResolve(ClientCheckCertResolve);
+
+#[async_trait::async_trait]
+impl tls_client::client::ResolvesClientCertAsync for AsyncClientCheckCertResolve {
+    async fn resolve(
+        &self,
+        acceptable_issuers: &[&[u8]],
+        sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<sign::CertifiedKey>> {
+        tokio::task::yield_now().await;
+        ResolvesClientCert::resolve(&self.0, acceptable_issuers, sigschemes)
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+#[tokio::test]
+async fn client_cert_resolve_async() {
+    for kt in ALL_KEY_TYPES.iter() {
+        let server_config = Arc::new(make_server_config_with_mandatory_client_auth(*kt));
+
+        for version in tls_client::ALL_VERSIONS {
+            let mut client_config = make_client_config_with_versions(*kt, &[version]);
+            client_config.client_auth_cert_resolver =
+                Arc::new(AsyncClientCheckCertResolve(ClientCheckCertResolve::new(1)));
+
+            let (mut client, mut server) =
+                make_pair_for_arc_configs(&Arc::new(client_config), &server_config).await;
+
+            assert_eq!(
+                do_handshake_until_error(&mut client, &mut server).await,
+                Err(ErrorFromPeer::Server(
+                    rustls::Error::NoCertificatesPresented
+                ))
+            );
+        }
+    }
+}
+
 #[tokio::test]
 async fn client_auth_works() {
     for kt in ALL_KEY_TYPES.iter() {
@@ -879,30 +1800,505 @@ async fn client_auth_works() {
 }
 
 #[tokio::test]
-async fn client_error_is_sticky() {
-    let (mut client, _) = make_pair(KeyType::Rsa).await;
-    client
-        .read_tls(&mut b"\x16\x03\x03\x00\x08\x0f\x00\x00\x04junk".as_ref())
-        .unwrap();
-    let mut err = client.process_new_packets().await;
-    assert!(err.is_err());
-    err = client.process_new_packets().await;
-    assert!(err.is_err());
+async fn client_poll_process_drives_handshake() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    let waker = futures::task::noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    while server.is_handshaking() || client.is_handshaking() {
+        send(&mut client, &mut server);
+        server.process_new_packets().unwrap();
+        receive(&mut server, &mut client);
+        match client.poll_process(&mut cx) {
+            Poll::Ready(result) => {
+                result.unwrap();
+            }
+            Poll::Pending => panic!("poll_process returned Pending"),
+        }
+    }
 }
 
-#[tokio::test]
-#[allow(clippy::no_effect)]
-async fn client_is_send() {
-    let (client, _) = make_pair(KeyType::Rsa).await;
-    &client as &dyn Send;
+/// Makes a started client whose backend suspends, and its server.
+async fn make_suspending_pair() -> (ClientConnection, ServerConnection) {
+    let mut client = ClientConnection::new(
+        Arc::new(make_client_config(KeyType::Rsa)),
+        Box::new(SuspendingBackend::new()),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    client.start().await.unwrap();
+    let server = ServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+    (client, server)
 }
 
 #[tokio::test]
-#[ignore = "needs to be fixed"]
-async fn client_respects_buffer_limit_pre_handshake() {
-    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
-
-    client.set_buffer_limit(Some(32));
+async fn client_poll_process_resumes_suspended_backend() {
+    let (mut client, mut server) = make_suspending_pair().await;
+    let waker = futures::task::noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    let mut suspended = 0;
+    while server.is_handshaking() || client.is_handshaking() {
+        send(&mut client, &mut server);
+        server.process_new_packets().unwrap();
+        receive(&mut server, &mut client);
+        loop {
+            match client.poll_process(&mut cx) {
+                Poll::Ready(result) => {
+                    result.unwrap();
+                    break;
+                }
+                Poll::Pending => suspended += 1,
+            }
+        }
+    }
+    assert!(suspended > 0);
+
+    server.writer().write_all(b"hello").unwrap();
+    receive(&mut server, &mut client);
+    client.process_new_packets().await.unwrap();
+    check_read(&mut client.reader(), b"hello");
+}
+
+#[tokio::test]
+async fn client_stream_resumes_suspended_backend() {
+    let (mut client, mut server) = make_suspending_pair().await;
+    do_handshake(&mut client, &mut server).await;
+    let waker = futures::task::noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    let mut sock = futures::io::Cursor::new(Vec::new());
+    let mut stream = tls_client::Stream::new(&mut client, &mut sock);
+    let mut stream = std::pin::Pin::new(&mut stream);
+    let written = loop {
+        if let Poll::Ready(written) =
+            futures::AsyncWrite::poll_write(stream.as_mut(), &mut cx, b"hello")
+        {
+            break written.unwrap();
+        }
+    };
+    assert_eq!(written, 5);
+    while futures::AsyncWrite::poll_flush(stream.as_mut(), &mut cx).is_pending() {}
+
+    server.read_tls(&mut sock.into_inner().as_slice()).unwrap();
+    server.process_new_packets().unwrap();
+    let mut buf = [0u8; 5];
+    server.reader().read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn client_handshakes_with_async_server() {
+    use tls_client::server::AsyncServerConnection;
+
+    let mut client = ClientConnection::new(
+        Arc::new(make_client_config(KeyType::Rsa)),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    client.start().await.unwrap();
+    let mut server =
+        AsyncServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+
+    while server.is_handshaking() || client.is_handshaking() {
+        let mut buf = Vec::new();
+        while client.wants_write() {
+            client.write_tls(&mut buf).unwrap();
+        }
+        server.read_tls(&mut buf.as_slice()).unwrap();
+        server.process_new_packets().unwrap();
+
+        buf.clear();
+        while server.wants_write() {
+            server.write_tls(&mut buf).unwrap();
+        }
+        client.read_tls(&mut buf.as_slice()).unwrap();
+        client.process_new_packets().await.unwrap();
+    }
+
+    server.write_all_plaintext(b"hello").await.unwrap();
+    let mut buf = Vec::new();
+    server.write_tls(&mut buf).unwrap();
+    client.read_tls(&mut buf.as_slice()).unwrap();
+    client.process_new_packets().await.unwrap();
+    check_read(&mut client.reader(), b"hello");
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn async_server_takes_all_of_each_transport_read() {
+    use tls_client::server::AsyncServerConnection;
+
+    let mut client = ClientConnection::new(
+        Arc::new(make_client_config(KeyType::Rsa)),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    client.start().await.unwrap();
+    let mut server =
+        AsyncServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+
+    while server.is_handshaking() || client.is_handshaking() {
+        let mut buf = Vec::new();
+        while client.wants_write() {
+            client.write_tls(&mut buf).unwrap();
+        }
+        server.read_tls(&mut buf.as_slice()).unwrap();
+        server.process_new_packets().unwrap();
+
+        buf.clear();
+        while server.wants_write() {
+            server.write_tls(&mut buf).unwrap();
+        }
+        client.read_tls(&mut buf.as_slice()).unwrap();
+        client.process_new_packets().await.unwrap();
+    }
+
+    // Full-sized records straddle the transport reads, so rustls cannot
+    // take all of some of them at once.
+    let data = vec![0x5a; 40_000];
+    client.write_all_plaintext(&data).await.unwrap();
+    let mut tls = Vec::new();
+    while client.wants_write() {
+        client.write_tls(&mut tls).unwrap();
+    }
+
+    let mut rd = futures::io::Cursor::new(tls.clone());
+    let mut read = 0;
+    let mut received = Vec::new();
+    while read < tls.len() {
+        read += server.read_tls_async(&mut rd).await.unwrap();
+        server.process_new_packets().unwrap();
+        server.reader().read_to_end(&mut received).unwrap_err();
+    }
+    assert_eq!(read, tls.len());
+    assert_eq!(received, data);
+}
+
+#[tokio::test]
+async fn client_error_is_sticky() {
+    let (mut client, _) = make_pair(KeyType::Rsa).await;
+    client
+        .read_tls(&mut b"\x16\x03\x03\x00\x08\x0f\x00\x00\x04junk".as_ref())
+        .unwrap();
+    let mut err = client.process_new_packets().await;
+    assert!(err.is_err());
+    err = client.process_new_packets().await;
+    assert!(err.is_err());
+}
+
+#[cfg(feature = "dangerous_configuration")]
+#[tokio::test]
+async fn client_uses_injected_handshake_randomness() {
+    use tls_client::internal::msgs::{enums::NamedGroup, handshake::Random};
+
+    let make_client = || {
+        let mut backend = RustCryptoBackend::new();
+        backend.set_client_random([7u8; 32]);
+        backend
+            .set_key_share_private(NamedGroup::secp256r1, &[1u8; 32])
+            .unwrap();
+        ClientConnection::new(
+            Arc::new(make_client_config(KeyType::Rsa)),
+            Box::new(backend),
+            dns_name("localhost"),
+        )
+        .unwrap()
+    };
+
+    let mut client = make_client();
+    client.start().await.unwrap();
+    assert_eq!(client_hello(&mut client).random, Random([7u8; 32]));
+
+    // The injected key share must still produce a working handshake.
+    let mut client = make_client();
+    client.start().await.unwrap();
+    let mut server = ServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+    do_handshake(&mut client, &mut server).await;
+}
+
+struct FixedRandom(u8);
+
+impl tls_client::SecureRandomProvider for FixedRandom {
+    fn fill(&self, bytes: &mut [u8]) -> Result<(), tls_client::GetRandomFailed> {
+        bytes.fill(self.0);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn client_server_name_can_be_set_before_start() {
+    let mut client = ClientConnection::new(
+        Arc::new(make_client_config(KeyType::Rsa)),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("not-the-server.example"),
+    )
+    .unwrap();
+    client.set_server_name(dns_name("localhost")).unwrap();
+    assert_eq!(client.server_name(), &dns_name("localhost"));
+    client.start().await.unwrap();
+
+    assert_eq!(
+        client.set_server_name(dns_name("example.com")),
+        Err(Error::General(
+            "server name cannot be changed after start".into()
+        ))
+    );
+
+    let mut server = ServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+    do_handshake(&mut client, &mut server).await;
+    assert_eq!(client.server_name(), &dns_name("localhost"));
+}
+
+#[tokio::test]
+async fn client_precomputes_key_shares_before_start() {
+    let mut client = ClientConnection::new(
+        Arc::new(make_client_config(KeyType::Rsa)),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    client.precompute_key_shares().await.unwrap();
+    client.start().await.unwrap();
+    assert_eq!(
+        client.precompute_key_shares().await,
+        Err(Error::General(
+            "key shares cannot be precomputed after start".into()
+        ))
+    );
+
+    let mut server = ServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+    do_handshake(&mut client, &mut server).await;
+}
+
+#[tokio::test]
+async fn client_uses_configured_rng_provider() {
+    use tls_client::internal::msgs::handshake::SessionID;
+
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.rng_provider = Arc::new(FixedRandom(0x42));
+    let client_config = Arc::new(client_config);
+
+    let mut client = ClientConnection::new(
+        client_config.clone(),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    client.start().await.unwrap();
+    assert_eq!(
+        client_hello(&mut client).session_id,
+        SessionID::from([0x42; 32])
+    );
+
+    let (mut client, mut server) =
+        make_pair_for_arc_configs(&client_config, &Arc::new(make_server_config(KeyType::Rsa)))
+            .await;
+    do_handshake(&mut client, &mut server).await;
+}
+
+#[cfg(feature = "dangerous_extract_secrets")]
+#[tokio::test]
+async fn client_extracts_secrets_after_handshake() {
+    let (client, _) = make_pair(KeyType::Rsa).await;
+    assert_eq!(
+        client.extract_secrets().await.unwrap_err(),
+        Error::HandshakeNotComplete
+    );
+
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    do_handshake(&mut client, &mut server).await;
+    client.write_all_plaintext(b"hello").await.unwrap();
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let suite = client.negotiated_cipher_suite().unwrap();
+    let extracted = client.extract_secrets().await.unwrap();
+    assert_eq!(extracted.suite, suite);
+
+    let secrets = extracted.secrets;
+    assert_eq!(secrets.client_write_key.len(), 16);
+    assert_eq!(secrets.server_write_key.len(), 16);
+    assert_eq!(secrets.client_write_iv.len(), 4);
+    assert_eq!(secrets.server_write_iv.len(), 4);
+    assert_ne!(secrets.client_write_key, secrets.server_write_key);
+    // Finished then "hello" were sent, and Finished received.
+    assert_eq!(secrets.client_seq, 2);
+    assert_eq!(secrets.server_seq, 1);
+}
+
+#[cfg(feature = "notary")]
+#[tokio::test]
+async fn client_exports_notary_bundle() {
+    use tls_client::{
+        notary::{NotaryBundle, RecordDirection},
+        ContentType,
+    };
+
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    assert_eq!(
+        client.export_notary_bundle().unwrap_err(),
+        Error::HandshakeNotComplete
+    );
+
+    do_handshake(&mut client, &mut server).await;
+    client.write_all_plaintext(b"hello").await.unwrap();
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let bundle = client.export_notary_bundle().unwrap();
+    assert_eq!(bundle.version, client.protocol_version().unwrap());
+    assert_eq!(
+        bundle.cipher_suite,
+        client.negotiated_cipher_suite().unwrap().suite()
+    );
+    assert_eq!(bundle.certificates, client.peer_certificates().unwrap());
+    assert!(bundle
+        .handshake_transcript
+        .starts_with(client.client_hello_bytes().unwrap()));
+    assert_eq!(
+        Some(bundle.handshake_transcript.as_slice()),
+        client.handshake_transcript()
+    );
+
+    let last = bundle.records.last().unwrap();
+    assert_eq!(last.direction, RecordDirection::Sent);
+    assert_eq!(last.typ, ContentType::ApplicationData);
+    assert!(last.seq.is_some());
+    assert!(bundle
+        .records
+        .iter()
+        .any(|r| r.direction == RecordDirection::Received && r.seq.is_none()));
+
+    let bytes = bundle.to_bytes();
+    assert_eq!(NotaryBundle::from_bytes(&bytes).unwrap(), bundle);
+
+    let mut unknown_format = bytes.clone();
+    unknown_format[0] = 0xff;
+    assert!(NotaryBundle::from_bytes(&unknown_format).is_err());
+    assert!(NotaryBundle::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[cfg(feature = "dangerous_configuration")]
+#[tokio::test]
+async fn client_reports_server_finished_verify_data() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    assert_eq!(client.server_finished_verify_data(), None);
+
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    receive(&mut server, &mut client);
+    client.process_new_packets().await.unwrap();
+    assert_eq!(client.server_finished_verify_data(), None);
+
+    do_handshake(&mut client, &mut server).await;
+    let verify_data = client.server_finished_verify_data().unwrap();
+    assert_eq!(verify_data.len(), 12);
+}
+
+#[tokio::test]
+async fn client_processes_bounded_records_per_batch() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    do_handshake(&mut client, &mut server).await;
+
+    for _ in 0..5 {
+        server.writer().write_all(b"x").unwrap();
+    }
+    receive(&mut server, &mut client);
+
+    let io_state = client.process_new_packets_batched(2).await.unwrap();
+    assert_eq!(io_state.plaintext_bytes_to_read(), 2);
+    assert!(io_state.has_unprocessed_records());
+    assert!(client.has_unprocessed_records());
+
+    let io_state = client.process_new_packets_batched(2).await.unwrap();
+    assert_eq!(io_state.plaintext_bytes_to_read(), 4);
+    assert!(io_state.has_unprocessed_records());
+    assert!(client.has_unprocessed_records());
+
+    let io_state = client.process_new_packets_batched(2).await.unwrap();
+    assert_eq!(io_state.plaintext_bytes_to_read(), 5);
+    assert!(!io_state.has_unprocessed_records());
+    assert!(!client.has_unprocessed_records());
+
+    check_read(&mut client.reader(), b"xxxxx");
+}
+
+async fn strict_record_parsing_error(input: &[u8]) -> Error {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.strict_record_parsing = true;
+    let (mut client, _) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    client.read_tls(&mut &input[..]).unwrap();
+    client.process_new_packets().await.unwrap_err()
+}
+
+#[tokio::test]
+async fn client_strict_record_parsing_errors() {
+    let cases: &[(&[u8], &str)] = &[
+        (
+            b"\x16\x03\x03\xff\xff\x00",
+            "received record with illegal length",
+        ),
+        (
+            b"\x16\x03\x03\x00\x00",
+            "received record with illegal length",
+        ),
+        (
+            b"\x99\x03\x03\x00\x01\x00",
+            "received record with unknown content type",
+        ),
+        (
+            b"\x16\x04\x03\x00\x01\x00",
+            "received record with illegal protocol version",
+        ),
+        (
+            b"\x16\x03\x04\x00\x01\x00",
+            "received record with unexpected version TLSv1_3",
+        ),
+        (
+            b"\x16\x03\x03\x00\x02\x02\x00\x15\x03\x03\x00\x02\x01\x00",
+            "received Alert record while a handshake message was incomplete",
+        ),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(
+            strict_record_parsing_error(input).await,
+            Error::PeerMisbehavedError(expected.to_string()),
+            "for input {:?}",
+            input
+        );
+    }
+}
+
+#[tokio::test]
+async fn client_lenient_record_parsing_is_default() {
+    let (mut client, _) = make_pair(KeyType::Rsa).await;
+    client
+        .read_tls(&mut b"\x16\x03\x03\xff\xff\x00".as_ref())
+        .unwrap();
+    assert_eq!(
+        client.process_new_packets().await,
+        Err(Error::CorruptMessage)
+    );
+}
+
+#[tokio::test]
+#[allow(clippy::no_effect)]
+async fn client_is_send() {
+    let (client, _) = make_pair(KeyType::Rsa).await;
+    &client as &dyn Send;
+}
+
+#[tokio::test]
+async fn client_respects_buffer_limit_pre_handshake() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+
+    client.set_buffer_limit(Some(32));
 
     assert_eq!(
         client
@@ -926,28 +2322,30 @@ async fn client_respects_buffer_limit_pre_handshake() {
     check_read(&mut server.reader(), b"01234567890123456789012345678901");
 }
 
-// #[tokio::test]
-// async fn client_respects_buffer_limit_pre_handshake_with_vectored_write() {
-//     let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+#[tokio::test]
+async fn client_respects_buffer_limit_pre_handshake_with_vectored_write() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
 
-//     client.set_buffer_limit(Some(32));
+    client.set_buffer_limit(Some(32));
 
-//     assert_eq!(
-//         client
-//             .write_vectored(&[
-//                 IoSlice::new(b"01234567890123456789"),
-//                 IoSlice::new(b"01234567890123456789")
-//             ]).await
-//             .unwrap(),
-//         32
-//     );
+    assert_eq!(
+        client
+            .write_vectored_plaintext(&[
+                IoSlice::new(b"01234567890123456789"),
+                IoSlice::new(b"01234567890123456789"),
+                IoSlice::new(b"abcdef")
+            ])
+            .await
+            .unwrap(),
+        32
+    );
 
-//     do_handshake(&mut client, &mut server).await;
-//     send(&mut client, &mut server);
-//     server.process_new_packets().unwrap();
+    do_handshake(&mut client, &mut server).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
 
-//     check_read(&mut server.reader(), b"01234567890123456789012345678901");
-// }
+    check_read(&mut server.reader(), b"01234567890123456789012345678901");
+}
 
 #[ignore = "needs to be fixed"]
 #[tokio::test]
@@ -978,6 +2376,30 @@ async fn client_respects_buffer_limit_post_handshake() {
     check_read(&mut server.reader(), b"01234567890123456789012345");
 }
 
+#[tokio::test]
+async fn client_write_all_fails_at_buffer_limit() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+
+    do_handshake(&mut client, &mut server).await;
+    // Room for the first write's record, plus six bytes of the second.
+    let overhead = 5 + client.negotiated_cipher_suite().unwrap().record_overhead();
+    client.set_buffer_limit(Some(20 + overhead + 6));
+
+    assert_eq!(
+        client.write_all_plaintext(b"01234567890123456789").await,
+        Ok(20)
+    );
+    assert_eq!(
+        client.write_all_plaintext(b"01234567890123456789").await,
+        Err(Error::General("plaintext would exceed buffer limit".into()))
+    );
+
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    check_read(&mut server.reader(), b"01234567890123456789012345");
+}
+
 struct ServerSession<'a, C, S>
 where
     C: DerefMut + Deref<Target = rustls::ConnectionCommon<S>>,
@@ -1173,6 +2595,44 @@ async fn client_read_returns_wouldblock_when_no_data() {
                      Err(err) if err.kind() == io::ErrorKind::WouldBlock));
 }
 
+#[tokio::test]
+async fn client_stream_read_pends_until_plaintext_arrives() {
+    use futures::{task::ArcWake, AsyncRead};
+
+    struct CountingWaker(AtomicUsize);
+
+    impl ArcWake for CountingWaker {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    do_handshake(&mut client, &mut server).await;
+    server.writer().write_all(b"hello").unwrap();
+
+    let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = futures::task::waker(wakes.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut buf = [0u8; 5];
+
+    let mut pipe = ServerSession::new(&mut server);
+    let mut transport = PendingFirstRead {
+        io: BlockingIo(&mut pipe),
+        pending: true,
+    };
+    let mut stream = tls_client::Stream::new(&mut client, &mut transport);
+    assert!(std::pin::Pin::new(&mut stream)
+        .poll_read(&mut cx, &mut buf)
+        .is_pending());
+    assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+
+    match std::pin::Pin::new(&mut stream).poll_read(&mut cx, &mut buf) {
+        Poll::Ready(Ok(5)) => assert_eq!(&buf, b"hello"),
+        other => panic!("unexpected poll result: {:?}", other),
+    }
+}
+
 #[ignore = "needs to be fixed"]
 #[tokio::test]
 async fn client_returns_initial_io_state() {
@@ -1184,6 +2644,121 @@ async fn client_returns_initial_io_state() {
     assert!(io_state.tls_bytes_to_write() > 200);
 }
 
+#[tokio::test]
+async fn client_reports_buffered_plaintext_in_io_state() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    let io_state = client.process_new_packets().await.unwrap();
+    assert_eq!(io_state.plaintext_bytes_buffered(), 0);
+
+    assert_eq!(client.write_plaintext(b"hello").await.unwrap(), 5);
+    let io_state = client.process_new_packets().await.unwrap();
+    assert_eq!(io_state.plaintext_bytes_buffered(), 5);
+
+    do_handshake(&mut client, &mut server).await;
+    let io_state = client.process_new_packets().await.unwrap();
+    assert_eq!(io_state.plaintext_bytes_buffered(), 0);
+}
+
+#[derive(Default)]
+struct RecordLog(
+    Mutex<
+        Vec<(
+            tls_client::client::Direction,
+            tls_client::ContentType,
+            usize,
+        )>,
+    >,
+);
+
+impl tls_client::client::RecordObserver for RecordLog {
+    fn on_record(
+        &self,
+        direction: tls_client::client::Direction,
+        content_type: tls_client::ContentType,
+        len: usize,
+    ) {
+        self.0.lock().unwrap().push((direction, content_type, len));
+    }
+}
+
+#[tokio::test]
+async fn client_reports_records_to_observer() {
+    use tls_client::{client::Direction, ContentType};
+
+    let log = Arc::new(RecordLog::default());
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.record_observer = Some(log.clone());
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    do_handshake(&mut client, &mut server).await;
+
+    {
+        let records = log.0.lock().unwrap();
+        assert_eq!(records[0].0, Direction::Outbound);
+        assert_eq!(records[0].1, ContentType::Handshake);
+        for direction in [Direction::Outbound, Direction::Inbound] {
+            assert!(records
+                .iter()
+                .any(|r| r.0 == direction && r.1 == ContentType::ChangeCipherSpec));
+        }
+    }
+
+    server.writer().write_all(b"hello").unwrap();
+    receive(&mut server, &mut client);
+    let (direction, content_type, len) = *log.0.lock().unwrap().last().unwrap();
+    assert_eq!(direction, Direction::Inbound);
+    assert_eq!(content_type, ContentType::ApplicationData);
+    assert!(len > 5);
+}
+
+#[tokio::test]
+async fn client_split_halves_send_and_receive() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    do_handshake(&mut client, &mut server).await;
+    let (mut read_half, mut write_half) = client.split();
+
+    assert_eq!(write_half.write_plaintext(b"hello").await.unwrap(), 5);
+    write_half.flush_plaintext().await.unwrap();
+    let mut tls = Vec::new();
+    while write_half.wants_write().await {
+        write_half.write_tls(&mut tls).await.unwrap();
+    }
+    server.read_tls(&mut &tls[..]).unwrap();
+    server.process_new_packets().unwrap();
+    check_read(&mut server.reader(), b"hello");
+
+    server.writer().write_all(b"world").unwrap();
+    let mut tls = Vec::new();
+    server.write_tls(&mut tls).unwrap();
+    read_half.read_tls(&mut &tls[..]).await.unwrap();
+    read_half.process_new_packets().await.unwrap();
+    let mut buf = [0u8; 5];
+    assert_eq!(read_half.read_plaintext(&mut buf).await.unwrap(), 5);
+    assert_eq!(&buf, b"world");
+
+    let (other_client, _) = make_pair(KeyType::Rsa).await;
+    let (_, other_write) = other_client.split();
+    let read_half = read_half.reunite(other_write).unwrap_err().0;
+
+    let mut client = read_half.reunite(write_half).unwrap();
+    client.write_all_plaintext(b"again").await.unwrap();
+    assert!(client.wants_write());
+}
+
+#[tokio::test]
+async fn client_process_without_new_data_makes_no_progress() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    receive(&mut server, &mut client);
+
+    assert!(client.process_new_packets().await.unwrap().made_progress());
+    let first = client.process_new_packets().await.unwrap();
+    let second = client.process_new_packets().await.unwrap();
+    assert!(!first.made_progress());
+    assert_eq!(first, second);
+}
+
 #[tokio::test]
 async fn client_complete_io_for_handshake() {
     let (mut client, mut server) = make_pair(KeyType::Rsa).await;
@@ -1197,6 +2772,89 @@ async fn client_complete_io_for_handshake() {
     assert!(!client.is_handshaking());
 }
 
+/// A transport which returns `Poll::Pending` from its first read, and
+/// otherwise defers to `io`.
+struct PendingFirstRead<T> {
+    io: T,
+    pending: bool,
+}
+
+impl<T: futures::AsyncRead + Unpin> futures::AsyncRead for PendingFirstRead<T> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if mem::take(&mut self.pending) {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        std::pin::Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl<T: futures::AsyncWrite + Unpin> futures::AsyncWrite for PendingFirstRead<T> {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        std::pin::Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        std::pin::Pin::new(&mut self.io).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_close(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        std::pin::Pin::new(&mut self.io).poll_close(cx)
+    }
+}
+
+#[tokio::test]
+async fn client_complete_io_resumes_after_cancellation() {
+    use std::future::Future;
+
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    let mut pipe = ServerSession::new(&mut server);
+    let mut transport = PendingFirstRead {
+        io: BlockingIo(&mut pipe),
+        pending: true,
+    };
+
+    let waker = futures::task::noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    {
+        let mut first = Box::pin(client.complete_io(&mut transport));
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+    }
+
+    // The ClientHello went out before the read pended, and nothing was
+    // lost by dropping the future.
+    assert!(client.is_handshaking());
+    assert!(!client.wants_write());
+    assert!(client.wants_read());
+
+    let (rdlen, wrlen) = client.complete_io(&mut transport).await.unwrap();
+    assert!(rdlen > 0 && wrlen > 0);
+    assert!(!client.is_handshaking());
+    assert!(!server.is_handshaking());
+}
+
 #[tokio::test]
 async fn client_complete_io_for_handshake_eof() {
     let (mut client, _) = make_pair(KeyType::Rsa).await;
@@ -1207,6 +2865,68 @@ async fn client_complete_io_for_handshake_eof() {
     assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
 }
 
+/// A transport which accepts everything written to it, and answers
+/// the first read with `reply`.
+struct ReplyingTransport {
+    reply: Option<io::Result<Vec<u8>>>,
+}
+
+impl io::Read for ReplyingTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.reply.take() {
+            Some(Ok(bytes)) => {
+                buf[..bytes.len()].copy_from_slice(&bytes);
+                Ok(bytes.len())
+            }
+            Some(Err(err)) => Err(err),
+            None => Ok(0),
+        }
+    }
+}
+
+impl io::Write for ReplyingTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn client_complete_io_reports_transport_error() {
+    let (mut client, _) = make_pair(KeyType::Rsa).await;
+    let mut transport = BlockingIo(ReplyingTransport {
+        reply: Some(Err(io::Error::from(io::ErrorKind::ConnectionReset))),
+    });
+
+    let err = client.complete_io(&mut transport).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    assert_eq!(
+        client.take_io_error().map(|err| err.kind()),
+        Some(io::ErrorKind::ConnectionReset)
+    );
+    assert!(client.take_io_error().is_none());
+}
+
+#[tokio::test]
+async fn client_complete_io_reports_alert_as_protocol_error() {
+    let (mut client, _) = make_pair(KeyType::Rsa).await;
+    // A fatal handshake_failure alert.
+    let mut transport = BlockingIo(ReplyingTransport {
+        reply: Some(Ok(vec![0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x28])),
+    });
+
+    let err = client.complete_io(&mut transport).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(
+        *err.into_inner().unwrap().downcast::<Error>().unwrap(),
+        Error::AlertReceived(AlertDescription::HandshakeFailure)
+    );
+    assert!(client.take_io_error().is_none());
+}
+
 #[ignore = "needs to be fixed"]
 #[tokio::test]
 async fn client_complete_io_for_write() {
@@ -1262,63 +2982,237 @@ async fn client_complete_io_for_read() {
     }
 }
 
-// #[tokio::test]
-// async fn client_stream_write() {
-//     for kt in ALL_KEY_TYPES.iter() {
-//         let (mut client, mut server) = make_pair(*kt).await;
-
-//         {
-//             let mut pipe = ServerSession::new(&mut server);
-//             let mut stream = Stream::new(&mut client, &mut pipe);
-//             assert_eq!(stream.write(b"hello").unwrap(), 5);
-//         }
-//         check_read(&mut server.reader(), b"hello");
-//     }
-// }
+#[tokio::test]
+async fn client_stream_write() {
+    use futures::AsyncWriteExt;
 
-// #[tokio::test]
-// async fn client_streamowned_write() {
-//     for kt in ALL_KEY_TYPES.iter() {
-//         let (client, mut server) = make_pair(*kt).await;
-
-//         {
-//             let pipe = ServerSession::new(&mut server);
-//             let mut stream = StreamOwned::new(client, pipe);
-//             assert_eq!(stream.write(b"hello").unwrap(), 5);
-//         }
-//         check_read(&mut server.reader(), b"hello");
-//     }
-// }
+    for kt in ALL_KEY_TYPES.iter() {
+        let (mut client, mut server) = make_pair(*kt).await;
 
-// #[tokio::test]
-// async fn client_stream_read() {
-//     for kt in ALL_KEY_TYPES.iter() {
-//         let (mut client, mut server) = make_pair(*kt).await;
+        {
+            let mut pipe = BlockingIo(ServerSession::new(&mut server));
+            let mut stream = tls_client::Stream::new(&mut client, &mut pipe);
+            assert_eq!(stream.write(b"hello").await.unwrap(), 5);
+        }
+        check_read(&mut server.reader(), b"hello");
+    }
+}
 
-//         server.writer().write_all(b"world").unwrap();
+#[tokio::test]
+async fn client_streamowned_write() {
+    use futures::AsyncWriteExt;
 
-//         {
-//             let mut pipe = ServerSession::new(&mut server);
-//             let mut stream = Stream::new(&mut client, &mut pipe);
-//             check_read(&mut stream, b"world");
-//         }
-//     }
-// }
+    for kt in ALL_KEY_TYPES.iter() {
+        let (client, mut server) = make_pair(*kt).await;
 
-// #[tokio::test]
-// async fn client_streamowned_read() {
-//     for kt in ALL_KEY_TYPES.iter() {
-//         let (client, mut server) = make_pair(*kt).await;
+        {
+            let pipe = BlockingIo(ServerSession::new(&mut server));
+            let mut stream = tls_client::StreamOwned::new(client, pipe);
+            assert_eq!(stream.write(b"hello").await.unwrap(), 5);
+        }
+        check_read(&mut server.reader(), b"hello");
+    }
+}
 
-//         server.writer().write_all(b"world").unwrap();
+#[tokio::test]
+async fn client_stream_read() {
+    use futures::AsyncReadExt;
 
-//         {
-//             let pipe = ServerSession::new(&mut server);
-//             let mut stream = StreamOwned::new(client, pipe);
-//             check_read(&mut stream, b"world");
-//         }
-//     }
-// }
+    for kt in ALL_KEY_TYPES.iter() {
+        let (mut client, mut server) = make_pair(*kt).await;
+
+        server.writer().write_all(b"world").unwrap();
+
+        {
+            let mut pipe = BlockingIo(ServerSession::new(&mut server));
+            let mut stream = tls_client::Stream::new(&mut client, &mut pipe);
+            let mut buf = [0u8; 6];
+            assert_eq!(stream.read(&mut buf).await.unwrap(), 5);
+            assert_eq!(&buf[..5], b"world");
+        }
+    }
+}
+
+#[tokio::test]
+async fn client_streamowned_read() {
+    use futures::AsyncReadExt;
+
+    for kt in ALL_KEY_TYPES.iter() {
+        let (client, mut server) = make_pair(*kt).await;
+
+        server.writer().write_all(b"world").unwrap();
+
+        {
+            let pipe = BlockingIo(ServerSession::new(&mut server));
+            let mut stream = tls_client::StreamOwned::new(client, pipe);
+            let mut buf = [0u8; 6];
+            assert_eq!(stream.read(&mut buf).await.unwrap(), 5);
+            assert_eq!(&buf[..5], b"world");
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn client_tls_stream_connects_and_exchanges_data() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut server = ServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+    server.writer().write_all(b"world").unwrap();
+
+    {
+        let mut stream = tls_client::TlsStream::connect(
+            Arc::new(make_client_config(KeyType::Rsa)),
+            dns_name("localhost"),
+            BlockingIo(ServerSession::new(&mut server)),
+        )
+        .await
+        .unwrap();
+        assert!(!stream.get_ref().1.is_handshaking());
+
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"world");
+
+        stream.write_all(b"hello").await.unwrap();
+        stream.shutdown().await.unwrap();
+    }
+
+    check_read_and_close(&mut server.reader(), b"hello");
+}
+
+#[cfg(feature = "test-utils")]
+#[tokio::test]
+async fn client_connects_over_tcp() {
+    use tokio::io::AsyncReadExt;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server_thread = std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut server = ServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+        server.writer().write_all(b"world").unwrap();
+        while server.is_handshaking() || server.wants_write() {
+            server.complete_io(&mut socket).unwrap();
+        }
+    });
+
+    let mut stream = tls_client::testing::connect_tcp(
+        Arc::new(make_client_config(KeyType::Rsa)),
+        dns_name("localhost"),
+        addr,
+    )
+    .await
+    .unwrap();
+    assert!(!stream.get_ref().1.is_handshaking());
+
+    let mut buf = [0u8; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"world");
+
+    server_thread.join().unwrap();
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn client_complete_io_with_deadline_times_out_and_resumes() {
+    use std::time::{Duration, Instant};
+
+    /// Takes everything written, but never has anything to read.
+    struct Stalled(Vec<u8>);
+
+    impl futures::AsyncRead for Stalled {
+        fn poll_read(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            _: &mut [u8],
+        ) -> std::task::Poll<io::Result<usize>> {
+            std::task::Poll::Pending
+        }
+    }
+
+    impl futures::AsyncWrite for Stalled {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<io::Result<usize>> {
+            self.0.extend_from_slice(buf);
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+
+    let mut stalled = Stalled(Vec::new());
+    let err = client
+        .complete_io_with_deadline(&mut stalled, Instant::now() + Duration::from_millis(50))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(client.is_handshaking());
+
+    // the ClientHello went out before the deadline; carry on from there
+    server.read_tls(&mut &stalled.0[..]).unwrap();
+    server.process_new_packets().unwrap();
+    client
+        .complete_io(&mut BlockingIo(ServerSession::new(&mut server)))
+        .await
+        .unwrap();
+    assert!(!client.is_handshaking());
+    assert!(!server.is_handshaking());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn client_complete_io_with_deadline_cuts_backend_waits_short() {
+    use std::time::{Duration, Instant};
+
+    let delay = Duration::from_millis(500);
+    let mut client = ClientConnection::new(
+        Arc::new(make_client_config(KeyType::Rsa)),
+        Box::new(SuspendingBackend::with_delay(delay)),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    client.start().await.unwrap();
+    let mut server = ServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+
+    // the transport never waits, so only the backend can hold this up
+    let started = Instant::now();
+    let err = client
+        .complete_io_with_deadline(
+            &mut BlockingIo(ServerSession::new(&mut server)),
+            started + Duration::from_millis(20),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    assert!(started.elapsed() < delay);
+    assert!(client.is_handshaking());
+
+    // the suspended backend operation is finished by the next call
+    client
+        .complete_io(&mut BlockingIo(ServerSession::new(&mut server)))
+        .await
+        .unwrap();
+    assert!(!client.is_handshaking());
+    assert!(!server.is_handshaking());
+}
 
 #[tokio::test]
 async fn server_stream_write() {
@@ -1555,25 +3449,78 @@ async fn test_tls13_exporter() {
     }
 }
 
-async fn do_suite_test(
-    client_config: ClientConfig,
-    server_config: ServerConfig,
-    expect_suite: SupportedCipherSuite,
-    expect_version: ProtocolVersion,
-) {
-    println!(
-        "do_suite_test {:?} {:?}",
-        expect_version,
-        expect_suite.suite()
-    );
-    let (mut client, mut server) = make_pair_for_configs(client_config, server_config).await;
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_can_require_ems() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.require_ems = true;
 
-    assert_eq!(None, client.negotiated_cipher_suite());
-    assert_eq!(None, server.negotiated_cipher_suite());
-    assert_eq!(None, client.protocol_version());
-    assert_eq!(None, version_compat(server.protocol_version()));
-    assert!(client.is_handshaking());
-    assert!(server.is_handshaking());
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    do_handshake(&mut client, &mut server).await;
+
+    // Both sides derived the same extended master secret.
+    server.writer().write_all(b"hello").unwrap();
+    receive(&mut server, &mut client);
+    client.process_new_packets().await.unwrap();
+    check_read(&mut client.reader(), b"hello");
+}
+
+#[cfg(feature = "tls12")]
+fn strip_ems_ack(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        server_hello
+            .extensions
+            .retain(|ext| !matches!(ext, ServerExtension::ExtendedMasterSecretAck));
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_server_without_ems_when_required() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.require_ems = true;
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, strip_ems_ack, &mut client);
+    assert_eq!(
+        client.process_new_packets().await,
+        Err(Error::PeerIncompatibleError(
+            "EMS required but not negotiated".into()
+        ))
+    );
+}
+
+async fn do_suite_test(
+    client_config: ClientConfig,
+    server_config: ServerConfig,
+    expect_suite: SupportedCipherSuite,
+    expect_version: ProtocolVersion,
+) {
+    println!(
+        "do_suite_test {:?} {:?}",
+        expect_version,
+        expect_suite.suite()
+    );
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config).await;
+
+    assert_eq!(None, client.negotiated_cipher_suite());
+    assert_eq!(None, client.early_negotiated_cipher_suite());
+    assert_eq!(None, server.negotiated_cipher_suite());
+    assert_eq!(None, client.protocol_version());
+    assert_eq!(None, version_compat(server.protocol_version()));
+    assert!(client.is_handshaking());
+    assert!(server.is_handshaking());
 
     send(&mut client, &mut server);
     server.process_new_packets().unwrap();
@@ -1586,12 +3533,17 @@ async fn do_suite_test(
         version_compat(server.protocol_version())
     );
     assert_eq!(None, client.negotiated_cipher_suite());
+    assert_eq!(None, client.early_negotiated_cipher_suite());
     // assert_eq!(Some(expect_suite), server.negotiated_cipher_suite());
 
     receive(&mut server, &mut client);
     client.process_new_packets().await.unwrap();
 
+    // Both are known from ServerHello, before the server's Finished.
+    assert!(client.is_handshaking());
+    assert_eq!(Some(expect_version), client.protocol_version());
     assert_eq!(Some(expect_suite), client.negotiated_cipher_suite());
+    assert_eq!(Some(expect_suite), client.early_negotiated_cipher_suite());
     // assert_eq!(Some(expect_suite), server.negotiated_cipher_suite());
 
     send(&mut client, &mut server);
@@ -1607,6 +3559,7 @@ async fn do_suite_test(
         version_compat(server.protocol_version())
     );
     assert_eq!(Some(expect_suite), client.negotiated_cipher_suite());
+    assert_eq!(Some(expect_suite), client.early_negotiated_cipher_suite());
     // assert_eq!(Some(expect_suite), server.negotiated_cipher_suite());
 }
 
@@ -1924,6 +3877,38 @@ async fn servered_write_for_client_appdata() {
     );
 }
 
+#[tokio::test]
+async fn servered_write_for_client_appdata_coalesced() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.coalesce_writes = true;
+    let server_config = make_server_config(KeyType::Rsa);
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config).await;
+    do_handshake(&mut client, &mut server).await;
+
+    client
+        .write_all_plaintext(b"01234567890123456789")
+        .await
+        .unwrap();
+    client
+        .write_all_plaintext(b"01234567890123456789")
+        .await
+        .unwrap();
+    assert!(!client.wants_write());
+
+    client.flush_plaintext().await.unwrap();
+    {
+        let mut pipe = ServerSession::new(&mut server);
+        let wrlen = client.write_tls(&mut pipe).unwrap();
+        // header + explicit nonce + 40 bytes of data + tag
+        assert_eq!(69, wrlen);
+        assert_eq!(pipe.writevs, vec![vec![69]]);
+    }
+    check_read(
+        &mut server.reader(),
+        b"0123456789012345678901234567890123456789",
+    );
+}
+
 #[ignore = "needs to be fixed"]
 #[tokio::test]
 async fn servered_write_for_server_handshake_with_half_rtt_data() {
@@ -2188,6 +4173,43 @@ impl tls_client::client::StoresClientSessions for ClientStorage {
     }
 }
 
+struct AsyncClientStorage {
+    storage: Arc<tls_client::client::ClientSessionMemoryCache>,
+}
+
+#[async_trait::async_trait]
+impl tls_client::client::StoresClientSessionsAsync for AsyncClientStorage {
+    async fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        tokio::task::yield_now().await;
+        tls_client::client::StoresClientSessions::put(&*self.storage, key, value)
+    }
+
+    async fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        tokio::task::yield_now().await;
+        tls_client::client::StoresClientSessions::get(&*self.storage, key)
+    }
+}
+
+#[tokio::test]
+async fn client_accepts_async_session_storage() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.session_storage = Arc::new(AsyncClientStorage {
+        storage: tls_client::client::ClientSessionMemoryCache::new(16),
+    });
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    do_handshake(&mut client, &mut server).await;
+    assert!(!client.is_handshaking());
+
+    // Sync stores are still accepted.
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.session_storage = Arc::new(ClientStorage::new());
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    do_handshake(&mut client, &mut server).await;
+    assert!(!client.is_handshaking());
+}
+
 #[ignore = "needs to be fixed"]
 #[tokio::test]
 async fn tls13_stateful_resumption() {
@@ -2371,6 +4393,40 @@ async fn test_client_does_not_offer_sha1() {
     }
 }
 
+#[tokio::test]
+async fn client_offers_only_configured_signature_schemes() {
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_signature_schemes(&[
+            SignatureScheme::RSA_PKCS1_SHA1,
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+        ])
+        .unwrap();
+    let client_config = finish_client_config(KeyType::Rsa, builder);
+    assert_eq!(
+        client_config.signature_schemes,
+        Some(vec![
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+        ])
+    );
+
+    let (mut client, _) =
+        make_pair_for_configs(client_config.clone(), make_server_config(KeyType::Rsa)).await;
+    assert_eq!(
+        client_hello(&mut client).get_sigalgs_extension().unwrap(),
+        &vec![
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+        ]
+    );
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    do_handshake(&mut client, &mut server).await;
+}
+
 #[ignore = "needs to be fixed"]
 #[tokio::test]
 async fn test_client_config_keyshare() {
@@ -2543,6 +4599,35 @@ async fn test_client_mtu_reduction() {
     }
 }
 
+#[tokio::test]
+async fn test_client_mtu_reduction_allows_for_encryption() {
+    for kt in ALL_KEY_TYPES.iter() {
+        let mut client_config = make_client_config(*kt);
+        client_config.max_fragment_size = Some(64);
+        let (mut client, mut server) =
+            make_pair_for_configs(client_config, make_server_config(*kt)).await;
+        assert_eq!(client.max_plaintext_per_record(), 64 - 5);
+        do_handshake(&mut client, &mut server).await;
+
+        let overhead = client.negotiated_cipher_suite().unwrap().record_overhead();
+        assert_eq!(client.max_plaintext_per_record(), 64 - 5 - overhead);
+
+        client.write_all_plaintext(&[0u8; 1024]).await.unwrap();
+        let mut buf = Vec::new();
+        client.write_tls(&mut buf).unwrap();
+
+        let mut records = 0;
+        let mut rest = &buf[..];
+        while !rest.is_empty() {
+            let len = 5 + u16::from_be_bytes([rest[3], rest[4]]) as usize;
+            assert!(len <= 64, "record of {} bytes", len);
+            records += 1;
+            rest = &rest[len..];
+        }
+        assert!(records > 1);
+    }
+}
+
 #[ignore = "needs to be fixed"]
 #[tokio::test]
 async fn test_server_mtu_reduction() {
@@ -2555,7 +4640,7 @@ async fn test_server_mtu_reduction() {
     let big_data = [0u8; 2048];
     server.writer().write_all(&big_data).unwrap();
 
-    let encryption_overhead = 20; // FIXME: see issue #991
+    let mut writes = vec![];
 
     send(&mut client, &mut server);
     server.process_new_packets().unwrap();
@@ -2564,9 +4649,7 @@ async fn test_server_mtu_reduction() {
         server.write_tls(&mut pipe).unwrap();
 
         assert_eq!(pipe.writevs.len(), 1);
-        assert!(pipe.writevs[0]
-            .iter()
-            .all(|x| *x <= 64 + encryption_overhead));
+        writes.extend(pipe.writevs[0].clone());
     }
 
     client.process_new_packets().await.unwrap();
@@ -2576,13 +4659,15 @@ async fn test_server_mtu_reduction() {
         let mut pipe = ClientSession::new(&mut client);
         server.write_tls(&mut pipe).unwrap();
         assert_eq!(pipe.writevs.len(), 1);
-        assert!(pipe.writevs[0]
-            .iter()
-            .all(|x| *x <= 64 + encryption_overhead));
+        writes.extend(pipe.writevs[0].clone());
     }
 
     client.process_new_packets().await.unwrap();
     check_read(&mut client.reader(), &big_data);
+
+    // The server does not allow for encryption when fragmenting.
+    let encryption_overhead = client.negotiated_cipher_suite().unwrap().record_overhead();
+    assert!(writes.iter().all(|x| *x <= 64 + encryption_overhead));
 }
 
 async fn check_client_max_fragment_size(size: usize) -> Option<Error> {
@@ -2629,7 +4714,219 @@ fn connection_types_are_not_huge() {
     assert_lt(mem::size_of::<ClientConnection>(), 1600);
 }
 
-use tls_client::internal::msgs::message::{Message, MessagePayload};
+use tls_client::internal::msgs::{
+    base::Payload,
+    enums::{AlertDescription, ContentType, ExtensionType, HandshakeType},
+    handshake::{ClientExtension, HandshakeMessagePayload, HandshakePayload, ServerExtension},
+    message::{Message, MessagePayload, PlainMessage},
+};
+
+#[tokio::test]
+async fn test_client_hello_follows_profile() {
+    let profile = ClientProfile::firefox();
+    let client_config = finish_client_config(
+        KeyType::Rsa,
+        ClientConfig::builder()
+            .with_profile(profile.clone())
+            .unwrap(),
+    );
+    let (mut client, _) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    let client_hello = client_hello(&mut client);
+
+    let sent: Vec<ExtensionType> = client_hello
+        .extensions
+        .iter()
+        .map(|ext| ext.get_type())
+        .collect();
+    let expected: Vec<ExtensionType> = profile
+        .extension_order
+        .iter()
+        .copied()
+        .filter(|typ| sent.contains(typ))
+        .collect();
+    assert!(!expected.is_empty());
+    assert_eq!(&sent[..expected.len()], &expected[..]);
+    assert_eq!(
+        client_hello.compression_methods,
+        profile.compression_methods
+    );
+}
+
+fn make_client_config_with_grease() -> ClientConfig {
+    let profile = ClientProfile {
+        grease: true,
+        ..ClientProfile::firefox()
+    };
+    finish_client_config(
+        KeyType::Rsa,
+        ClientConfig::builder().with_profile(profile).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_client_hello_with_grease() {
+    let is_grease = |v: u16| v & 0x0f0f == 0x0a0a && v >> 12 == (v >> 4) & 0xf;
+
+    let (mut client, _) = make_pair_for_configs(
+        make_client_config_with_grease(),
+        make_server_config(KeyType::Rsa),
+    )
+    .await;
+    let client_hello = client_hello(&mut client);
+    assert!(is_grease(client_hello.cipher_suites[0].get_u16()));
+    assert!(is_grease(client_hello.extensions[0].get_type().get_u16()));
+    assert!(is_grease(
+        client_hello.get_namedgroups_extension().unwrap()[0].get_u16()
+    ));
+    assert!(is_grease(
+        client_hello.get_versions_extension().unwrap()[0].get_u16()
+    ));
+
+    let (mut client, mut server) = make_pair_for_configs(
+        make_client_config_with_grease(),
+        make_server_config(KeyType::Rsa),
+    )
+    .await;
+    do_handshake(&mut client, &mut server).await;
+}
+
+#[tokio::test]
+async fn test_client_hello_with_enable_grease() {
+    let is_grease = |v: u16| v & 0x0f0f == 0x0a0a && v >> 12 == (v >> 4) & 0xf;
+
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.enable_grease = true;
+    let client_config = Arc::new(client_config);
+
+    let (mut client, _) =
+        make_pair_for_arc_configs(&client_config, &Arc::new(make_server_config(KeyType::Rsa)))
+            .await;
+    let client_hello = client_hello(&mut client);
+    assert!(is_grease(client_hello.cipher_suites[0].get_u16()));
+    assert!(client_hello
+        .extensions
+        .iter()
+        .any(|ext| is_grease(ext.get_type().get_u16())));
+
+    let (mut client, mut server) =
+        make_pair_for_arc_configs(&client_config, &Arc::new(make_server_config(KeyType::Rsa)))
+            .await;
+    do_handshake(&mut client, &mut server).await;
+}
+
+#[cfg(feature = "tls12")]
+fn echo_grease_extension(msg: &mut Message) -> Altered {
+    use tls_client::internal::msgs::handshake::UnknownExtension;
+
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        server_hello
+            .extensions
+            .push(ServerExtension::Unknown(UnknownExtension {
+                typ: ExtensionType::Unknown(0x0a0a),
+                payload: Payload::empty(),
+            }));
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn test_client_ignores_echoed_grease_extension() {
+    // A zero seed selects the GREASE value 0x0a0a.
+    let mut client_config = make_client_config_with_grease();
+    client_config.rng_provider = Arc::new(FixedRandom(0));
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, echo_grease_extension, &mut client);
+    client.process_new_packets().await.unwrap();
+}
+
+fn add_record_size_limit(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        server_hello
+            .extensions
+            .push(ServerExtension::RecordSizeLimit(64));
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn test_client_honours_server_record_size_limit() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.record_size_limit = Some(64);
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, add_record_size_limit, &mut client);
+    client.process_new_packets().await.unwrap();
+    assert_eq!(client.negotiated_record_size_limit(), Some(64));
+
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+
+    // Check the plaintext records sent before ChangeCipherSpec.
+    let mut handshake_records = 0;
+    let mut rest = &buf[..];
+    while rest.len() >= 5 && rest[0] != 0x14 {
+        let len = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+        assert!(len <= 64, "record of {} bytes", len);
+        handshake_records += 1;
+        rest = &rest[5 + len..];
+    }
+    assert!(handshake_records > 1);
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn test_client_rejects_unsolicited_record_size_limit() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, add_record_size_limit, &mut client);
+    assert_eq!(
+        client.process_new_packets().await,
+        Err(Error::PeerMisbehavedError(
+            "server sent unsolicited extension".into()
+        ))
+    );
+}
+
+#[tokio::test]
+async fn test_client_rejects_too_small_record_size_limit() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.record_size_limit = Some(63);
+    let err = ClientConnection::new(
+        Arc::new(client_config),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("localhost"),
+    )
+    .err();
+    assert_eq!(
+        err,
+        Some(Error::General("record_size_limit is below 64".into()))
+    );
+}
 
 #[ignore = "needs to be fixed"]
 #[tokio::test]
@@ -2709,6 +5006,763 @@ async fn test_client_tls12_no_resume_after_server_downgrade() {
     assert_eq!(client_storage.puts(), 2);
 }
 
+#[cfg(feature = "tls12")]
+fn add_status_ack(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        server_hello
+            .extensions
+            .push(ServerExtension::CertificateStatusAck);
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+fn add_bogus_alpn(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        server_hello
+            .extensions
+            .push(ServerExtension::make_alpn(&[b"bogus"]));
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_unoffered_alpn_protocol() {
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa, &[&tls_client::version::TLS12]);
+    client_config.alpn_protocols = vec![b"h2".to_vec()];
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, add_bogus_alpn, &mut client);
+    assert_eq!(
+        client.process_new_packets().await.unwrap_err(),
+        Error::PeerMisbehavedError("ALPN protocol not offered".into())
+    );
+    assert_eq!(client.alpn_protocol(), None);
+}
+
+#[cfg(feature = "tls12")]
+fn choose_aes256_rsa_suite(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        server_hello.cipher_suite = CipherSuite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384;
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_unoffered_cipher_suite() {
+    use tls_client::internal::msgs::handshake::ClientHelloPayload;
+
+    // The suite is configured, but is not sent.
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa, &[&tls_client::version::TLS12]);
+    client_config.client_hello_transform = Some(Arc::new(|hello: &mut ClientHelloPayload| {
+        hello
+            .cipher_suites
+            .retain(|suite| *suite != CipherSuite::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384)
+    }));
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, choose_aes256_rsa_suite, &mut client);
+    assert_eq!(
+        client.process_new_packets().await.unwrap_err(),
+        Error::PeerMisbehavedError("server chose unoffered cipher suite".into())
+    );
+}
+
+#[cfg(feature = "tls12")]
+fn choose_raw_public_key(msg: &mut Message) -> Altered {
+    use tls_client::internal::msgs::enums::CertificateType;
+
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        server_hello
+            .extensions
+            .push(ServerExtension::ClientCertificateType(
+                CertificateType::RawPublicKey,
+            ));
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_unoffered_client_certificate_type() {
+    use tls_client::internal::msgs::enums::CertificateType;
+
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa, &[&tls_client::version::TLS12]);
+    client_config.client_cert_type_preference = vec![CertificateType::X509];
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, choose_raw_public_key, &mut client);
+    assert_eq!(
+        client.process_new_packets().await.unwrap_err(),
+        Error::PeerMisbehavedError("server chose unoffered client certificate type".into())
+    );
+}
+
+#[tokio::test]
+async fn client_exposes_stapled_ocsp_response() {
+    let kt = KeyType::Rsa;
+    let ocsp = b"not-really-an-ocsp-response".to_vec();
+    let server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert_with_ocsp_and_sct(
+            kt.get_chain_rustls(),
+            kt.get_key_rustls(),
+            ocsp.clone(),
+            Vec::new(),
+        )
+        .unwrap();
+
+    let (mut client, mut server) =
+        make_pair_for_configs(make_client_config(kt), server_config.clone()).await;
+    assert_eq!(client.peer_stapled_ocsp_response(), None);
+    do_handshake(&mut client, &mut server).await;
+    assert_eq!(client.peer_stapled_ocsp_response(), Some(&ocsp[..]));
+
+    let mut client_config = make_client_config(kt);
+    client_config.request_ocsp_stapling = false;
+    let (mut client, mut server) = make_pair_for_configs(client_config, server_config).await;
+    do_handshake(&mut client, &mut server).await;
+    assert_eq!(client.peer_stapled_ocsp_response(), None);
+}
+
+#[tokio::test]
+async fn client_exposes_signed_cert_timestamps() {
+    let kt = KeyType::Rsa;
+    // A list of two SCTs, "sct-one" and "sct-two".
+    let mut sct_list = vec![0x00, 0x12];
+    for sct in [&b"sct-one"[..], &b"sct-two"[..]] {
+        sct_list.extend_from_slice(&(sct.len() as u16).to_be_bytes());
+        sct_list.extend_from_slice(sct);
+    }
+    let server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert_with_ocsp_and_sct(
+            kt.get_chain_rustls(),
+            kt.get_key_rustls(),
+            Vec::new(),
+            sct_list,
+        )
+        .unwrap();
+
+    let (mut client, mut server) =
+        make_pair_for_configs(make_client_config(kt), server_config).await;
+    assert!(client.peer_signed_cert_timestamps().is_none());
+    do_handshake(&mut client, &mut server).await;
+
+    let scts: Vec<&[u8]> = client
+        .peer_signed_cert_timestamps()
+        .unwrap()
+        .iter()
+        .map(|sct| sct.0.as_slice())
+        .collect();
+    assert_eq!(scts, vec![&b"sct-one"[..], &b"sct-two"[..]]);
+}
+
+#[cfg(feature = "tls12")]
+async fn unsolicited_status_ack(reject_unsolicited_extensions: bool) -> Result<(), Error> {
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa, &[&tls_client::version::TLS12]);
+    client_config.request_ocsp_stapling = false;
+    client_config.reject_unsolicited_extensions = reject_unsolicited_extensions;
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, add_status_ack, &mut client);
+    client.process_new_packets().await.map(|_| ())
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_ignores_unsolicited_status_ack_by_default() {
+    assert_eq!(unsolicited_status_ack(false).await, Ok(()));
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_unsolicited_status_ack_when_strict() {
+    assert_eq!(
+        unsolicited_status_ack(true).await,
+        Err(Error::PeerMisbehavedError(
+            "server sent unsolicited extension".into()
+        ))
+    );
+}
+
+#[tokio::test]
+async fn client_exposes_client_hello_bytes() {
+    let mut client = ClientConnection::new(
+        Arc::new(make_client_config(KeyType::Rsa)),
+        Box::new(RustCryptoBackend::new()),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    assert_eq!(client.client_hello_bytes(), None);
+    client.start().await.unwrap();
+
+    let hello = client.client_hello_bytes().unwrap().to_vec();
+    assert_eq!(hello[0], 0x01);
+
+    let mut buf = Vec::new();
+    client.write_tls(&mut buf).unwrap();
+    assert_eq!(&buf[5..], &hello[..]);
+    assert_eq!(client.client_hello_bytes(), Some(&hello[..]));
+}
+
+#[tokio::test]
+async fn client_records_handshake_transcript() {
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    let hello = client.client_hello_bytes().unwrap().to_vec();
+    assert_eq!(client.handshake_transcript(), Some(&hello[..]));
+    assert_eq!(client.transcript_hash(), None);
+
+    do_handshake(&mut client, &mut server).await;
+    let transcript = client.handshake_transcript().unwrap().to_vec();
+    assert!(transcript.starts_with(&hello));
+    assert!(transcript.len() > hello.len());
+
+    let suite = client.negotiated_cipher_suite().unwrap();
+    let hash = client.transcript_hash().unwrap();
+    assert_eq!(hash.len(), suite.hash_algorithm().output_len());
+
+    // Application data does not change the transcript.
+    client.write_all_plaintext(b"hello").await.unwrap();
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+    server.writer().write_all(b"world").unwrap();
+    receive(&mut server, &mut client);
+    client.process_new_packets().await.unwrap();
+    assert_eq!(client.handshake_transcript(), Some(&transcript[..]));
+    assert_eq!(client.transcript_hash(), Some(hash));
+}
+
+#[tokio::test]
+async fn client_requests_max_fragment_length() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.max_fragment_length = Some(MaxFragmentLength::Pow2_9);
+    let (mut client, _) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+
+    let hello = client_hello(&mut client);
+    assert!(hello.extensions.iter().any(|ext| matches!(
+        ext,
+        ClientExtension::MaxFragmentLength(MaxFragmentLength::Pow2_9)
+    )));
+}
+
+#[cfg(feature = "tls12")]
+fn strip_tls13_offer(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ClientHello(hello),
+        ..
+    }) = &mut msg.payload
+    {
+        for ext in hello.extensions.iter_mut() {
+            if let ClientExtension::SupportedVersions(versions) = ext {
+                versions.retain(|version| *version != ProtocolVersion::TLSv1_3);
+            }
+        }
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+fn check_downgrade_sentinel(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        assert_eq!(&server_hello.random.0[24..], b"DOWNGRD\x01");
+    }
+    Altered::InPlace
+}
+
+/// Has the client offer TLS1.3, which an attacker strips before it
+/// reaches a server supporting TLS1.3.  The server then downgrades to
+/// TLS1.2, and says so in its random.
+#[cfg(feature = "tls12")]
+async fn downgraded_server_hello(enforce_downgrade_protection: bool) -> Result<(), Error> {
+    use tls_client::internal::msgs::handshake::ClientHelloPayload;
+
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.enforce_downgrade_protection = enforce_downgrade_protection;
+    client_config.client_hello_transform = Some(Arc::new(|hello: &mut ClientHelloPayload| {
+        for ext in hello.extensions.iter_mut() {
+            if let ClientExtension::SupportedVersions(versions) = ext {
+                versions.insert(0, ProtocolVersion::TLSv1_3);
+            }
+        }
+    }));
+
+    let (mut client, server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    let mut server = server.into();
+    send_altered(&mut client, strip_tls13_offer, &mut server);
+    server.process_new_packets().unwrap();
+
+    receive_altered(&mut server, check_downgrade_sentinel, &mut client);
+    client.process_new_packets().await.map(|_| ())
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_downgrade_sentinel_by_default() {
+    assert!(make_client_config(KeyType::Rsa).enforce_downgrade_protection);
+    assert_eq!(
+        downgraded_server_hello(true).await,
+        Err(Error::PeerMisbehavedError(
+            "downgrade to TLS1.2 when TLS1.3 is supported".into()
+        ))
+    );
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_ignores_downgrade_sentinel_when_not_enforced() {
+    assert_eq!(downgraded_server_hello(false).await, Ok(()));
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_accepts_downgrade_sentinel_when_tls13_not_offered() {
+    // The server supports TLS1.3, so marks its random; but the client
+    // only offered TLS1.2, so there was no downgrade.
+    let (mut client, mut server) = make_pair(KeyType::Rsa).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, check_downgrade_sentinel, &mut client);
+    client.process_new_packets().await.unwrap();
+}
+
+#[cfg(feature = "tls12")]
+fn downgrade_server_hello_record_version(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        typ: HandshakeType::ServerHello,
+        ..
+    }) = msg.payload
+    {
+        msg.version = ProtocolVersion::TLSv1_0;
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_reports_unusual_record_versions() {
+    let client_config =
+        make_client_config_with_versions(KeyType::Rsa, &[&tls_client::version::TLS12]);
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    assert!(client.observed_record_versions().is_empty());
+
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(
+        &mut server,
+        downgrade_server_hello_record_version,
+        &mut client,
+    );
+    client.process_new_packets().await.unwrap();
+
+    let mut server = match server {
+        rustls::Connection::Server(server) => server,
+        rustls::Connection::Client(_) => unreachable!(),
+    };
+    do_handshake(&mut client, &mut server).await;
+    assert!(!client.is_handshaking());
+
+    assert_eq!(
+        client.observed_record_versions(),
+        vec![ProtocolVersion::TLSv1_0, ProtocolVersion::TLSv1_2]
+    );
+}
+
+#[cfg(feature = "tls12")]
+fn zero_server_random(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        server_hello.random.0 = [0u8; 32];
+    }
+    Altered::InPlace
+}
+
+#[tokio::test]
+async fn client_hello_transform_can_drop_extension() {
+    use tls_client::internal::msgs::handshake::ClientHelloPayload;
+
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.client_hello_transform = Some(Arc::new(|hello: &mut ClientHelloPayload| {
+        hello
+            .extensions
+            .retain(|ext| ext.get_type() != ExtensionType::StatusRequest)
+    }));
+    let client_config = Arc::new(client_config);
+    let server_config = Arc::new(make_server_config(KeyType::Rsa));
+
+    let (mut client, _) = make_pair_for_arc_configs(&client_config, &server_config).await;
+    let hello = client_hello(&mut client);
+    assert!(hello
+        .extensions
+        .iter()
+        .all(|ext| ext.get_type() != ExtensionType::StatusRequest));
+
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config).await;
+    do_handshake(&mut client, &mut server).await;
+    assert!(!client.is_handshaking());
+}
+
+#[tokio::test]
+async fn client_hello_inspector_sees_wire_image() {
+    let inspected = Arc::new(Mutex::new(Vec::<Vec<u8>>::new()));
+    let mut client_config = make_client_config(KeyType::Rsa);
+    let seen = inspected.clone();
+    client_config.client_hello_inspector = Some(Arc::new(move |bytes: &[u8]| {
+        seen.lock().unwrap().push(bytes.to_vec())
+    }));
+
+    let (mut client, _) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    let mut wire = Vec::new();
+    client.write_tls(&mut wire).unwrap();
+
+    let inspected = inspected.lock().unwrap();
+    assert_eq!(inspected.len(), 1);
+    assert_eq!(inspected[0][0], HandshakeType::ClientHello.get_u8());
+    // The record header is followed by exactly the inspected bytes.
+    assert_eq!(&wire[5..], &inspected[0][..]);
+}
+
+#[tokio::test]
+async fn client_reports_session_ticket_as_post_handshake_event() {
+    use tls_client::{
+        client::PostHandshakeEvent,
+        internal::msgs::handshake::{ClientHelloPayload, ClientSessionTicket},
+    };
+
+    // This client does not ask for tickets itself, so ask for one here.
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.client_hello_transform = Some(Arc::new(|hello: &mut ClientHelloPayload| {
+        hello
+            .extensions
+            .push(ClientExtension::SessionTicket(ClientSessionTicket::Request))
+    }));
+    let mut server_config = make_server_config(KeyType::Rsa);
+    server_config.ticketer = rustls::Ticketer::new().unwrap();
+    let server_config = Arc::new(server_config);
+
+    let client_config_silent = Arc::new(client_config.clone());
+    let (mut client, mut server) =
+        make_pair_for_arc_configs(&client_config_silent, &server_config).await;
+    do_handshake(&mut client, &mut server).await;
+    assert_eq!(client.take_post_handshake_events(), vec![]);
+
+    client_config.report_post_handshake_events = true;
+    let client_config = Arc::new(client_config);
+    let (mut client, mut server) = make_pair_for_arc_configs(&client_config, &server_config).await;
+    do_handshake(&mut client, &mut server).await;
+    let events = client.take_post_handshake_events();
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        events[0],
+        PostHandshakeEvent::NewSessionTicket { .. }
+    ));
+    assert_eq!(client.take_post_handshake_events(), vec![]);
+}
+
+#[cfg(feature = "tls12")]
+async fn server_random_checked(alter: fn(&mut Message) -> Altered) -> Result<(), Error> {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.server_random_check = Some(Arc::new(|random: &[u8; 32]| random != &[0u8; 32]));
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, alter, &mut client);
+    client.process_new_packets().await.map(|_| ())
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_accepts_server_random_passing_check() {
+    assert_eq!(server_random_checked(|_| Altered::InPlace).await, Ok(()));
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_server_random_failing_check() {
+    assert_eq!(
+        server_random_checked(zero_server_random).await,
+        Err(Error::PeerMisbehavedError("server random rejected".into()))
+    );
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_reports_transcript_bytes_in_order() {
+    let chunks = Arc::new(Mutex::new(Vec::<Vec<u8>>::new()));
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa, &[&tls_client::version::TLS12]);
+    let observed = chunks.clone();
+    client_config.transcript_byte_observer = Some(Arc::new(move |bytes: &[u8]| {
+        observed.lock().unwrap().push(bytes.to_vec())
+    }));
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    do_handshake(&mut client, &mut server).await;
+
+    // Each chunk is one whole handshake message, in the order hashed.
+    let types = chunks
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|chunk| {
+            let len = u32::from_be_bytes([0, chunk[1], chunk[2], chunk[3]]) as usize;
+            assert_eq!(chunk.len(), 4 + len);
+            HandshakeType::from(chunk[0])
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![
+            HandshakeType::ClientHello,
+            HandshakeType::ServerHello,
+            HandshakeType::Certificate,
+            HandshakeType::ServerKeyExchange,
+            HandshakeType::ServerHelloDone,
+            HandshakeType::ClientKeyExchange,
+            HandshakeType::Finished,
+            HandshakeType::Finished,
+        ]
+    );
+}
+
+#[cfg(feature = "tls12")]
+async fn max_fragment_length_echo(
+    echo: fn(&mut Message) -> Altered,
+) -> (ClientConnection, Result<(), Error>) {
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa, &[&tls_client::version::TLS12]);
+    client_config.max_fragment_length = Some(MaxFragmentLength::Pow2_9);
+
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, echo, &mut client);
+    let result = client.process_new_packets().await.map(|_| ());
+    (client, result)
+}
+
+#[cfg(feature = "tls12")]
+fn echo_max_fragment_length(msg: &mut Message, mfl: MaxFragmentLength) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        payload: HandshakePayload::ServerHello(server_hello),
+        ..
+    }) = &mut msg.payload
+    {
+        server_hello
+            .extensions
+            .push(ServerExtension::MaxFragmentLength(mfl));
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_accepts_matching_max_fragment_length() {
+    let (client, result) =
+        max_fragment_length_echo(|msg| echo_max_fragment_length(msg, MaxFragmentLength::Pow2_9))
+            .await;
+    assert_eq!(result, Ok(()));
+    assert_eq!(client.negotiated_max_fragment_length(), Some(512));
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_mismatched_max_fragment_length() {
+    let (client, result) =
+        max_fragment_length_echo(|msg| echo_max_fragment_length(msg, MaxFragmentLength::Pow2_12))
+            .await;
+    assert_eq!(
+        result,
+        Err(Error::PeerMisbehavedError("MFL mismatch".into()))
+    );
+    assert_eq!(client.negotiated_max_fragment_length(), None);
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_accepts_fragmented_certificate() {
+    let mut server_config = make_server_config(KeyType::Rsa);
+    server_config.max_fragment_size = Some(128);
+    let (mut client, mut server) =
+        make_pair_for_configs(make_client_config(KeyType::Rsa), server_config).await;
+    do_handshake(&mut client, &mut server).await;
+}
+
+#[cfg(feature = "tls12")]
+fn fragment_server_hello(msg: &mut Message) -> Altered {
+    if let MessagePayload::Handshake(HandshakeMessagePayload {
+        typ: HandshakeType::ServerHello,
+        ..
+    }) = msg.payload
+    {
+        let mut payload = Vec::new();
+        msg.payload.encode(&mut payload);
+
+        let mut raw = Vec::new();
+        for chunk in payload.chunks(8) {
+            let fragment = PlainMessage {
+                typ: ContentType::Handshake,
+                version: msg.version,
+                payload: Payload::new(chunk.to_vec()),
+            };
+            raw.extend(fragment.into_unencrypted_opaque().encode());
+        }
+        return Altered::Raw(raw);
+    }
+    Altered::InPlace
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_over_fragmented_handshake_message() {
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.max_handshake_fragments = 4;
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    send(&mut client, &mut server);
+    server.process_new_packets().unwrap();
+
+    let mut server = server.into();
+    receive_altered(&mut server, fragment_server_hello, &mut client);
+    assert_eq!(
+        client.process_new_packets().await.unwrap_err(),
+        Error::PeerMisbehavedError("handshake message split across more than 4 records".into())
+    );
+}
+
+#[tokio::test]
+async fn client_rejects_oversized_handshake_message() {
+    // the server's certificate chain is much larger than this
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.max_handshake_message_size = Some(512);
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    assert_eq!(
+        do_handshake_until_error(&mut client, &mut server).await,
+        Err(ErrorFromPeer::Client(Error::HandshakeMessageTooLarge))
+    );
+
+    let mut client_config = make_client_config(KeyType::Rsa);
+    client_config.max_handshake_message_size = None;
+    let (mut client, mut server) =
+        make_pair_for_configs(client_config, make_server_config(KeyType::Rsa)).await;
+    do_handshake(&mut client, &mut server).await;
+}
+
+fn hello_request() -> PlainMessage {
+    Message {
+        version: ProtocolVersion::TLSv1_2,
+        payload: MessagePayload::Handshake(HandshakeMessagePayload {
+            typ: HandshakeType::HelloRequest,
+            payload: HandshakePayload::HelloRequest,
+        }),
+    }
+    .into()
+}
+
+async fn post_handshake_hello_request(strict: bool) -> Result<(), Error> {
+    let mut client_config =
+        make_client_config_with_versions(KeyType::Rsa, &[&tls_client::version::TLS12]);
+    client_config.strict_post_handshake = strict;
+
+    let (backend, injected) = InjectingBackend::new();
+    let mut client = ClientConnection::new(
+        Arc::new(client_config),
+        Box::new(backend),
+        dns_name("localhost"),
+    )
+    .unwrap();
+    client.start().await.unwrap();
+    let mut server = ServerConnection::new(Arc::new(make_server_config(KeyType::Rsa))).unwrap();
+    do_handshake(&mut client, &mut server).await;
+
+    injected.lock().unwrap().push_back(hello_request());
+    client.process_new_packets().await.map(|_| ())
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_tolerates_post_handshake_hello_request_by_default() {
+    assert_eq!(post_handshake_hello_request(false).await, Ok(()));
+}
+
+#[cfg(feature = "tls12")]
+#[tokio::test]
+async fn client_rejects_stray_post_handshake_message_when_strict() {
+    assert_eq!(
+        post_handshake_hello_request(true).await,
+        Err(Error::PeerMisbehavedError(
+            "received unexpected post-handshake HelloRequest message".into()
+        ))
+    );
+}
+
 #[derive(Default, Debug)]
 struct LogCounts {
     trace: usize,
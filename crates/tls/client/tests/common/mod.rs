@@ -1,20 +1,26 @@
 #![allow(dead_code)]
 
+use async_trait::async_trait;
 use futures::{AsyncRead, AsyncWrite};
 use rustls::{server::AllowAnyAuthenticatedClient, ServerConfig, ServerConnection};
 use std::{
+    collections::VecDeque,
     convert::{TryFrom, TryInto},
     io,
-    sync::Arc,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 use tls_client::{
     internal::msgs::{
         codec::Reader,
-        message::{Message, OpaqueMessage, PlainMessage},
+        enums::ProtocolVersion,
+        handshake::{ClientHelloPayload, HandshakePayload, Random},
+        message::{Message, MessagePayload, OpaqueMessage, PlainMessage},
     },
-    Certificate, ClientConfig, ClientConnection, Error, PrivateKey, RootCertStore,
-    RustCryptoBackend,
+    Backend, BackendError, Certificate, ClientConfig, ClientConnection, DecryptMode, EncryptMode,
+    Error, PrivateKey, RootCertStore, RustCryptoBackend, SupportedCipherSuite,
 };
+use tls_core::{cert::ServerCertDetails, ke::ServerKxDetails, key::PublicKey};
 
 macro_rules! embed_files {
     (
@@ -46,6 +52,7 @@ embed_files! {
     (ECDSA_CLIENT_CHAIN, "ecdsa", "client.chain");
     (ECDSA_CLIENT_FULLCHAIN, "ecdsa", "client.fullchain");
     (ECDSA_CLIENT_KEY, "ecdsa", "client.key");
+    (ECDSA_CLIENT_P12, "ecdsa", "client.p12");
     (ECDSA_CLIENT_REQ, "ecdsa", "client.req");
     (ECDSA_END_CERT, "ecdsa", "end.cert");
     (ECDSA_END_CHAIN, "ecdsa", "end.chain");
@@ -65,6 +72,7 @@ embed_files! {
     (EDDSA_CLIENT_CHAIN, "eddsa", "client.chain");
     (EDDSA_CLIENT_FULLCHAIN, "eddsa", "client.fullchain");
     (EDDSA_CLIENT_KEY, "eddsa", "client.key");
+    (EDDSA_CLIENT_P12, "eddsa", "client.p12");
     (EDDSA_CLIENT_REQ, "eddsa", "client.req");
     (EDDSA_END_CERT, "eddsa", "end.cert");
     (EDDSA_END_CHAIN, "eddsa", "end.chain");
@@ -82,6 +90,7 @@ embed_files! {
     (RSA_CLIENT_CHAIN, "rsa", "client.chain");
     (RSA_CLIENT_FULLCHAIN, "rsa", "client.fullchain");
     (RSA_CLIENT_KEY, "rsa", "client.key");
+    (RSA_CLIENT_P12, "rsa", "client.p12");
     (RSA_CLIENT_REQ, "rsa", "client.req");
     (RSA_CLIENT_RSA, "rsa", "client.rsa");
     (RSA_END_CERT, "rsa", "end.cert");
@@ -93,6 +102,7 @@ embed_files! {
     (RSA_INTER_CERT, "rsa", "inter.cert");
     (RSA_INTER_KEY, "rsa", "inter.key");
     (RSA_INTER_REQ, "rsa", "inter.req");
+    (RSA_INTER_XSIGNED_CERT, "rsa", "inter.xsigned.cert");
 }
 
 pub fn version_eq(left: tls_client::ProtocolVersion, right: rustls::ProtocolVersion) -> bool {
@@ -322,6 +332,11 @@ impl KeyType {
             .collect()
     }
 
+    /// The client chain and key as PKCS#12, with the password "test".
+    pub fn get_client_pkcs12(&self) -> &'static [u8] {
+        self.bytes_for("client.p12")
+    }
+
     fn get_client_key(&self) -> PrivateKey {
         PrivateKey(
             rustls_pemfile::pkcs8_private_keys(&mut io::BufReader::new(
@@ -343,6 +358,16 @@ impl KeyType {
     }
 }
 
+/// The RSA intermediate, cross-signed by the ECDSA CA with a validity
+/// period long past.
+pub fn get_expired_cross_signed_inter_rustls() -> rustls::Certificate {
+    rustls::Certificate(
+        rustls_pemfile::certs(&mut io::BufReader::new(RSA_INTER_XSIGNED_CERT))
+            .unwrap()
+            .remove(0),
+    )
+}
+
 pub fn finish_server_config(
     kt: KeyType,
     conf: rustls::ConfigBuilder<ServerConfig, rustls::WantsVerifier>,
@@ -405,16 +430,19 @@ pub fn make_server_config_with_mandatory_client_auth(kt: KeyType) -> ServerConfi
         .unwrap()
 }
 
-pub fn finish_client_config(
-    kt: KeyType,
-    config: tls_client::ConfigBuilder<tls_client::WantsVerifier>,
-) -> ClientConfig {
+pub fn make_client_root_store(kt: KeyType) -> RootCertStore {
     let mut root_store = RootCertStore::empty();
     let mut rootbuf = io::BufReader::new(kt.bytes_for("ca.cert"));
     root_store.add_parsable_certificates(&rustls_pemfile::certs(&mut rootbuf).unwrap());
+    root_store
+}
 
+pub fn finish_client_config(
+    kt: KeyType,
+    config: tls_client::ConfigBuilder<tls_client::WantsVerifier>,
+) -> ClientConfig {
     config
-        .with_root_certificates(root_store)
+        .with_root_certificates(make_client_root_store(kt))
         .with_no_client_auth()
 }
 
@@ -422,12 +450,8 @@ pub fn finish_client_config_with_creds(
     kt: KeyType,
     config: tls_client::ConfigBuilder<tls_client::WantsVerifier>,
 ) -> ClientConfig {
-    let mut root_store = RootCertStore::empty();
-    let mut rootbuf = io::BufReader::new(kt.bytes_for("ca.cert"));
-    root_store.add_parsable_certificates(&rustls_pemfile::certs(&mut rootbuf).unwrap());
-
     config
-        .with_root_certificates(root_store)
+        .with_root_certificates(make_client_root_store(kt))
         .with_single_cert(kt.get_client_chain(), kt.get_client_key())
         .unwrap()
 }
@@ -504,6 +528,23 @@ pub async fn make_pair_for_arc_configs(
     )
 }
 
+/// Takes the first flight from `client` and returns the ClientHello it contains.
+pub fn client_hello(client: &mut ClientConnection) -> ClientHelloPayload {
+    assert!(client.wants_write());
+    let mut buf = [0u8; 262144];
+    let sz = client.write_tls(&mut buf.as_mut()).unwrap();
+    let msg = OpaqueMessage::read(&mut Reader::init(&buf[..sz])).unwrap();
+    let msg = Message::try_from(msg.into_plain_message()).unwrap();
+
+    match msg.payload {
+        MessagePayload::Handshake(hs) => match hs.payload {
+            HandshakePayload::ClientHello(ch) => ch,
+            _ => panic!("expected a ClientHello"),
+        },
+        _ => panic!("expected a handshake message"),
+    }
+}
+
 pub async fn do_handshake(
     client: &mut ClientConnection,
     server: &mut ServerConnection,
@@ -648,3 +689,293 @@ where
         std::task::Poll::Ready(self.get_mut().0.read(buf))
     }
 }
+
+#[cfg(feature = "tokio")]
+impl<T> tokio::io::AsyncWrite for BlockingIo<T>
+where
+    T: std::io::Write + Unpin,
+{
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        std::task::Poll::Ready(self.get_mut().0.write(buf))
+    }
+
+    fn poll_write_vectored(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> std::task::Poll<io::Result<usize>> {
+        std::task::Poll::Ready(self.get_mut().0.write_vectored(bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::task::Poll::Ready(self.get_mut().0.flush())
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T> tokio::io::AsyncRead for BlockingIo<T>
+where
+    T: std::io::Read + Unpin,
+{
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        let read = self.get_mut().0.read(buf.initialize_unfilled());
+        std::task::Poll::Ready(read.map(|read| buf.advance(read)))
+    }
+}
+
+/// A [`tls_client::Backend`] which wraps [`RustCryptoBackend`] and lets a test
+/// hand the client arbitrary "decrypted" records, as if the server had sent
+/// them over an encrypted channel.
+pub struct InjectingBackend {
+    inner: RustCryptoBackend,
+    injected: Arc<Mutex<VecDeque<PlainMessage>>>,
+}
+
+impl InjectingBackend {
+    pub fn new() -> (Self, Arc<Mutex<VecDeque<PlainMessage>>>) {
+        let injected = Arc::new(Mutex::new(VecDeque::new()));
+        (
+            Self {
+                inner: RustCryptoBackend::new(),
+                injected: Arc::clone(&injected),
+            },
+            injected,
+        )
+    }
+}
+
+#[async_trait]
+impl Backend for InjectingBackend {
+    async fn set_protocol_version(&mut self, version: ProtocolVersion) -> Result<(), BackendError> {
+        self.inner.set_protocol_version(version).await
+    }
+    async fn set_cipher_suite(&mut self, suite: SupportedCipherSuite) -> Result<(), BackendError> {
+        self.inner.set_cipher_suite(suite).await
+    }
+    async fn get_suite(&mut self) -> Result<SupportedCipherSuite, BackendError> {
+        self.inner.get_suite().await
+    }
+    async fn set_encrypt(&mut self, mode: EncryptMode) -> Result<(), BackendError> {
+        self.inner.set_encrypt(mode).await
+    }
+    async fn set_decrypt(&mut self, mode: DecryptMode) -> Result<(), BackendError> {
+        self.inner.set_decrypt(mode).await
+    }
+    async fn get_client_random(&mut self) -> Result<Random, BackendError> {
+        self.inner.get_client_random().await
+    }
+    async fn get_client_key_share(&mut self) -> Result<PublicKey, BackendError> {
+        self.inner.get_client_key_share().await
+    }
+    async fn prepare_client_key_share(&mut self) -> Result<(), BackendError> {
+        self.inner.prepare_client_key_share().await
+    }
+    async fn set_server_random(&mut self, random: Random) -> Result<(), BackendError> {
+        self.inner.set_server_random(random).await
+    }
+    async fn set_server_key_share(&mut self, key: PublicKey) -> Result<(), BackendError> {
+        self.inner.set_server_key_share(key).await
+    }
+    async fn set_server_cert_details(
+        &mut self,
+        cert_details: ServerCertDetails,
+    ) -> Result<(), BackendError> {
+        self.inner.set_server_cert_details(cert_details).await
+    }
+    async fn set_server_kx_details(
+        &mut self,
+        kx_details: ServerKxDetails,
+    ) -> Result<(), BackendError> {
+        self.inner.set_server_kx_details(kx_details).await
+    }
+    async fn set_hs_hash_client_key_exchange(&mut self, hash: Vec<u8>) -> Result<(), BackendError> {
+        self.inner.set_hs_hash_client_key_exchange(hash).await
+    }
+    async fn set_hs_hash_server_hello(&mut self, hash: Vec<u8>) -> Result<(), BackendError> {
+        self.inner.set_hs_hash_server_hello(hash).await
+    }
+    async fn get_server_finished_vd(&mut self, hash: Vec<u8>) -> Result<Vec<u8>, BackendError> {
+        self.inner.get_server_finished_vd(hash).await
+    }
+    async fn get_client_finished_vd(&mut self, hash: Vec<u8>) -> Result<Vec<u8>, BackendError> {
+        self.inner.get_client_finished_vd(hash).await
+    }
+    async fn prepare_encryption(&mut self) -> Result<(), BackendError> {
+        self.inner.prepare_encryption().await
+    }
+    async fn push_incoming(&mut self, msg: OpaqueMessage) -> Result<(), BackendError> {
+        self.inner.push_incoming(msg).await
+    }
+    async fn next_incoming(&mut self) -> Result<Option<PlainMessage>, BackendError> {
+        if let Some(msg) = self.injected.lock().unwrap().pop_front() {
+            return Ok(Some(msg));
+        }
+        self.inner.next_incoming().await
+    }
+    async fn push_outgoing(&mut self, msg: PlainMessage) -> Result<(), BackendError> {
+        self.inner.push_outgoing(msg).await
+    }
+    async fn next_outgoing(&mut self) -> Result<Option<OpaqueMessage>, BackendError> {
+        self.inner.next_outgoing().await
+    }
+    async fn start_traffic(&mut self) -> Result<(), BackendError> {
+        self.inner.start_traffic().await
+    }
+    async fn flush(&mut self) -> Result<(), BackendError> {
+        self.inner.flush().await
+    }
+    async fn is_empty(&mut self) -> Result<bool, BackendError> {
+        Ok(self.injected.lock().unwrap().is_empty() && self.inner.is_empty().await?)
+    }
+}
+
+/// A [`tls_client::Backend`] which wraps [`RustCryptoBackend`] but suspends
+/// once in every `flush`, like a backend waiting on another party.
+pub struct SuspendingBackend {
+    inner: RustCryptoBackend,
+    delay: Option<Duration>,
+}
+
+impl SuspendingBackend {
+    pub fn new() -> Self {
+        Self {
+            inner: RustCryptoBackend::new(),
+            delay: None,
+        }
+    }
+
+    /// Suspends for `delay` in every `flush`, rather than just yielding.
+    pub fn with_delay(delay: Duration) -> Self {
+        Self {
+            inner: RustCryptoBackend::new(),
+            delay: Some(delay),
+        }
+    }
+}
+
+/// Returns `Poll::Pending` once, waking the task straight away.
+struct YieldOnce(bool);
+
+impl std::future::Future for YieldOnce {
+    type Output = ();
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<()> {
+        if self.0 {
+            return std::task::Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        std::task::Poll::Pending
+    }
+}
+
+#[async_trait]
+impl Backend for SuspendingBackend {
+    async fn set_protocol_version(&mut self, version: ProtocolVersion) -> Result<(), BackendError> {
+        self.inner.set_protocol_version(version).await
+    }
+    async fn set_cipher_suite(&mut self, suite: SupportedCipherSuite) -> Result<(), BackendError> {
+        self.inner.set_cipher_suite(suite).await
+    }
+    async fn get_suite(&mut self) -> Result<SupportedCipherSuite, BackendError> {
+        self.inner.get_suite().await
+    }
+    async fn set_encrypt(&mut self, mode: EncryptMode) -> Result<(), BackendError> {
+        self.inner.set_encrypt(mode).await
+    }
+    async fn set_decrypt(&mut self, mode: DecryptMode) -> Result<(), BackendError> {
+        self.inner.set_decrypt(mode).await
+    }
+    async fn get_client_random(&mut self) -> Result<Random, BackendError> {
+        self.inner.get_client_random().await
+    }
+    async fn get_client_key_share(&mut self) -> Result<PublicKey, BackendError> {
+        self.inner.get_client_key_share().await
+    }
+    async fn prepare_client_key_share(&mut self) -> Result<(), BackendError> {
+        self.inner.prepare_client_key_share().await
+    }
+    async fn set_server_random(&mut self, random: Random) -> Result<(), BackendError> {
+        self.inner.set_server_random(random).await
+    }
+    async fn set_server_key_share(&mut self, key: PublicKey) -> Result<(), BackendError> {
+        self.inner.set_server_key_share(key).await
+    }
+    async fn set_server_cert_details(
+        &mut self,
+        cert_details: ServerCertDetails,
+    ) -> Result<(), BackendError> {
+        self.inner.set_server_cert_details(cert_details).await
+    }
+    async fn set_server_kx_details(
+        &mut self,
+        kx_details: ServerKxDetails,
+    ) -> Result<(), BackendError> {
+        self.inner.set_server_kx_details(kx_details).await
+    }
+    async fn set_hs_hash_client_key_exchange(&mut self, hash: Vec<u8>) -> Result<(), BackendError> {
+        self.inner.set_hs_hash_client_key_exchange(hash).await
+    }
+    async fn set_hs_hash_server_hello(&mut self, hash: Vec<u8>) -> Result<(), BackendError> {
+        self.inner.set_hs_hash_server_hello(hash).await
+    }
+    async fn get_server_finished_vd(&mut self, hash: Vec<u8>) -> Result<Vec<u8>, BackendError> {
+        self.inner.get_server_finished_vd(hash).await
+    }
+    async fn get_client_finished_vd(&mut self, hash: Vec<u8>) -> Result<Vec<u8>, BackendError> {
+        self.inner.get_client_finished_vd(hash).await
+    }
+    async fn prepare_encryption(&mut self) -> Result<(), BackendError> {
+        self.inner.prepare_encryption().await
+    }
+    async fn push_incoming(&mut self, msg: OpaqueMessage) -> Result<(), BackendError> {
+        self.inner.push_incoming(msg).await
+    }
+    async fn next_incoming(&mut self) -> Result<Option<PlainMessage>, BackendError> {
+        self.inner.next_incoming().await
+    }
+    async fn push_outgoing(&mut self, msg: PlainMessage) -> Result<(), BackendError> {
+        self.inner.push_outgoing(msg).await
+    }
+    async fn next_outgoing(&mut self) -> Result<Option<OpaqueMessage>, BackendError> {
+        self.inner.next_outgoing().await
+    }
+    async fn start_traffic(&mut self) -> Result<(), BackendError> {
+        self.inner.start_traffic().await
+    }
+    async fn flush(&mut self) -> Result<(), BackendError> {
+        match self.delay {
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => YieldOnce(false).await,
+        }
+        self.inner.flush().await
+    }
+    async fn is_empty(&mut self) -> Result<bool, BackendError> {
+        self.inner.is_empty().await
+    }
+}
@@ -1,5 +1,9 @@
 use crate::{
-    msgs::handshake::{DistinguishedName, DistinguishedNames},
+    msgs::{
+        base::PayloadU16,
+        codec::{Codec, Reader},
+        handshake::{DistinguishedName, DistinguishedNames},
+    },
     x509,
 };
 
@@ -42,6 +46,36 @@ impl OwnedTrustAnchor {
     }
 }
 
+impl Codec for OwnedTrustAnchor {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        PayloadU16::new(self.subject.clone()).encode(bytes);
+        PayloadU16::new(self.spki.clone()).encode(bytes);
+        match &self.name_constraints {
+            Some(name_constraints) => {
+                1u8.encode(bytes);
+                PayloadU16::new(name_constraints.clone()).encode(bytes);
+            }
+            None => 0u8.encode(bytes),
+        }
+    }
+
+    fn read(r: &mut Reader) -> Option<Self> {
+        let subject = PayloadU16::read(r)?.0;
+        let spki = PayloadU16::read(r)?.0;
+        let name_constraints = match u8::read(r)? {
+            0 => None,
+            1 => Some(PayloadU16::read(r)?.0),
+            _ => return None,
+        };
+
+        Some(Self {
+            subject,
+            spki,
+            name_constraints,
+        })
+    }
+}
+
 /// Errors that can occur during operations with RootCertStore
 #[derive(Debug, thiserror::Error)]
 #[allow(missing_docs)]
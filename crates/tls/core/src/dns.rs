@@ -1,13 +1,13 @@
-use std::{error::Error as StdError, fmt};
+use std::{error::Error as StdError, fmt, net::IpAddr};
 
 use crate::verify;
 
 /// Encodes ways a client can know the expected name of the server.
 ///
-/// This currently covers knowing the DNS name of the server, but
-/// will be extended in the future to knowing the IP address of the
-/// server, as well as supporting privacy-preserving names for the
-/// server ("ECH").  For this reason this enum is `non_exhaustive`.
+/// This currently covers knowing the DNS name or the IP address of
+/// the server, but will be extended in the future to supporting
+/// privacy-preserving names for the server ("ECH").  For this reason
+/// this enum is `non_exhaustive`.
 ///
 /// # Making one
 ///
@@ -24,6 +24,9 @@ use crate::verify;
 /// let x = "example.com".try_into().expect("invalid DNS name");
 /// # let _: ServerName = x;
 /// ```
+///
+/// A string which is not a DNS name but is an IP address, such as
+/// `"192.0.2.1"`, makes a [`ServerName::IpAddress`].
 #[non_exhaustive]
 #[derive(Debug, PartialEq, Clone)]
 pub enum ServerName {
@@ -31,6 +34,11 @@ pub enum ServerName {
     /// is sent in the TLS Server Name Indication (SNI)
     /// extension.
     DnsName(verify::DnsName),
+
+    /// The server is identified by an IP address.  The certificate
+    /// must have a matching `iPAddress` subjectAltName, and no SNI
+    /// extension is sent.
+    IpAddress(IpAddr),
 }
 
 impl ServerName {
@@ -40,39 +48,65 @@ impl ServerName {
     pub fn for_sni(&self) -> Option<webpki::DnsNameRef> {
         match self {
             Self::DnsName(dns_name) => Some(dns_name.0.as_ref()),
+            Self::IpAddress(_) => None,
         }
     }
 
+    /// Return the name as the string sent in the SNI extension.
+    ///
+    /// This is [`None`] for names which have no DNS form, and so are
+    /// never sent in SNI.
+    pub fn sni_hostname(&self) -> Option<&str> {
+        self.for_sni().map(Into::into)
+    }
+
     /// Return a prefix-free, unique encoding for the name.
     pub fn encode(&self) -> Vec<u8> {
         enum UniqueTypeCode {
             DnsName = 0x01,
+            IpAddr = 0x02,
         }
 
-        let Self::DnsName(dns_name) = self;
-        let bytes = dns_name.0.as_ref();
+        let (code, bytes): (_, Vec<u8>) = match self {
+            Self::DnsName(dns_name) => {
+                let name = dns_name.0.as_ref();
+                let name: &[u8] = name.as_ref();
+                (UniqueTypeCode::DnsName, name.to_vec())
+            }
+            Self::IpAddress(IpAddr::V4(addr)) => (UniqueTypeCode::IpAddr, addr.octets().to_vec()),
+            Self::IpAddress(IpAddr::V6(addr)) => (UniqueTypeCode::IpAddr, addr.octets().to_vec()),
+        };
 
-        let mut r = Vec::with_capacity(2 + bytes.as_ref().len());
-        r.push(UniqueTypeCode::DnsName as u8);
-        r.push(bytes.as_ref().len() as u8);
-        r.extend_from_slice(bytes.as_ref());
+        let mut r = Vec::with_capacity(2 + bytes.len());
+        r.push(code as u8);
+        r.push(bytes.len() as u8);
+        r.extend_from_slice(&bytes);
 
         r
     }
 }
 
 /// Attempt to make a ServerName from a string by parsing
-/// it as a DNS name.
+/// it as a DNS name, or failing that as an IP address.
 impl TryFrom<&str> for ServerName {
     type Error = InvalidDnsNameError;
     fn try_from(s: &str) -> Result<Self, Self::Error> {
         match webpki::DnsNameRef::try_from_ascii_str(s) {
             Ok(dns) => Ok(Self::DnsName(verify::DnsName(dns.into()))),
-            Err(webpki::InvalidDnsNameError) => Err(InvalidDnsNameError),
+            Err(webpki::InvalidDnsNameError) => s
+                .parse::<IpAddr>()
+                .map(Self::IpAddress)
+                .map_err(|_| InvalidDnsNameError),
         }
     }
 }
 
+impl From<IpAddr> for ServerName {
+    fn from(addr: IpAddr) -> Self {
+        Self::IpAddress(addr)
+    }
+}
+
 /// The provided input could not be parsed because
 /// it is not a syntactically-valid DNS Name.
 #[derive(Debug)]
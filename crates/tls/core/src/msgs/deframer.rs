@@ -17,6 +17,9 @@ pub struct MessageDeframer {
     /// the deframer cannot recover.
     pub desynced: bool,
 
+    /// Why the deframer desynced, if it did.
+    pub error: Option<MessageError>,
+
     /// A fixed-size buffer containing the currently-accumulating
     /// TLS message.
     buf: Box<[u8; OpaqueMessage::MAX_WIRE_SIZE]>,
@@ -48,6 +51,7 @@ impl MessageDeframer {
         Self {
             frames: VecDeque::new(),
             desynced: false,
+            error: None,
             buf: Box::new([0u8; OpaqueMessage::MAX_WIRE_SIZE]),
             used: 0,
         }
@@ -65,17 +69,7 @@ impl MessageDeframer {
         let new_bytes = rd.read(&mut self.buf[self.used..])?;
 
         self.used += new_bytes;
-
-        loop {
-            match self.try_deframe_one() {
-                BufferContents::Invalid => {
-                    self.desynced = true;
-                    break;
-                }
-                BufferContents::Valid => continue,
-                BufferContents::Partial => break,
-            }
-        }
+        self.deframe_all();
 
         Ok(new_bytes)
     }
@@ -89,10 +83,39 @@ impl MessageDeframer {
         // we do a zero length read.  That looks like an EOF to
         // the next layer up, which is fine.
         debug_assert!(self.used <= OpaqueMessage::MAX_WIRE_SIZE);
+
+        // `used` is only advanced once the read has completed, so that
+        // dropping this future part-way through loses nothing.
         let new_bytes = rd.read(&mut self.buf[self.used..]).await?;
 
         self.used += new_bytes;
+        self.deframe_all();
+
+        Ok(new_bytes)
+    }
+
+    /// Add the bytes of `bytes` to our internal buffer, decoding full
+    /// messages as they complete.
+    ///
+    /// Unlike `read`, this takes as much of `bytes` as it can, not just
+    /// what fits in the buffer at once.  It returns how many bytes were
+    /// taken, which is fewer than `bytes.len()` only if the peer is found
+    /// not to be talking TLS.
+    pub fn read_slice(&mut self, bytes: &[u8]) -> usize {
+        let mut taken = 0;
+        while taken < bytes.len() && !self.desynced {
+            debug_assert!(self.used < OpaqueMessage::MAX_WIRE_SIZE);
+            let len = (bytes.len() - taken).min(OpaqueMessage::MAX_WIRE_SIZE - self.used);
+            self.buf[self.used..self.used + len].copy_from_slice(&bytes[taken..taken + len]);
+            self.used += len;
+            taken += len;
+            self.deframe_all();
+        }
+        taken
+    }
 
+    /// Decode every full message in our buffer.
+    fn deframe_all(&mut self) {
         loop {
             match self.try_deframe_one() {
                 BufferContents::Invalid => {
@@ -103,8 +126,6 @@ impl MessageDeframer {
                 BufferContents::Partial => break,
             }
         }
-
-        Ok(new_bytes)
     }
 
     /// Returns true if we have messages for the caller
@@ -131,7 +152,10 @@ impl MessageDeframer {
             Err(MessageError::TooShortForHeader) | Err(MessageError::TooShortForLength) => {
                 BufferContents::Partial
             }
-            Err(_) => BufferContents::Invalid,
+            Err(e) => {
+                self.error = Some(e);
+                BufferContents::Invalid
+            }
         }
     }
 
@@ -162,7 +186,10 @@ impl MessageDeframer {
 #[cfg(test)]
 mod tests {
     use super::MessageDeframer;
-    use crate::{msgs, msgs::message::Message};
+    use crate::{
+        msgs,
+        msgs::message::{Message, MessageError, OpaqueMessage},
+    };
     use std::{convert::TryFrom, io};
 
     const FIRST_MESSAGE: &[u8] = include_bytes!("../../testdata/deframer-test.1.bin");
@@ -386,6 +413,30 @@ mod tests {
         assert!(!d.desynced);
     }
 
+    #[test]
+    fn test_read_slice() {
+        let mut bytes = FIRST_MESSAGE.to_vec();
+        bytes.extend_from_slice(SECOND_MESSAGE);
+
+        let mut d = MessageDeframer::new();
+        assert_eq!(d.read_slice(&bytes[..3]), 3);
+        assert_eq!(d.frames.len(), 0);
+        assert_eq!(d.read_slice(&bytes[3..]), bytes.len() - 3);
+        pop_first(&mut d);
+        pop_second(&mut d);
+        assert!(!d.has_pending());
+    }
+
+    #[test]
+    fn test_read_slice_stops_when_desynced() {
+        let mut bytes = INVALID_CONTENTTYPE_MESSAGE.to_vec();
+        bytes.extend_from_slice(&[0u8; OpaqueMessage::MAX_WIRE_SIZE]);
+
+        let mut d = MessageDeframer::new();
+        assert!(d.read_slice(&bytes) < bytes.len());
+        assert!(d.desynced);
+    }
+
     #[test]
     fn test_invalid_contenttype_errors() {
         let mut d = MessageDeframer::new();
@@ -394,6 +445,7 @@ mod tests {
             input_bytes(&mut d, INVALID_CONTENTTYPE_MESSAGE),
         );
         assert!(d.desynced);
+        assert_eq!(d.error, Some(MessageError::IllegalContentType));
     }
 
     #[test]
@@ -404,6 +456,7 @@ mod tests {
             input_bytes(&mut d, INVALID_VERSION_MESSAGE),
         );
         assert!(d.desynced);
+        assert_eq!(d.error, Some(MessageError::IllegalProtocolVersion));
     }
 
     #[test]
@@ -414,6 +467,7 @@ mod tests {
             input_bytes(&mut d, INVALID_LENGTH_MESSAGE),
         );
         assert!(d.desynced);
+        assert_eq!(d.error, Some(MessageError::IllegalLength));
     }
 
     #[test]
@@ -438,5 +492,6 @@ mod tests {
             input_bytes(&mut d, INVALID_EMPTY_MESSAGE),
         );
         assert!(d.desynced);
+        assert_eq!(d.error, Some(MessageError::IllegalLength));
     }
 }
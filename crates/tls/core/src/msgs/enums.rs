@@ -217,6 +217,50 @@ enum_builder! {
     }
 }
 
+enum_builder! {
+    /// The `MaxFragmentLength` TLS protocol enum, from RFC 6066.
+    ///
+    /// Values in this enum are taken
+    /// from the various RFCs covering TLS, and are listed by IANA.
+    /// The `Unknown` item is used when processing unrecognised ordinals.
+    @U8
+    EnumName: MaxFragmentLength;
+    EnumVal{
+        Pow2_9 => 0x01,
+        Pow2_10 => 0x02,
+        Pow2_11 => 0x03,
+        Pow2_12 => 0x04
+    }
+}
+
+impl MaxFragmentLength {
+    /// The maximum fragment length in bytes, or `None` for an
+    /// unknown value.
+    pub fn max_len(&self) -> Option<u16> {
+        match *self {
+            Self::Pow2_9 => Some(512),
+            Self::Pow2_10 => Some(1024),
+            Self::Pow2_11 => Some(2048),
+            Self::Pow2_12 => Some(4096),
+            Self::Unknown(_) => None,
+        }
+    }
+}
+
+enum_builder! {
+    /// The `CertificateType` TLS protocol enum, from RFC 7250.
+    ///
+    /// Values in this enum are taken
+    /// from the various RFCs covering TLS, and are listed by IANA.
+    /// The `Unknown` item is used when processing unrecognised ordinals.
+    @U8
+    EnumName: CertificateType;
+    EnumVal{
+        X509 => 0x00,
+        RawPublicKey => 0x02
+    }
+}
+
 enum_builder! {
     /// The `ExtensionType` TLS protocol enum.
     ///
@@ -244,8 +288,11 @@ enum_builder! {
         Heartbeat => 0x000f,
         ALProtocolNegotiation => 0x0010,
         SCT => 0x0012,
+        ClientCertificateType => 0x0013,
+        ServerCertificateType => 0x0014,
         Padding => 0x0015,
         ExtendedMasterSecret => 0x0017,
+        RecordSizeLimit => 0x001c,
         SessionTicket => 0x0023,
         PreSharedKey => 0x0029,
         EarlyData => 0x002a,
@@ -261,6 +308,7 @@ enum_builder! {
         TransportParameters => 0x0039,
         NextProtocolNegotiation => 0x3374,
         ChannelId => 0x754f,
+        EncryptedClientHello => 0xfe0d,
         RenegotiationInfo => 0xff01,
         TransportParametersDraft => 0xffa5
     }
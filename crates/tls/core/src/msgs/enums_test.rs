@@ -60,6 +60,8 @@ fn test_enums() {
         AlertDescription::CloseNotify,
         AlertDescription::NoApplicationProtocol,
     );
+    test_enum8::<MaxFragmentLength>(MaxFragmentLength::Pow2_9, MaxFragmentLength::Pow2_12);
+    test_enum8::<CertificateType>(CertificateType::X509, CertificateType::RawPublicKey);
     test_enum8::<HeartbeatMessageType>(
         HeartbeatMessageType::Request,
         HeartbeatMessageType::Response,
@@ -14,6 +14,7 @@ pub const MAX_FRAGMENT_SIZE: usize = MAX_FRAGMENT_LEN + PACKET_OVERHEAD;
 
 pub struct MessageFragmenter {
     max_frag: usize,
+    max_fragment_size: Option<usize>,
 }
 
 impl MessageFragmenter {
@@ -23,7 +24,10 @@ impl MessageFragmenter {
     /// this includes overhead. A `max_fragment_size` of 10 will produce TLS fragments
     /// up to 10 bytes.
     pub fn new(max_fragment_size: Option<usize>) -> Result<Self, Error> {
-        let mut new = Self { max_frag: 0 };
+        let mut new = Self {
+            max_frag: 0,
+            max_fragment_size: None,
+        };
         new.set_max_fragment_size(max_fragment_size)?;
         Ok(new)
     }
@@ -33,13 +37,24 @@ impl MessageFragmenter {
     /// The new messages are appended to the `out` deque.
     /// Payloads are copied.
     pub fn fragment(&self, msg: PlainMessage, out: &mut VecDeque<PlainMessage>) {
+        self.fragment_to(msg, self.max_frag, out)
+    }
+
+    /// Like `fragment`, but the new messages' fragments are no more
+    /// than `max_frag`, which must not be zero.
+    pub fn fragment_to(
+        &self,
+        msg: PlainMessage,
+        max_frag: usize,
+        out: &mut VecDeque<PlainMessage>,
+    ) {
         // Non-fragment path
-        if msg.payload.0.len() <= self.max_frag {
+        if msg.payload.0.len() <= max_frag {
             out.push_back(msg);
             return;
         }
 
-        for chunk in msg.payload.0.chunks(self.max_frag) {
+        for chunk in msg.payload.0.chunks(max_frag) {
             out.push_back(PlainMessage {
                 typ: msg.typ,
                 version: msg.version,
@@ -73,8 +88,20 @@ impl MessageFragmenter {
             None => MAX_FRAGMENT_LEN,
             _ => return Err(Error::BadMaxFragmentSize),
         };
+        self.max_fragment_size = new;
         Ok(())
     }
+
+    /// The maximum fragment size set, including overhead, or `None`
+    /// if fragments are only limited to `MAX_FRAGMENT_LEN`.
+    pub fn max_fragment_size(&self) -> Option<usize> {
+        self.max_fragment_size
+    }
+
+    /// The longest fragment `fragment` produces, excluding overhead.
+    pub fn max_fragment_len(&self) -> usize {
+        self.max_frag
+    }
 }
 
 #[cfg(test)]
@@ -168,4 +195,20 @@ mod tests {
         );
         assert_eq!(q.len(), 0);
     }
+
+    #[test]
+    fn fragment_to_shorter_length() {
+        let m = PlainMessage {
+            typ: ContentType::ApplicationData,
+            version: ProtocolVersion::TLSv1_2,
+            payload: Payload::new((1..=10u8).collect::<Vec<u8>>()),
+        };
+
+        let frag = MessageFragmenter::new(Some(32)).unwrap();
+        assert_eq!(frag.max_fragment_len(), 32 - PACKET_OVERHEAD);
+        let mut q = VecDeque::new();
+        frag.fragment_to(m, 4, &mut q);
+        assert_eq!(q.len(), 3);
+        assert!(q.iter().all(|m| m.payload.0.len() <= 4));
+    }
 }
@@ -5,10 +5,10 @@ use crate::{
         codec,
         codec::{Codec, Reader},
         enums::{
-            CertificateStatusType, CipherSuite, ClientCertificateType, Compression, ECCurveType,
-            ECPointFormat, ExtensionType, HandshakeType, HashAlgorithm, KeyUpdateRequest,
-            NamedGroup, PSKKeyExchangeMode, ProtocolVersion, ServerNameType, SignatureAlgorithm,
-            SignatureScheme,
+            CertificateStatusType, CertificateType, CipherSuite, ClientCertificateType,
+            Compression, ECCurveType, ECPointFormat, ExtensionType, HandshakeType, HashAlgorithm,
+            KeyUpdateRequest, MaxFragmentLength, NamedGroup, PSKKeyExchangeMode, ProtocolVersion,
+            ServerNameType, SignatureAlgorithm, SignatureScheme,
         },
     },
     rand, Error,
@@ -149,6 +149,13 @@ impl Codec for SessionID {
     }
 }
 
+impl From<[u8; 32]> for SessionID {
+    #[inline]
+    fn from(data: [u8; 32]) -> Self {
+        Self { data, len: 32 }
+    }
+}
+
 impl SessionID {
     pub fn random() -> Result<Self, Error> {
         let mut data = [0u8; 32];
@@ -555,6 +562,7 @@ pub type SCTList = VecU16OfPayloadU16;
 declare_u8_vec!(PSKKeyExchangeModes, PSKKeyExchangeMode);
 declare_u16_vec!(KeyShareEntries, KeyShareEntry);
 declare_u8_vec!(ProtocolVersions, ProtocolVersion);
+declare_u8_vec!(CertificateTypes, CertificateType);
 
 #[derive(Clone, Debug)]
 pub enum ClientExtension {
@@ -575,6 +583,9 @@ pub enum ClientExtension {
     TransportParameters(Vec<u8>),
     TransportParametersDraft(Vec<u8>),
     EarlyData,
+    MaxFragmentLength(MaxFragmentLength),
+    ClientCertificateTypes(CertificateTypes),
+    RecordSizeLimit(u16),
     Unknown(UnknownExtension),
 }
 
@@ -598,6 +609,9 @@ impl ClientExtension {
             Self::TransportParameters(_) => ExtensionType::TransportParameters,
             Self::TransportParametersDraft(_) => ExtensionType::TransportParametersDraft,
             Self::EarlyData => ExtensionType::EarlyData,
+            Self::MaxFragmentLength(_) => ExtensionType::MaxFragmentLength,
+            Self::ClientCertificateTypes(_) => ExtensionType::ClientCertificateType,
+            Self::RecordSizeLimit(_) => ExtensionType::RecordSizeLimit,
             Self::Unknown(ref r) => r.typ,
         }
     }
@@ -628,6 +642,9 @@ impl Codec for ClientExtension {
             Self::TransportParameters(ref r) | Self::TransportParametersDraft(ref r) => {
                 sub.extend_from_slice(r)
             }
+            Self::MaxFragmentLength(ref r) => r.encode(&mut sub),
+            Self::ClientCertificateTypes(ref r) => r.encode(&mut sub),
+            Self::RecordSizeLimit(r) => r.encode(&mut sub),
             Self::Unknown(ref r) => r.encode(&mut sub),
         }
 
@@ -683,6 +700,13 @@ impl Codec for ClientExtension {
                 Self::TransportParametersDraft(sub.rest().to_vec())
             }
             ExtensionType::EarlyData if !sub.any_left() => Self::EarlyData,
+            ExtensionType::MaxFragmentLength => {
+                Self::MaxFragmentLength(MaxFragmentLength::read(&mut sub)?)
+            }
+            ExtensionType::ClientCertificateType => {
+                Self::ClientCertificateTypes(CertificateTypes::read(&mut sub)?)
+            }
+            ExtensionType::RecordSizeLimit => Self::RecordSizeLimit(u16::read(&mut sub)?),
             _ => Self::Unknown(UnknownExtension::read(typ, &mut sub)),
         };
 
@@ -743,6 +767,9 @@ pub enum ServerExtension {
     TransportParameters(Vec<u8>),
     TransportParametersDraft(Vec<u8>),
     EarlyData,
+    MaxFragmentLength(MaxFragmentLength),
+    ClientCertificateType(CertificateType),
+    RecordSizeLimit(u16),
     Unknown(UnknownExtension),
 }
 
@@ -763,6 +790,9 @@ impl ServerExtension {
             Self::TransportParameters(_) => ExtensionType::TransportParameters,
             Self::TransportParametersDraft(_) => ExtensionType::TransportParametersDraft,
             Self::EarlyData => ExtensionType::EarlyData,
+            Self::MaxFragmentLength(_) => ExtensionType::MaxFragmentLength,
+            Self::ClientCertificateType(_) => ExtensionType::ClientCertificateType,
+            Self::RecordSizeLimit(_) => ExtensionType::RecordSizeLimit,
             Self::Unknown(ref r) => r.typ,
         }
     }
@@ -789,6 +819,9 @@ impl Codec for ServerExtension {
             Self::TransportParameters(ref r) | Self::TransportParametersDraft(ref r) => {
                 sub.extend_from_slice(r)
             }
+            Self::MaxFragmentLength(ref r) => r.encode(&mut sub),
+            Self::ClientCertificateType(ref r) => r.encode(&mut sub),
+            Self::RecordSizeLimit(r) => r.encode(&mut sub),
             Self::Unknown(ref r) => r.encode(&mut sub),
         }
 
@@ -827,6 +860,13 @@ impl Codec for ServerExtension {
                 Self::TransportParametersDraft(sub.rest().to_vec())
             }
             ExtensionType::EarlyData => Self::EarlyData,
+            ExtensionType::MaxFragmentLength => {
+                Self::MaxFragmentLength(MaxFragmentLength::read(&mut sub)?)
+            }
+            ExtensionType::ClientCertificateType => {
+                Self::ClientCertificateType(CertificateType::read(&mut sub)?)
+            }
+            ExtensionType::RecordSizeLimit => Self::RecordSizeLimit(u16::read(&mut sub)?),
             _ => Self::Unknown(UnknownExtension::read(typ, &mut sub)),
         };
 
@@ -1749,6 +1789,30 @@ pub trait HasServerExtensions {
     fn early_data_extension_offered(&self) -> bool {
         self.find_extension(ExtensionType::EarlyData).is_some()
     }
+
+    fn get_max_fragment_length(&self) -> Option<MaxFragmentLength> {
+        let ext = self.find_extension(ExtensionType::MaxFragmentLength)?;
+        match *ext {
+            ServerExtension::MaxFragmentLength(mfl) => Some(mfl),
+            _ => None,
+        }
+    }
+
+    fn get_client_certificate_type(&self) -> Option<CertificateType> {
+        let ext = self.find_extension(ExtensionType::ClientCertificateType)?;
+        match *ext {
+            ServerExtension::ClientCertificateType(typ) => Some(typ),
+            _ => None,
+        }
+    }
+
+    fn get_record_size_limit(&self) -> Option<u16> {
+        let ext = self.find_extension(ExtensionType::RecordSizeLimit)?;
+        match *ext {
+            ServerExtension::RecordSizeLimit(limit) => Some(limit),
+            _ => None,
+        }
+    }
 }
 
 impl HasServerExtensions for EncryptedExtensions {
@@ -2103,6 +2167,11 @@ pub enum HandshakePayload {
     ServerHello(ServerHelloPayload),
     HelloRetryRequest(HelloRetryRequest),
     Certificate(CertificatePayload),
+    /// An RFC 7250 raw public key, as a DER `SubjectPublicKeyInfo`.
+    ///
+    /// This is only ever sent: a Certificate message is always read as
+    /// [`HandshakePayload::Certificate`].
+    RawPublicKeyCertificate(PayloadU24),
     CertificateTLS13(CertificatePayloadTLS13),
     ServerKeyExchange(ServerKeyExchangePayload),
     CertificateRequest(CertificateRequestPayload),
@@ -2131,6 +2200,7 @@ impl HandshakePayload {
             HandshakePayload::ServerHello(ref x) => x.encode(bytes),
             HandshakePayload::HelloRetryRequest(ref x) => x.encode(bytes),
             HandshakePayload::Certificate(ref x) => x.encode(bytes),
+            HandshakePayload::RawPublicKeyCertificate(ref x) => x.encode(bytes),
             HandshakePayload::CertificateTLS13(ref x) => x.encode(bytes),
             HandshakePayload::ServerKeyExchange(ref x) => x.encode(bytes),
             HandshakePayload::ClientKeyExchange(ref x) => x.encode(bytes),
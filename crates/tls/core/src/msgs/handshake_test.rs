@@ -108,6 +108,36 @@ fn refuses_certificate_req_ext_with_unparsed_bytes() {
     assert!(CertReqExtension::read(&mut rd).is_none());
 }
 
+#[test]
+fn certificate_req_tls13_skips_unknown_extensions() {
+    // empty context, then 25 bytes of extensions
+    let mut bytes = vec![0x00u8, 0x00, 0x19];
+    // unassigned extension
+    bytes.extend_from_slice(&[0x12, 0x34, 0x00, 0x02, 0xab, 0xcd]);
+    // signature_algorithms
+    bytes.extend_from_slice(&[0x00, 0x0d, 0x00, 0x04, 0x00, 0x02, 0x04, 0x03]);
+    // certificate_authorities
+    bytes.extend_from_slice(&[
+        0x00, 0x2f, 0x00, 0x07, 0x00, 0x05, 0x00, 0x03, 0x01, 0x02, 0x03,
+    ]);
+    let mut rd = Reader::init(&bytes);
+    let certreq = CertificateRequestPayloadTLS13::read(&mut rd).unwrap();
+    assert!(!rd.any_left());
+
+    assert_eq!(
+        certreq.get_sigalgs_extension(),
+        Some(&vec![SignatureScheme::ECDSA_NISTP256_SHA256])
+    );
+    assert_eq!(
+        certreq.get_authorities_extension(),
+        Some(&vec![PayloadU16(vec![1, 2, 3])])
+    );
+    assert_eq!(
+        certreq.extensions[0].get_type(),
+        ExtensionType::Unknown(0x1234)
+    );
+}
+
 #[test]
 fn refuses_helloreq_ext_with_unparsed_bytes() {
     let bytes = [0x00u8, 0x2b, 0x00, 0x03, 0x00, 0x00, 0x01];
@@ -385,6 +415,12 @@ fn get_sample_clienthellopayload() -> ClientHelloPayload {
             ClientExtension::CertificateStatusRequest(CertificateStatusRequest::build_ocsp()),
             ClientExtension::SignedCertificateTimestampRequest,
             ClientExtension::TransportParameters(vec![1, 2, 3]),
+            ClientExtension::MaxFragmentLength(MaxFragmentLength::Pow2_10),
+            ClientExtension::RecordSizeLimit(0x4001),
+            ClientExtension::ClientCertificateTypes(vec![
+                CertificateType::RawPublicKey,
+                CertificateType::X509,
+            ]),
             ClientExtension::Unknown(UnknownExtension {
                 typ: ExtensionType::Unknown(12345),
                 payload: Payload(vec![1, 2, 3]),
@@ -753,6 +789,9 @@ fn get_sample_serverhellopayload() -> ServerHelloPayload {
             ServerExtension::SignedCertificateTimestamp(vec![PayloadU16(vec![0])]),
             ServerExtension::SupportedVersions(ProtocolVersion::TLSv1_2),
             ServerExtension::TransportParameters(vec![1, 2, 3]),
+            ServerExtension::MaxFragmentLength(MaxFragmentLength::Pow2_10),
+            ServerExtension::ClientCertificateType(CertificateType::RawPublicKey),
+            ServerExtension::RecordSizeLimit(0x4001),
             ServerExtension::Unknown(UnknownExtension {
                 typ: ExtensionType::Unknown(12345),
                 payload: Payload(vec![1, 2, 3]),
@@ -869,6 +908,16 @@ fn get_sample_newsessionticketpayloadtls13() -> NewSessionTicketPayloadTLS13 {
     }
 }
 
+#[test]
+fn newsessionticket_tls13_distinguishes_absent_and_zero_early_data() {
+    let mut nst = get_sample_newsessionticketpayloadtls13();
+    assert_eq!(nst.get_max_early_data_size(), None);
+
+    nst.exts.push(NewSessionTicketExtension::EarlyData(0));
+    let nst = NewSessionTicketPayloadTLS13::read_bytes(&nst.get_encoding()).unwrap();
+    assert_eq!(nst.get_max_early_data_size(), Some(0));
+}
+
 fn get_sample_encryptedextensions() -> EncryptedExtensions {
     get_sample_serverhellopayload().extensions
 }
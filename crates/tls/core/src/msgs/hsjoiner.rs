@@ -9,10 +9,13 @@ use std::collections::VecDeque;
 const HEADER_SIZE: usize = 1 + 3;
 
 /// TLS allows for handshake messages of up to 16MB.  We
-/// restrict that to 64KB to limit potential for denial-of-
-/// service.
+/// restrict that to 64KB by default to limit potential for
+/// denial-of-service.
 const MAX_HANDSHAKE_SIZE: u32 = 0xffff;
 
+/// The largest handshake message TLS can encode.
+const MAX_ENCODABLE_SIZE: u32 = 0xff_ffff;
+
 /// This works to reconstruct TLS handshake messages
 /// from individual TLS messages.  It's guaranteed that
 /// TLS messages output from this layer contain precisely
@@ -23,6 +26,9 @@ pub struct HandshakeJoiner {
 
     /// The message payload we're currently accumulating.
     buf: Vec<u8>,
+
+    /// The largest handshake message body we accept.
+    max_message_size: u32,
 }
 
 impl Default for HandshakeJoiner {
@@ -48,9 +54,32 @@ impl HandshakeJoiner {
         Self {
             frames: VecDeque::new(),
             buf: Vec::new(),
+            max_message_size: MAX_HANDSHAKE_SIZE,
         }
     }
 
+    /// Make a new HandshakeJoiner which accepts handshake messages
+    /// with bodies of up to `max` bytes, or any size TLS can encode
+    /// if `max` is `None`.
+    pub fn with_max_message_size(max: Option<usize>) -> Self {
+        let max_message_size = match max {
+            Some(max) => max.min(MAX_ENCODABLE_SIZE as usize) as u32,
+            None => MAX_ENCODABLE_SIZE,
+        };
+
+        Self {
+            max_message_size,
+            ..Self::new()
+        }
+    }
+
+    /// Does our buffered data introduce a message larger than we
+    /// accept?  This is why [`HandshakeJoiner::take_message`] failed,
+    /// if it did and this is true.
+    pub fn is_message_too_large(&self) -> bool {
+        matches!(self.buf_contains_message(), BufferState::MessageTooLarge)
+    }
+
     /// Do we want to process this message?
     pub fn want_message(&self, msg: &PlainMessage) -> bool {
         msg.typ == ContentType::Handshake
@@ -106,7 +135,7 @@ impl HandshakeJoiner {
 
         let (header, rest) = self.buf.split_at(HEADER_SIZE);
         match codec::u24::decode(&header[1..]) {
-            Some(len) if len.0 > MAX_HANDSHAKE_SIZE => BufferState::MessageTooLarge,
+            Some(len) if len.0 > self.max_message_size => BufferState::MessageTooLarge,
             Some(len) if rest.get(..len.into()).is_some() => BufferState::OneMessage,
             _ => BufferState::NeedsMoreData,
         }
@@ -290,5 +319,35 @@ mod tests {
         assert!(hj.want_message(&msg));
         assert_eq!(hj.take_message(msg), None);
         assert!(!hj.is_empty());
+        assert!(hj.is_message_too_large());
+    }
+
+    #[test]
+    fn max_message_size() {
+        // Certificate of 256 bytes, providing 2.
+        let msg = PlainMessage {
+            typ: ContentType::Handshake,
+            version: ProtocolVersion::TLSv1_2,
+            payload: Payload::new(b"\x0b\x00\x01\x00\x00\x01".to_vec()),
+        };
+
+        let mut hj = HandshakeJoiner::with_max_message_size(Some(255));
+        assert_eq!(hj.take_message(msg.clone()), None);
+        assert!(hj.is_message_too_large());
+
+        let mut hj = HandshakeJoiner::with_max_message_size(Some(256));
+        assert_eq!(hj.take_message(msg.clone()), Some(0));
+        assert!(!hj.is_message_too_large());
+
+        // Giant certificate of 65540 bytes, providing 2.
+        let msg = PlainMessage {
+            typ: ContentType::Handshake,
+            version: ProtocolVersion::TLSv1_2,
+            payload: Payload::new(b"\x0b\x01\x00\x04\x00\x01".to_vec()),
+        };
+
+        let mut hj = HandshakeJoiner::with_max_message_size(None);
+        assert_eq!(hj.take_message(msg), Some(0));
+        assert!(!hj.is_message_too_large());
     }
 }
@@ -260,7 +260,7 @@ pub struct BorrowedPlainMessage<'a> {
     pub payload: &'a [u8],
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MessageError {
     TooShortForHeader,
     TooShortForLength,
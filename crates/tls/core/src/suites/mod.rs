@@ -41,6 +41,35 @@ pub enum AEADAlgorithm {
     CHACHA20_POLY1305,
 }
 
+impl AEADAlgorithm {
+    /// How many records may be encrypted with one key before the
+    /// confidentiality of the records is at risk.
+    ///
+    /// For AES-GCM this is the 2^24.5 full-size records of RFC 8446
+    /// section 5.5, rounded down.  ChaCha20-Poly1305 has no practical
+    /// limit.
+    pub fn confidentiality_limit(&self) -> u64 {
+        match self {
+            AEADAlgorithm::AES_128_GCM | AEADAlgorithm::AES_256_GCM => 1 << 24,
+            AEADAlgorithm::CHACHA20_POLY1305 => u64::MAX,
+        }
+    }
+
+    /// How many records may be decrypted with one key before forgery
+    /// becomes feasible, per RFC 9001 section 6.6.
+    pub fn integrity_limit(&self) -> u64 {
+        match self {
+            AEADAlgorithm::AES_128_GCM | AEADAlgorithm::AES_256_GCM => 1 << 52,
+            AEADAlgorithm::CHACHA20_POLY1305 => 1 << 36,
+        }
+    }
+
+    /// The length of the authentication tag added to each record.
+    pub fn tag_len(&self) -> usize {
+        16
+    }
+}
+
 /// Hash algorithm used by a cipher suite.
 #[derive(Debug, Eq, PartialEq)]
 pub enum HashAlgorithm {
@@ -118,6 +147,24 @@ impl SupportedCipherSuite {
         self.common().suite
     }
 
+    /// The AEAD algorithm this suite encrypts records with.
+    pub fn aead_algorithm(&self) -> &'static AEADAlgorithm {
+        self.common().aead_algorithm
+    }
+
+    /// How many bytes encryption adds to the payload of each record.
+    ///
+    /// For TLS1.2 this is the explicit nonce and the tag; for TLS1.3
+    /// the inner content type and the tag.
+    pub fn record_overhead(&self) -> usize {
+        let tag_len = self.aead_algorithm().tag_len();
+        match self {
+            #[cfg(feature = "tls12")]
+            SupportedCipherSuite::Tls12(inner) => inner.explicit_nonce_len + tag_len,
+            SupportedCipherSuite::Tls13(_) => 1 + tag_len,
+        }
+    }
+
     pub(crate) fn common(&self) -> &CipherSuiteCommon {
         match self {
             #[cfg(feature = "tls12")]
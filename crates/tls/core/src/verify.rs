@@ -7,9 +7,10 @@ use crate::{
         enums::SignatureScheme,
         handshake::{DigitallySignedStruct, DistinguishedNames},
     },
+    x509,
 };
 use ring::digest::Digest;
-use std::convert::TryFrom;
+use std::{convert::TryFrom, net::IpAddr};
 use web_time::SystemTime;
 
 type SignatureAlgorithms = &'static [&'static webpki::SignatureAlgorithm];
@@ -165,6 +166,14 @@ pub trait ServerCertVerifier: Send + Sync {
     fn request_scts(&self) -> bool {
         true
     }
+
+    /// Returns the trust anchors this verifier checks certificates
+    /// against, if it has a fixed set of them.
+    ///
+    /// The default implementation of this function returns `None`.
+    fn trust_anchors(&self) -> Option<&RootCertStore> {
+        None
+    }
 }
 
 /// A type which encapsuates a string that is a syntactically valid DNS name.
@@ -297,8 +306,6 @@ impl ServerCertVerifier for WebPkiVerifier {
         let seconds_since_unix_epoch = duration_since_epoch.as_secs();
         let webpki_now = webpki::Time::from_seconds_since_unix_epoch(seconds_since_unix_epoch);
 
-        let ServerName::DnsName(dns_name) = server_name;
-
         let cert = cert
             .verify_is_valid_tls_server_cert(
                 SUPPORTED_SIG_ALGS,
@@ -313,9 +320,17 @@ impl ServerCertVerifier for WebPkiVerifier {
             policy.verify(end_entity, now, scts)?;
         }
 
-        cert.verify_is_valid_for_dns_name(dns_name.0.as_ref())
-            .map_err(pki_error)
-            .map(|_| ServerCertVerified::assertion())
+        match server_name {
+            ServerName::DnsName(dns_name) => cert
+                .verify_is_valid_for_dns_name(dns_name.0.as_ref())
+                .map_err(pki_error)
+                .map(|_| ServerCertVerified::assertion()),
+            ServerName::IpAddress(addr) => verify_is_valid_for_ip_address(end_entity, addr),
+        }
+    }
+
+    fn trust_anchors(&self) -> Option<&RootCertStore> {
+        Some(&self.roots)
     }
 }
 
@@ -449,6 +464,26 @@ fn prepare<'a, 'b>(
     Ok((cert, intermediates, trustroots))
 }
 
+/// Checks `end_entity` has an `iPAddress` subjectAltName of `addr`.
+///
+/// webpki only matches DNS names, so these are found by hand.
+fn verify_is_valid_for_ip_address(
+    end_entity: &Certificate,
+    addr: &IpAddr,
+) -> Result<ServerCertVerified, Error> {
+    let octets = match addr {
+        IpAddr::V4(addr) => addr.octets().to_vec(),
+        IpAddr::V6(addr) => addr.octets().to_vec(),
+    };
+    let addresses =
+        x509::ip_address_sans(&end_entity.0).ok_or(Error::InvalidCertificateEncoding)?;
+    if addresses.contains(&octets.as_slice()) {
+        Ok(ServerCertVerified::assertion())
+    } else {
+        Err(pki_error(webpki::Error::CertNotValidForName))
+    }
+}
+
 pub(crate) fn pki_error(error: webpki::Error) -> Error {
     use webpki::Error::*;
     match error {
@@ -1,5 +1,6 @@
 // Additional x509/asn1 functions to those provided in webpki/ring.
 
+use crate::key::Certificate;
 use ring::io::der;
 
 pub fn wrap_in_asn1_len(bytes: &mut Vec<u8>) {
@@ -25,6 +26,441 @@ pub fn wrap_in_sequence(bytes: &mut Vec<u8>) {
     bytes.insert(0, der::Tag::Sequence as u8);
 }
 
+/// Reads one DER element from the front of `input`.
+///
+/// Returns the element's complete encoding, its contents and what
+/// follows it.
+fn read_element(input: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let first = *input.get(1)?;
+    let (len, header) = match first {
+        0..=0x7f => (first as usize, 2),
+        0x81..=0x84 => {
+            let n = (first & 0x7f) as usize;
+            let bytes = input.get(2..2 + n)?;
+            let len = bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            (len, 2 + n)
+        }
+        _ => return None,
+    };
+
+    let end = header.checked_add(len)?;
+    if end > input.len() {
+        return None;
+    }
+    Some((&input[..end], &input[header..end], &input[end..]))
+}
+
+/// Returns the contents of the DER SEQUENCE at the front of `input`.
+fn read_sequence(input: &[u8]) -> Option<&[u8]> {
+    if *input.first()? != der::Tag::Sequence as u8 {
+        return None;
+    }
+    read_element(input).map(|(_, contents, _)| contents)
+}
+
+/// Returns the TBSCertificate fields of `cert` which follow the
+/// signature algorithm.
+fn tbs_fields(cert: &[u8]) -> Option<&[u8]> {
+    let tbs = read_sequence(read_sequence(cert)?)?;
+
+    // Skip the optional version, the serial number and the signature
+    // algorithm.
+    let mut rest = tbs;
+    if *rest.first()? == 0xa0 {
+        rest = read_element(rest)?.2;
+    }
+    rest = read_element(rest)?.2;
+    read_element(rest).map(|(_, _, rest)| rest)
+}
+
+/// Returns the encoded issuer and subject names of the DER-encoded
+/// X.509 certificate `cert`, or `None` if it cannot be parsed.
+pub fn issuer_and_subject(cert: &[u8]) -> Option<(&[u8], &[u8])> {
+    let rest = tbs_fields(cert)?;
+    let (issuer, _, rest) = read_element(rest)?;
+    let (_validity, _, rest) = read_element(rest)?;
+    let (subject, _, _) = read_element(rest)?;
+    Some((issuer, subject))
+}
+
+/// The encoded OID of the subjectAltName extension, 2.5.29.17.
+const SUBJECT_ALT_NAME: &[u8] = &[0x06, 0x03, 0x55, 0x1d, 0x11];
+
+/// Returns the `iPAddress` entries of the subjectAltName extension of
+/// the DER-encoded X.509 certificate `cert`, as 4 or 16 byte addresses.
+///
+/// Returns `None` if `cert` cannot be parsed.
+pub fn ip_address_sans(cert: &[u8]) -> Option<Vec<&[u8]>> {
+    // Skip the issuer, validity, subject and subjectPublicKeyInfo.
+    let mut rest = tbs_fields(cert)?;
+    for _ in 0..4 {
+        rest = read_element(rest)?.2;
+    }
+
+    // Skip the optional unique identifiers, to reach the extensions.
+    let mut extensions = None;
+    while !rest.is_empty() {
+        let (_, contents, next) = read_element(rest)?;
+        if rest[0] == 0xa3 {
+            extensions = Some(read_sequence(contents)?);
+        }
+        rest = next;
+    }
+
+    let mut addresses = Vec::new();
+    let mut extensions = match extensions {
+        Some(extensions) => extensions,
+        None => return Some(addresses),
+    };
+    while !extensions.is_empty() {
+        let (extension, _, next) = read_element(extensions)?;
+        extensions = next;
+
+        let fields = read_sequence(extension)?;
+        let (oid, _, mut fields) = read_element(fields)?;
+        if oid != SUBJECT_ALT_NAME {
+            continue;
+        }
+        // Skip the critical flag.
+        if *fields.first()? == der::Tag::Boolean as u8 {
+            fields = read_element(fields)?.2;
+        }
+        let (_, value, _) = read_element(fields)?;
+
+        let mut names = read_sequence(value)?;
+        while !names.is_empty() {
+            let (_, contents, next) = read_element(names)?;
+            // iPAddress is [7] IMPLICIT OCTET STRING.
+            if names[0] == 0x87 {
+                addresses.push(contents);
+            }
+            names = next;
+        }
+    }
+    Some(addresses)
+}
+
+/// Returns true if `chain` starts with the end-entity certificate,
+/// followed by each certificate's issuer in turn, as TLS requires of the
+/// chain a server sends.
+///
+/// Certificates after the path from the end-entity certificate are
+/// allowed, as long as none of them continues that path.  Certificates
+/// that cannot be parsed are left for the certificate verifier to reject.
+pub fn is_ordered_chain(chain: &[Certificate]) -> bool {
+    let names = match chain
+        .iter()
+        .map(|cert| issuer_and_subject(&cert.0))
+        .collect::<Option<Vec<_>>>()
+    {
+        Some(names) => names,
+        None => return true,
+    };
+    let (leaf_issuer, leaf_subject) = match names.first() {
+        Some(leaf) => *leaf,
+        None => return true,
+    };
+
+    // Nothing else in the chain may be issued by the first certificate.
+    if leaf_issuer != leaf_subject && names[1..].iter().any(|(issuer, _)| *issuer == leaf_subject) {
+        return false;
+    }
+
+    let mut current = 0;
+    while current + 1 < names.len() && names[current].0 != names[current].1 {
+        if names[current + 1].1 == names[current].0 {
+            current += 1;
+            continue;
+        }
+        // The path ends here, so its next issuer must not come later.
+        return !names[current + 1..]
+            .iter()
+            .any(|(_, subject)| *subject == names[current].0);
+    }
+    true
+}
+
+/// Puts `chain` in the order TLS requires: the end-entity certificate
+/// first, followed by each certificate's issuer in turn.
+///
+/// The end-entity certificate is the one that issued no other
+/// certificate in the chain.  If there is not exactly one such
+/// certificate, or a certificate cannot be parsed, `chain` is returned
+/// unchanged.  Certificates that are not part of the path from the
+/// end-entity certificate are kept, in their original order, at the end.
+pub fn reorder_chain(chain: &[Certificate]) -> Vec<Certificate> {
+    let names = match chain
+        .iter()
+        .map(|cert| issuer_and_subject(&cert.0))
+        .collect::<Option<Vec<_>>>()
+    {
+        Some(names) => names,
+        None => return chain.to_vec(),
+    };
+
+    let issued_another = |i: usize| {
+        names
+            .iter()
+            .enumerate()
+            .any(|(j, (issuer, _))| j != i && *issuer == names[i].1)
+    };
+    let mut leaves = (0..chain.len()).filter(|i| !issued_another(*i));
+    let leaf = match (leaves.next(), leaves.next()) {
+        (Some(leaf), None) => leaf,
+        _ => return chain.to_vec(),
+    };
+
+    let mut order = vec![leaf];
+    let mut current = leaf;
+    while let Some(next) =
+        (0..chain.len()).find(|j| !order.contains(j) && names[*j].1 == names[current].0)
+    {
+        order.push(next);
+        current = next;
+    }
+    let unused: Vec<usize> = (0..chain.len()).filter(|i| !order.contains(i)).collect();
+    order.extend(unused);
+
+    order.into_iter().map(|i| chain[i].clone()).collect()
+}
+
+/// Returns the certificates in `intermediates` that could be on a path
+/// from `end_entity` to a trust anchor, in their original order.
+///
+/// `anchor_subjects` are the encoded subject names of the trust anchors.
+/// A path ends at a certificate issued by one of them, since a verifier
+/// tries the trust anchors before looking at any other issuer.  When
+/// `anchor_subjects` is empty every path is followed as far as the
+/// intermediates allow.  Certificates that cannot be parsed are dropped,
+/// as no verifier could use them either.
+pub fn issuer_path(
+    end_entity: &Certificate,
+    intermediates: &[Certificate],
+    anchor_subjects: &[&[u8]],
+) -> Vec<Certificate> {
+    let names: Vec<_> = intermediates
+        .iter()
+        .map(|cert| issuer_and_subject(&cert.0))
+        .collect();
+    let issued_by_anchor = |issuer: &[u8]| anchor_subjects.contains(&issuer);
+
+    let mut wanted = match issuer_and_subject(&end_entity.0) {
+        Some((issuer, _)) if !issued_by_anchor(issuer) => vec![issuer],
+        _ => Vec::new(),
+    };
+    let mut used = vec![false; intermediates.len()];
+    while let Some(issuer) = wanted.pop() {
+        for (i, name) in names.iter().enumerate() {
+            match name {
+                Some((next, subject)) if !used[i] && *subject == issuer => {
+                    used[i] = true;
+                    if !issued_by_anchor(next) {
+                        wanted.push(next);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    intermediates
+        .iter()
+        .zip(used)
+        .filter(|(_, used)| *used)
+        .map(|(cert, _)| cert.clone())
+        .collect()
+}
+
+/// The longest path [`candidate_paths`] follows, in intermediates.
+const MAX_PATH_LEN: usize = 6;
+
+/// The most paths [`candidate_paths`] returns.
+const MAX_CANDIDATE_PATHS: usize = 16;
+
+/// Returns the paths from `end_entity` to a trust anchor through
+/// `intermediates`, in the order a verifier tries them.
+///
+/// Each path lists the intermediates used, starting with the issuer of
+/// `end_entity`, and ends with a certificate issued by one of the trust
+/// anchors named by `anchor_subjects`.  An empty path means
+/// `end_entity` was issued by a trust anchor.  Paths are matched by name
+/// only; no signatures are checked.
+pub fn candidate_paths(
+    end_entity: &Certificate,
+    intermediates: &[Certificate],
+    anchor_subjects: &[&[u8]],
+) -> Vec<Vec<Certificate>> {
+    let names: Vec<_> = intermediates
+        .iter()
+        .map(|cert| issuer_and_subject(&cert.0))
+        .collect();
+
+    let mut paths = Vec::new();
+    if let Some((issuer, _)) = issuer_and_subject(&end_entity.0) {
+        extend_paths(issuer, &names, anchor_subjects, &mut Vec::new(), &mut paths);
+    }
+
+    paths
+        .into_iter()
+        .map(|path| path.into_iter().map(|i| intermediates[i].clone()).collect())
+        .collect()
+}
+
+/// Adds to `paths` each path that continues `path` from a certificate
+/// issued by `issuer`.
+fn extend_paths(
+    issuer: &[u8],
+    names: &[Option<(&[u8], &[u8])>],
+    anchor_subjects: &[&[u8]],
+    path: &mut Vec<usize>,
+    paths: &mut Vec<Vec<usize>>,
+) {
+    if paths.len() >= MAX_CANDIDATE_PATHS {
+        return;
+    }
+
+    // Trust anchors are tried before any intermediate.
+    if anchor_subjects.contains(&issuer) {
+        paths.push(path.clone());
+    }
+
+    if path.len() >= MAX_PATH_LEN {
+        return;
+    }
+
+    for (i, name) in names.iter().enumerate() {
+        match name {
+            Some((next, subject)) if *subject == issuer && !path.contains(&i) => {
+                path.push(i);
+                extend_paths(next, names, anchor_subjects, path, paths);
+                path.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_issuer_and_subject() {
+    let leaf = include_bytes!("../testdata/cert-rustlang.0.der");
+    let inter = include_bytes!("../testdata/cert-rustlang.1.der");
+    let (leaf_issuer, leaf_subject) = issuer_and_subject(leaf).unwrap();
+    let (_, inter_subject) = issuer_and_subject(inter).unwrap();
+    assert_eq!(leaf_issuer, inter_subject);
+    assert_ne!(leaf_subject, inter_subject);
+    assert_eq!(issuer_and_subject(&leaf[..leaf.len() / 2]), None);
+}
+
+#[test]
+fn test_ip_address_sans() {
+    let cert = include_bytes!("../testdata/cert-ipaddress.der");
+    let addresses = ip_address_sans(cert).unwrap();
+    assert_eq!(
+        addresses,
+        vec![
+            &[192, 0, 2, 1][..],
+            &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1][..],
+        ]
+    );
+
+    let leaf = include_bytes!("../testdata/cert-rustlang.0.der");
+    assert_eq!(ip_address_sans(leaf), Some(vec![]));
+    assert_eq!(ip_address_sans(&cert[..cert.len() / 2]), None);
+}
+
+#[test]
+fn test_chain_order() {
+    let chain = [
+        &include_bytes!("../testdata/cert-rustlang.0.der")[..],
+        &include_bytes!("../testdata/cert-rustlang.1.der")[..],
+        &include_bytes!("../testdata/cert-rustlang.2.der")[..],
+        &include_bytes!("../testdata/cert-rustlang.3.der")[..],
+    ]
+    .iter()
+    .map(|der| Certificate(der.to_vec()))
+    .collect::<Vec<_>>();
+    assert!(is_ordered_chain(&chain));
+    assert_eq!(reorder_chain(&chain), chain);
+
+    let shuffled = vec![
+        chain[2].clone(),
+        chain[0].clone(),
+        chain[3].clone(),
+        chain[1].clone(),
+    ];
+    assert!(!is_ordered_chain(&shuffled));
+    assert_eq!(reorder_chain(&shuffled), chain);
+
+    let unrelated = Certificate(include_bytes!("../testdata/cert-arstechnica.0.der").to_vec());
+    let mut extra = chain.clone();
+    extra.push(unrelated.clone());
+    assert!(is_ordered_chain(&extra));
+
+    let interrupted = vec![chain[0].clone(), unrelated, chain[1].clone()];
+    assert!(!is_ordered_chain(&interrupted));
+
+    let leaf_last = vec![chain[1].clone(), chain[0].clone()];
+    assert!(!is_ordered_chain(&leaf_last));
+}
+
+#[test]
+fn test_issuer_path() {
+    let chain = [
+        &include_bytes!("../testdata/cert-rustlang.0.der")[..],
+        &include_bytes!("../testdata/cert-rustlang.1.der")[..],
+        &include_bytes!("../testdata/cert-rustlang.2.der")[..],
+        &include_bytes!("../testdata/cert-rustlang.3.der")[..],
+    ]
+    .iter()
+    .map(|der| Certificate(der.to_vec()))
+    .collect::<Vec<_>>();
+    let unrelated = Certificate(include_bytes!("../testdata/cert-arstechnica.0.der").to_vec());
+
+    let intermediates = vec![unrelated.clone(), chain[1].clone(), chain[2].clone()];
+    assert_eq!(
+        issuer_path(&chain[0], &intermediates, &[]),
+        vec![chain[1].clone(), chain[2].clone()]
+    );
+
+    let (_, anchor) = issuer_and_subject(&chain[2].0).unwrap();
+    assert_eq!(
+        issuer_path(&chain[0], &intermediates, &[anchor]),
+        vec![chain[1].clone()]
+    );
+
+    assert_eq!(issuer_path(&chain[0], &[unrelated], &[]), vec![]);
+}
+
+#[test]
+fn test_candidate_paths() {
+    let chain = [
+        &include_bytes!("../testdata/cert-rustlang.0.der")[..],
+        &include_bytes!("../testdata/cert-rustlang.1.der")[..],
+        &include_bytes!("../testdata/cert-rustlang.2.der")[..],
+        &include_bytes!("../testdata/cert-rustlang.3.der")[..],
+    ]
+    .iter()
+    .map(|der| Certificate(der.to_vec()))
+    .collect::<Vec<_>>();
+    let (inter_issuer, _) = issuer_and_subject(&chain[1].0).unwrap();
+    let (top_issuer, _) = issuer_and_subject(&chain[3].0).unwrap();
+
+    // Either the intermediate's issuer or the top certificate's issuer
+    // may be trusted; the shorter path comes first.
+    let intermediates = &chain[1..];
+    assert_eq!(
+        candidate_paths(&chain[0], intermediates, &[top_issuer, inter_issuer]),
+        vec![
+            vec![chain[1].clone()],
+            vec![chain[1].clone(), chain[2].clone(), chain[3].clone()],
+        ]
+    );
+    assert_eq!(
+        candidate_paths(&chain[0], intermediates, &[]),
+        Vec::<Vec<Certificate>>::new()
+    );
+}
+
 #[test]
 fn test_empty() {
     let mut val = Vec::new();